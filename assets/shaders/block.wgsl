@@ -0,0 +1,51 @@
+// Block textures repeated over the quads of the chunk meshes
+//
+// Greedy meshing merges faces into quads several blocks wide, so the meshes count blocks across
+// each quad in `uv` and give the top left corner of its tile in the atlas in `uv_b`. Sampling
+// `uv_b + fract(uv) * tile_size` repeats the tile once per block instead of stretching it.
+
+#import bevy_pbr::{
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::alpha_discard,
+    pbr_types::STANDARD_MATERIAL_FLAGS_UNLIT_BIT,
+}
+
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::{
+    prepass_io::{VertexOutput, FragmentOutput},
+    pbr_deferred_functions::deferred_output,
+}
+#else
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+#endif
+
+// Size of a tile of the atlas, in UV units
+@group(2) @binding(100) var<uniform> tile_size: vec2<f32>;
+
+@fragment
+fn fragment(in: VertexOutput, @builtin(front_facing) is_front: bool) -> FragmentOutput {
+    var tiled = in;
+#ifdef VERTEX_UVS_B
+    tiled.uv = in.uv_b + fract(in.uv) * tile_size;
+#endif
+
+    var pbr_input = pbr_input_from_standard_material(tiled, is_front);
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+#ifdef PREPASS_PIPELINE
+    let out = deferred_output(in, pbr_input);
+#else
+    var out: FragmentOutput;
+    if (pbr_input.material.flags & STANDARD_MATERIAL_FLAGS_UNLIT_BIT) == 0u {
+        out.color = apply_pbr_lighting(pbr_input);
+    } else {
+        out.color = pbr_input.material.base_color;
+    }
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+#endif
+
+    return out;
+}
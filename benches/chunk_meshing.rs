@@ -0,0 +1,85 @@
+//! Compares greedy meshing with the mesher building one quad per visible face
+//!
+//! Run with `cargo bench --bench chunk_meshing`. For a few kinds of chunks it prints the vertices
+//! of the opaque meshes of all their sections, and how long meshing them takes.
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use voxel_game::block_registry::BlockRegistry;
+use voxel_game::chunk::{Chunk, SECTION_COUNT};
+use voxel_game::mesh_builder::{create_section_mesh_with, MeshingStrategy};
+use voxel_game::pipeline::generate_chunk;
+use voxel_game::world_generator::GeneratorSettings;
+use voxel_game::worldgen_data::WorldgenData;
+use voxel_game::IVec3;
+
+const ITERATIONS: u32 = 20;
+
+fn generated_chunk(settings: GeneratorSettings, registry: &Arc<BlockRegistry>) -> Chunk {
+    let generator = settings
+        .build(0, &WorldgenData::default(), registry)
+        .unwrap();
+
+    generate_chunk(generator.generator.as_ref(), IVec3::ZERO)
+}
+
+/// Vertices of the opaque meshes of the chunk, and the time to build all of its meshes
+fn measure(
+    chunk: &Chunk,
+    registry: &BlockRegistry,
+    strategy: MeshingStrategy,
+) -> (usize, Duration) {
+    let vertices = (0..SECTION_COUNT)
+        .map(|section| {
+            create_section_mesh_with(chunk, section, registry, strategy)
+                .opaque
+                .count_vertices()
+        })
+        .sum();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        for section in 0..SECTION_COUNT {
+            black_box(create_section_mesh_with(
+                black_box(chunk),
+                section,
+                registry,
+                strategy,
+            ));
+        }
+    }
+
+    (vertices, start.elapsed() / ITERATIONS)
+}
+
+fn main() {
+    let registry = Arc::new(BlockRegistry::default());
+
+    println!(
+        "{:<10} {:<9} {:>10} {:>12}",
+        "chunk", "mesher", "vertices", "build time"
+    );
+
+    for (name, chunk) in [
+        (
+            "superflat",
+            generated_chunk(GeneratorSettings::superflat(), &registry),
+        ),
+        (
+            "terrain",
+            generated_chunk(GeneratorSettings::Default, &registry),
+        ),
+    ] {
+        for (mesher, strategy) in [
+            ("per face", MeshingStrategy::PerFace),
+            ("greedy", MeshingStrategy::Greedy),
+        ] {
+            let (vertices, build_time) = measure(&chunk, &registry, strategy);
+            println!(
+                "{:<10} {:<9} {:>10} {:>12.2?}",
+                name, mesher, vertices, build_time
+            );
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Compares the sectioned palette chunk storage with the flat block array it replaced
+//!
+//! Run with `cargo bench --bench chunk_storage`. For a few kinds of chunks it prints the memory
+//! used by the blocks, the size of the encoded chunk before and after lz4 compression (what is
+//! written to region files and sent in `ServerMessage::Chunk`), and how long encoding takes.
+
+use bincode::config;
+use lz4::block::{compress, CompressionMode};
+use serde::Serialize;
+use serde_big_array::BigArray;
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use voxel_game::block::{Block, BlockType};
+use voxel_game::block_registry::BlockRegistry;
+use voxel_game::chunk::{Chunk, CHUNK_HEIGHT, CHUNK_SIZE};
+use voxel_game::pipeline::generate_chunk;
+use voxel_game::world_generator::GeneratorSettings;
+use voxel_game::worldgen_data::WorldgenData;
+use voxel_game::IVec3;
+
+const ITERATIONS: u32 = 50;
+const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_HEIGHT) as usize;
+
+/// The chunk layout before palettes, one full block per position
+#[derive(Serialize)]
+struct ArrayChunk {
+    #[serde(with = "BigArray")]
+    voxels: [Block; CHUNK_VOLUME],
+    pos: IVec3,
+}
+
+impl ArrayChunk {
+    fn from_chunk(chunk: &Chunk) -> Box<Self> {
+        let mut array_chunk = Box::new(ArrayChunk {
+            voxels: [Block::new_empty(); CHUNK_VOLUME],
+            pos: chunk.pos,
+        });
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_HEIGHT {
+                for z in 0..CHUNK_SIZE {
+                    let coordinate = IVec3::new(x, y, z);
+                    array_chunk.voxels
+                        [(z * CHUNK_SIZE * CHUNK_HEIGHT + y * CHUNK_SIZE + x) as usize] =
+                        chunk.voxel_at(&coordinate);
+                }
+            }
+        }
+
+        array_chunk
+    }
+}
+
+struct Measurement {
+    memory: usize,
+    encoded: usize,
+    compressed: usize,
+    encode_time: Duration,
+}
+
+fn encode(value: &impl Serialize) -> (usize, usize) {
+    let encoded = bincode::serde::encode_to_vec(value, config::standard()).unwrap();
+    let compressed = compress(&encoded, Some(CompressionMode::HIGHCOMPRESSION(12)), true).unwrap();
+
+    (encoded.len(), compressed.len())
+}
+
+fn measure(value: &impl Serialize, memory: usize) -> Measurement {
+    let (encoded, compressed) = encode(value);
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(encode(black_box(value)));
+    }
+
+    Measurement {
+        memory,
+        encoded,
+        compressed,
+        encode_time: start.elapsed() / ITERATIONS,
+    }
+}
+
+fn empty_chunk() -> Chunk {
+    Chunk::default()
+}
+
+fn terrain_chunk() -> Chunk {
+    let generator = GeneratorSettings::Default
+        .build(0, &WorldgenData::default(), &Arc::default())
+        .unwrap();
+
+    generate_chunk(generator.generator.as_ref(), IVec3::ZERO)
+}
+
+/// Every position set to one of the blocks at random, the worst case for the palette
+fn noise_chunk() -> Chunk {
+    let blocks: Vec<BlockType> = (0..BlockRegistry::default().len() as u16)
+        .map(BlockType::from_id)
+        .collect();
+
+    let mut chunk = Chunk::default();
+    let mut state: u32 = 0x9E37_79B9;
+
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_HEIGHT {
+            for z in 0..CHUNK_SIZE {
+                // xorshift32
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                chunk.set_voxel(
+                    &IVec3::new(x, y, z),
+                    Block::new(blocks[state as usize % blocks.len()]),
+                );
+            }
+        }
+    }
+
+    chunk
+}
+
+fn main() {
+    println!(
+        "{:<8} {:<8} {:>10} {:>10} {:>12} {:>12}",
+        "chunk", "storage", "memory", "encoded", "compressed", "encode time"
+    );
+
+    for (name, chunk) in [
+        ("empty", empty_chunk()),
+        ("terrain", terrain_chunk()),
+        ("noise", noise_chunk()),
+    ] {
+        let array_chunk = ArrayChunk::from_chunk(&chunk);
+
+        for (storage, measurement) in [
+            (
+                "array",
+                measure(&array_chunk, size_of_val(&array_chunk.voxels)),
+            ),
+            ("palette", measure(&chunk, chunk.memory_usage())),
+        ] {
+            println!(
+                "{:<8} {:<8} {:>10} {:>10} {:>12} {:>12.2?}",
+                name,
+                storage,
+                measurement.memory,
+                measurement.encoded,
+                measurement.compressed,
+                measurement.encode_time
+            );
+        }
+    }
+}
@@ -4,7 +4,9 @@ use bevy_egui::EguiPlugin;
 use bevy_renet::netcode::NetcodeServerPlugin;
 use bevy_renet::RenetServerPlugin;
 use renet_visualizer::RenetServerVisualizer;
+use std::path::Path;
 use voxel_game::chunk::ServerChunkPlugin;
+use voxel_game::server_settings::{ServerSettings, SERVER_SETTINGS_FILE};
 use voxel_game::world::{GameWorld, ServerWorldPlugin};
 use voxel_game::{
     new_renet_server, server_handle_messages_system, server_receive_system, server_update_system,
@@ -12,6 +14,15 @@ use voxel_game::{
 };
 
 fn main() {
+    let settings = ServerSettings::load(Path::new(SERVER_SETTINGS_FILE))
+        .unwrap_or_else(|err| panic!("Failed to read {}: {}", SERVER_SETTINGS_FILE, err));
+    let storage = settings.open_world().unwrap_or_else(|err| {
+        panic!(
+            "Failed to open world {:?}: {}",
+            settings.world_directory, err
+        )
+    });
+
     let (server, transport, _) = new_renet_server(false);
 
     App::new()
@@ -35,6 +46,7 @@ fn main() {
         ))
         .init_resource::<Lobby>()
         .init_resource::<GameWorld>()
+        .insert_resource(storage)
         .init_resource::<PendingClientMessage>()
         .insert_resource(RenetServerVisualizer::<200>::default())
         .add_systems(Startup, force_server_state_to_running_system)
@@ -1,6 +1,7 @@
-use crate::voxel::block::BlockType;
+use crate::voxel::block::Block;
+use crate::voxel::block_registry::GameBlockRegistry;
 use crate::voxel::quad::HALF_SIZE;
-use crate::voxel::world::{GameWorld, World};
+use crate::voxel::world::GameWorld;
 use crate::{Channel, ClientMessage, ClientState};
 use bevy::ecs::event::EventCursor;
 use bevy::input::mouse::MouseMotion;
@@ -25,6 +26,10 @@ pub struct Player {
     pub placing_at_pos: Option<IVec3>,
 }
 
+/// Position the server wants the local player to spawn at
+#[derive(Resource)]
+pub struct PlayerSpawn(pub Vec3);
+
 #[derive(Component, Default)]
 pub struct OtherPlayer {
     pub id: u64,
@@ -98,23 +103,32 @@ fn toggle_grab_cursor(window: &mut Window) {
     }
 }
 
-fn setup_player(mut commands: Commands, game_world: Res<GameWorld>) {
-    let highest_block = game_world
-        .world
-        .read()
-        .unwrap()
-        .get_highest_block_at_coord(&IVec2::new(0, 0))
-        .as_vec3();
+fn setup_player(
+    mut commands: Commands,
+    game_world: Res<GameWorld>,
+    spawn: Option<Res<PlayerSpawn>>,
+) {
+    let position = if let Some(spawn) = spawn {
+        spawn.0
+    } else {
+        let highest_block = game_world
+            .world
+            .read()
+            .unwrap()
+            .get_highest_block_at_coord(&IVec2::new(0, 0))
+            .as_vec3();
+
+        Vec3::new(
+            highest_block.x,
+            highest_block.y + HALF_SIZE + 2.,
+            highest_block.z,
+        )
+    };
 
     commands
         .spawn((
             Player::default(),
-            Transform::from_xyz(
-                highest_block.x,
-                highest_block.y + HALF_SIZE + 2.,
-                highest_block.z,
-            )
-            .looking_to(Vec3::Z, Vec3::Y),
+            Transform::from_translation(position).looking_to(Vec3::Z, Vec3::Y),
             VerticalMomentum(0.),
         ))
         .with_children(|parent| {
@@ -139,56 +153,49 @@ fn player_move(
     key_bindings: Res<KeyBindings>,
     mut query: Query<(&mut Transform, &mut VerticalMomentum), With<Player>>,
     game_world: Res<GameWorld>,
+    block_registry: Res<GameBlockRegistry>,
     mut client: ResMut<RenetClient>,
 ) {
+    let registry = &block_registry.registry;
+
     if let Ok(window) = primary_window.get_single() {
         for (mut transform, mut vertical_momentum) in query.iter_mut() {
             let mut desired_velocity = Vec3::ZERO;
 
             // Check if the player is on the ground
-            let is_grounded =
-                game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .check_block_at_coord(&World::coord_to_world(
-                        transform.translation
-                            - Vec3::new(
-                                -PLAYER_WIDTH,
-                                settings.gravity * time.delta_secs(),
-                                -PLAYER_WIDTH,
-                            ),
-                    ))
-                    || game_world.world.read().unwrap().check_block_at_coord(
-                        &World::coord_to_world(
-                            transform.translation
-                                - Vec3::new(
-                                    PLAYER_WIDTH,
-                                    settings.gravity * time.delta_secs(),
-                                    -PLAYER_WIDTH,
-                                ),
-                        ),
-                    )
-                    || game_world.world.read().unwrap().check_block_at_coord(
-                        &World::coord_to_world(
-                            transform.translation
-                                - Vec3::new(
-                                    PLAYER_WIDTH,
-                                    settings.gravity * time.delta_secs(),
-                                    PLAYER_WIDTH,
-                                ),
-                        ),
-                    )
-                    || game_world.world.read().unwrap().check_block_at_coord(
-                        &World::coord_to_world(
-                            transform.translation
-                                - Vec3::new(
-                                    PLAYER_WIDTH,
-                                    settings.gravity * time.delta_secs(),
-                                    -PLAYER_WIDTH,
-                                ),
-                        ),
-                    );
+            let is_grounded = game_world.world.read().unwrap().is_solid_at(
+                transform.translation
+                    - Vec3::new(
+                        -PLAYER_WIDTH,
+                        settings.gravity * time.delta_secs(),
+                        -PLAYER_WIDTH,
+                    ),
+                registry,
+            ) || game_world.world.read().unwrap().is_solid_at(
+                transform.translation
+                    - Vec3::new(
+                        PLAYER_WIDTH,
+                        settings.gravity * time.delta_secs(),
+                        -PLAYER_WIDTH,
+                    ),
+                registry,
+            ) || game_world.world.read().unwrap().is_solid_at(
+                transform.translation
+                    - Vec3::new(
+                        PLAYER_WIDTH,
+                        settings.gravity * time.delta_secs(),
+                        PLAYER_WIDTH,
+                    ),
+                registry,
+            ) || game_world.world.read().unwrap().is_solid_at(
+                transform.translation
+                    - Vec3::new(
+                        PLAYER_WIDTH,
+                        settings.gravity * time.delta_secs(),
+                        -PLAYER_WIDTH,
+                    ),
+                registry,
+            );
 
             for key in keys.get_pressed() {
                 match window.cursor_options.grab_mode {
@@ -233,126 +240,94 @@ fn player_move(
 
             // Check front
             if desired_velocity.z > 0.
-                && (game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .check_block_at_coord(&World::coord_to_world(
-                        transform.translation + Vec3::new(0., 0., PLAYER_WIDTH),
-                    ))
-                    || game_world.world.read().unwrap().check_block_at_coord(
-                        &World::coord_to_world(
-                            transform.translation + Vec3::new(0., 1., PLAYER_WIDTH),
-                        ),
-                    ))
+                && (game_world.world.read().unwrap().is_solid_at(
+                    transform.translation + Vec3::new(0., 0., PLAYER_WIDTH),
+                    registry,
+                ) || game_world.world.read().unwrap().is_solid_at(
+                    transform.translation + Vec3::new(0., 1., PLAYER_WIDTH),
+                    registry,
+                ))
             {
                 desired_velocity.z = 0.;
             }
 
             // Check back
             if desired_velocity.z < 0.
-                && (game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .check_block_at_coord(&World::coord_to_world(
-                        transform.translation + Vec3::new(0., 0., -PLAYER_WIDTH),
-                    ))
-                    || game_world.world.read().unwrap().check_block_at_coord(
-                        &World::coord_to_world(
-                            transform.translation + Vec3::new(0., 1., -PLAYER_WIDTH),
-                        ),
-                    ))
+                && (game_world.world.read().unwrap().is_solid_at(
+                    transform.translation + Vec3::new(0., 0., -PLAYER_WIDTH),
+                    registry,
+                ) || game_world.world.read().unwrap().is_solid_at(
+                    transform.translation + Vec3::new(0., 1., -PLAYER_WIDTH),
+                    registry,
+                ))
             {
                 desired_velocity.z = 0.;
             }
 
             // Check right
             if desired_velocity.x > 0.
-                && (game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .check_block_at_coord(&World::coord_to_world(
-                        transform.translation + Vec3::new(PLAYER_WIDTH, 0., 0.),
-                    ))
-                    || game_world.world.read().unwrap().check_block_at_coord(
-                        &World::coord_to_world(
-                            transform.translation + Vec3::new(PLAYER_WIDTH, 1., 0.),
-                        ),
-                    ))
+                && (game_world.world.read().unwrap().is_solid_at(
+                    transform.translation + Vec3::new(PLAYER_WIDTH, 0., 0.),
+                    registry,
+                ) || game_world.world.read().unwrap().is_solid_at(
+                    transform.translation + Vec3::new(PLAYER_WIDTH, 1., 0.),
+                    registry,
+                ))
             {
                 desired_velocity.x = 0.;
             }
 
             // Check left
             if desired_velocity.x < 0.
-                && (game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .check_block_at_coord(&World::coord_to_world(
-                        transform.translation + Vec3::new(-PLAYER_WIDTH, 0., 0.),
-                    ))
-                    || game_world.world.read().unwrap().check_block_at_coord(
-                        &World::coord_to_world(
-                            transform.translation + Vec3::new(-PLAYER_WIDTH, 1., 0.),
-                        ),
-                    ))
+                && (game_world.world.read().unwrap().is_solid_at(
+                    transform.translation + Vec3::new(-PLAYER_WIDTH, 0., 0.),
+                    registry,
+                ) || game_world.world.read().unwrap().is_solid_at(
+                    transform.translation + Vec3::new(-PLAYER_WIDTH, 1., 0.),
+                    registry,
+                ))
             {
                 desired_velocity.x = 0.;
             }
 
             // Check top
             if desired_velocity.y > 0.
-                && game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .check_block_at_coord(&World::coord_to_world(
-                        transform.translation
-                            + Vec3::new(
-                                -PLAYER_WIDTH,
-                                PLAYER_HEIGHT + settings.jump_height * time.delta_secs(),
-                                -PLAYER_WIDTH,
-                            ),
-                    ))
-                || game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .check_block_at_coord(&World::coord_to_world(
-                        transform.translation
-                            + Vec3::new(
-                                PLAYER_WIDTH,
-                                PLAYER_HEIGHT + settings.jump_height * time.delta_secs(),
-                                -PLAYER_WIDTH,
-                            ),
-                    ))
-                || game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .check_block_at_coord(&World::coord_to_world(
-                        transform.translation
-                            + Vec3::new(
-                                PLAYER_WIDTH,
-                                PLAYER_HEIGHT + settings.jump_height * time.delta_secs(),
-                                PLAYER_WIDTH,
-                            ),
-                    ))
-                || game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .check_block_at_coord(&World::coord_to_world(
-                        transform.translation
-                            + Vec3::new(
-                                PLAYER_WIDTH,
-                                PLAYER_HEIGHT + settings.jump_height * time.delta_secs(),
-                                -PLAYER_WIDTH,
-                            ),
-                    ))
+                && game_world.world.read().unwrap().is_solid_at(
+                    transform.translation
+                        + Vec3::new(
+                            -PLAYER_WIDTH,
+                            PLAYER_HEIGHT + settings.jump_height * time.delta_secs(),
+                            -PLAYER_WIDTH,
+                        ),
+                    registry,
+                )
+                || game_world.world.read().unwrap().is_solid_at(
+                    transform.translation
+                        + Vec3::new(
+                            PLAYER_WIDTH,
+                            PLAYER_HEIGHT + settings.jump_height * time.delta_secs(),
+                            -PLAYER_WIDTH,
+                        ),
+                    registry,
+                )
+                || game_world.world.read().unwrap().is_solid_at(
+                    transform.translation
+                        + Vec3::new(
+                            PLAYER_WIDTH,
+                            PLAYER_HEIGHT + settings.jump_height * time.delta_secs(),
+                            PLAYER_WIDTH,
+                        ),
+                    registry,
+                )
+                || game_world.world.read().unwrap().is_solid_at(
+                    transform.translation
+                        + Vec3::new(
+                            PLAYER_WIDTH,
+                            PLAYER_HEIGHT + settings.jump_height * time.delta_secs(),
+                            -PLAYER_WIDTH,
+                        ),
+                    registry,
+                )
             {
                 desired_velocity.y = 0.;
             }
@@ -445,9 +420,12 @@ fn player_handle_voxel_raycast(
     primary_window: Query<&Window, With<PrimaryWindow>>,
     player_camera_query: Query<&GlobalTransform, (Without<Player>, With<PlayerCamera>)>,
     game_world: Res<GameWorld>,
+    block_registry: Res<GameBlockRegistry>,
     buttons: Res<ButtonInput<MouseButton>>,
     mut client: ResMut<RenetClient>,
 ) {
+    let registry = &block_registry.registry;
+
     if let Ok(window) = primary_window.get_single() {
         if let Ok(mut player) = player_query.get_single_mut() {
             let player_camera = if let Ok(player_camera) = player_camera_query.get_single() {
@@ -461,6 +439,7 @@ fn player_handle_voxel_raycast(
                 player_camera.forward().as_vec3(),
                 RAY_CASTING_DISTANCE,
                 RAY_CASTING_STEP,
+                registry,
             );
 
             let (looking_at_pos, placing_at_pos, _) =
@@ -479,11 +458,11 @@ fn player_handle_voxel_raycast(
                         CursorGrabMode::None => (),
                         _ => {
                             if buttons.just_pressed(MouseButton::Left) {
-                                game_world
-                                    .world
-                                    .write()
-                                    .unwrap()
-                                    .edit_voxel(&looking_at_pos, BlockType::Void);
+                                game_world.world.write().unwrap().edit_voxel(
+                                    &looking_at_pos,
+                                    Block::new_empty(),
+                                    registry,
+                                );
 
                                 let message = bincode::serde::encode_to_vec(
                                     ClientMessage::BreakBlock(looking_at_pos),
@@ -492,14 +471,25 @@ fn player_handle_voxel_raycast(
                                 .unwrap();
                                 client.send_message(Channel::Reliable, message);
                             } else if buttons.just_pressed(MouseButton::Right) {
-                                game_world
-                                    .world
-                                    .write()
-                                    .unwrap()
-                                    .edit_voxel(&placing_at_pos, BlockType::Stone);
+                                let Some(stone) = registry.block_type("stone") else {
+                                    return;
+                                };
+                                let block = Block::with_state(
+                                    stone,
+                                    registry.get(stone).placement_state(
+                                        placing_at_pos - looking_at_pos,
+                                        player_camera.forward().as_vec3(),
+                                    ),
+                                );
+
+                                game_world.world.write().unwrap().edit_voxel(
+                                    &placing_at_pos,
+                                    block,
+                                    registry,
+                                );
 
                                 let message = bincode::serde::encode_to_vec(
-                                    ClientMessage::PlaceBlock(placing_at_pos, BlockType::Stone),
+                                    ClientMessage::PlaceBlock(placing_at_pos, block),
                                     config::standard(),
                                 )
                                 .unwrap();
@@ -541,7 +531,10 @@ impl Plugin for PlayerPlugin {
                     wireframe_toggle,
                 )
                     .in_set(PlayerSet)
-                    .run_if(in_state(ClientState::Playing)),
+                    // The client may be playing before a remote server sent its blocks
+                    .run_if(
+                        in_state(ClientState::Playing).and(resource_exists::<GameBlockRegistry>),
+                    ),
             );
     }
 }
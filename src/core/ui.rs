@@ -1,6 +1,16 @@
 use crate::core::player::{Player, PlayerCamera};
-use crate::voxel::world::World;
-use crate::{new_renet_client, new_renet_server, ClientMode, ClientState, ServerState};
+use crate::storage::saves::{parse_seed, Saves, WorldSummary};
+use crate::storage::{load_world_generator, WorldLoadError};
+use crate::terrain::image_terrain::ImageTerrainSettings;
+use crate::terrain::world_generator::{
+    format_flat_layers, parse_flat_layers, GameWorldGenerator, GeneratorSettings,
+};
+use crate::voxel::block_registry::GameBlockRegistry;
+use crate::voxel::world::{ChunkLoadingSettings, World, MAX_VIEW_DISTANCE, MIN_VIEW_DISTANCE};
+use crate::{
+    new_renet_client, new_renet_server, ClientMode, ClientState, PendingServerMessage, ServerState,
+    WorldStorage,
+};
 use bevy::app::{App, AppExit};
 use bevy::diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin};
 use bevy::math::IVec3;
@@ -10,7 +20,8 @@ use bevy_egui::{egui, EguiContexts, EguiPlugin};
 use bevy_renet::renet::RenetClient;
 use renet_visualizer::RenetServerVisualizer;
 use std::net::SocketAddr;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
 // Store main menu current menu state
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, States)]
@@ -33,6 +44,81 @@ struct MultiplayerMenuState {
     server_ip: String,
 }
 
+#[derive(Default)]
+struct SingleplayerMenuState {
+    saves: Saves,
+    /// `None` when the list has to be read again from disk
+    worlds: Option<Vec<WorldSummary>>,
+    selected_world: Option<String>,
+    rename_to: String,
+    confirm_delete: bool,
+    new_world_name: String,
+    new_world_seed: String,
+    new_world_generator: GeneratorSettings,
+    /// Layers of a superflat world, as read by [`parse_flat_layers`]
+    new_world_layers: String,
+    /// Images of a heightmap world, no color map when empty
+    new_world_heightmap: String,
+    new_world_color_map: String,
+    error: Option<String>,
+}
+
+impl SingleplayerMenuState {
+    fn refresh(&mut self) {
+        self.worlds = None;
+        self.selected_world = None;
+        self.confirm_delete = false;
+    }
+}
+
+fn format_last_played(last_played: Option<SystemTime>) -> String {
+    let Some(elapsed) = last_played.and_then(|time| time.elapsed().ok()) else {
+        return "Never played".to_string();
+    };
+
+    let minutes = elapsed.as_secs() / 60;
+    if minutes < 1 {
+        "Played just now".to_string()
+    } else if minutes < 60 {
+        format!("Played {} minutes ago", minutes)
+    } else if minutes < 60 * 24 {
+        format!("Played {} hours ago", minutes / 60)
+    } else {
+        format!("Played {} days ago", minutes / (60 * 24))
+    }
+}
+
+fn format_size(size: u64) -> String {
+    if size < 1024 * 1024 {
+        format!("{:.1} KB", size as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", size as f64 / (1024.0 * 1024.0))
+    }
+}
+
+fn start_singleplayer_world(commands: &mut Commands, world_directory: PathBuf) {
+    println!("Opening world {:?}", world_directory);
+
+    // Create a server
+    let (server, server_transport, addr) = new_renet_server(true);
+
+    commands.insert_resource(server);
+    commands.insert_resource(server_transport);
+    commands.insert_resource(RenetServerVisualizer::<200>::default());
+    commands.insert_resource(WorldStorage::new(world_directory));
+
+    connect_client(commands, addr);
+}
+
+/// Create the client, dropping what the previous server sent
+fn connect_client(commands: &mut Commands, server_addr: SocketAddr) {
+    let (client, transport) = new_renet_client(server_addr);
+    commands.insert_resource(client);
+    commands.insert_resource(transport);
+    commands.insert_resource(PendingServerMessage::default());
+    commands.remove_resource::<GameBlockRegistry>();
+}
+
 fn main_menu_system(
     mut commands: Commands,
     mut contexts: EguiContexts,
@@ -43,7 +129,17 @@ fn main_menu_system(
     mut next_client_state: ResMut<NextState<ClientState>>,
     mut exit: EventWriter<AppExit>,
     mut multiplayer_menu_state: Local<MultiplayerMenuState>,
+    mut singleplayer_menu_state: Local<SingleplayerMenuState>,
+    mut chunk_loading_settings: ResMut<ChunkLoadingSettings>,
+    load_error: Option<Res<WorldLoadError>>,
 ) {
+    // The world picked in the singleplayer menu couldn't be loaded
+    if let Some(load_error) = load_error {
+        singleplayer_menu_state.error = Some(load_error.0.clone());
+        commands.remove_resource::<WorldLoadError>();
+        next_main_menu_state.set(MainMenuState::Singleplayer);
+    }
+
     egui::CentralPanel::default().show(contexts.ctx_mut(), |ui| match state.get() {
         MainMenuState::MainMenu => {
             ui.heading("Main Menu");
@@ -67,26 +163,206 @@ fn main_menu_system(
         MainMenuState::Singleplayer => {
             ui.heading("Singleplayer");
 
+            let menu = &mut *singleplayer_menu_state;
+            if menu.worlds.is_none() {
+                menu.worlds = Some(menu.saves.list_worlds().unwrap_or_else(|err| {
+                    menu.error = Some(format!("Failed to list worlds: {}", err));
+                    Vec::new()
+                }));
+            }
+
+            let mut world_to_play = None;
+
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    let worlds = menu.worlds.as_ref().unwrap();
+
+                    if worlds.is_empty() {
+                        ui.label("No worlds yet");
+                    }
+
+                    for world in worlds {
+                        let selected = menu.selected_world.as_ref() == Some(&world.name);
+                        let response = ui.selectable_label(
+                            selected,
+                            format!(
+                                "{}\n{} - {}",
+                                world.name,
+                                format_last_played(world.last_played),
+                                format_size(world.size)
+                            ),
+                        );
+
+                        if response.double_clicked() {
+                            world_to_play = Some(world.directory.clone());
+                        } else if response.clicked() && !selected {
+                            menu.selected_world = Some(world.name.clone());
+                            menu.rename_to = world.name.clone();
+                            menu.confirm_delete = false;
+                        }
+                    }
+                });
+
+            if let Some(selected_world) = menu.selected_world.clone() {
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("Play").clicked() {
+                        world_to_play = Some(menu.saves.world_directory(&selected_world));
+                    }
+
+                    if ui.button("Duplicate").clicked() {
+                        let new_name = menu
+                            .saves
+                            .unique_name(&format!("{} - Copy", selected_world));
+                        match menu.saves.duplicate_world(&selected_world, &new_name) {
+                            Ok(()) => menu.refresh(),
+                            Err(err) => menu.error = Some(err.to_string()),
+                        }
+                    }
+
+                    if menu.confirm_delete {
+                        if ui.button("Confirm Delete").clicked() {
+                            match menu.saves.delete_world(&selected_world) {
+                                Ok(()) => menu.refresh(),
+                                Err(err) => menu.error = Some(err.to_string()),
+                            }
+                        }
+
+                        if ui.button("Cancel").clicked() {
+                            menu.confirm_delete = false;
+                        }
+                    } else if ui.button("Delete").clicked() {
+                        menu.confirm_delete = true;
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut menu.rename_to);
+
+                    if ui.button("Rename").clicked() && menu.rename_to != selected_world {
+                        match menu.saves.rename_world(&selected_world, &menu.rename_to) {
+                            Ok(()) => menu.refresh(),
+                            Err(err) => menu.error = Some(err.to_string()),
+                        }
+                    }
+                });
+            }
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.label("World Name:");
+                ui.text_edit_singleline(&mut menu.new_world_name);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Seed:");
+                ui.text_edit_singleline(&mut menu.new_world_seed);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Generator:");
+                egui::ComboBox::from_id_salt("new_world_generator")
+                    .selected_text(menu.new_world_generator.name())
+                    .show_ui(ui, |ui| {
+                        for preset in GeneratorSettings::presets() {
+                            let selected = menu.new_world_generator.name() == preset.name();
+                            if ui.selectable_label(selected, preset.name()).clicked() && !selected {
+                                match &preset {
+                                    GeneratorSettings::Superflat { layers } => {
+                                        menu.new_world_layers = format_flat_layers(layers);
+                                    }
+                                    GeneratorSettings::Heightmap(settings) => {
+                                        menu.new_world_heightmap = settings.heightmap.clone();
+                                        menu.new_world_color_map =
+                                            settings.color_map.clone().unwrap_or_default();
+                                    }
+                                    _ => {}
+                                }
+                                menu.new_world_generator = preset;
+                            }
+                        }
+                    });
+            });
+            match menu.new_world_generator {
+                GeneratorSettings::Superflat { .. } => {
+                    ui.horizontal(|ui| {
+                        ui.label("Layers:");
+                        ui.text_edit_singleline(&mut menu.new_world_layers);
+                    });
+                }
+                GeneratorSettings::Heightmap(_) => {
+                    ui.horizontal(|ui| {
+                        ui.label("Heightmap:");
+                        ui.text_edit_singleline(&mut menu.new_world_heightmap);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Color Map:");
+                        ui.text_edit_singleline(&mut menu.new_world_color_map);
+                    });
+                }
+                _ => {}
+            }
+
             if ui.button("Create World").clicked() {
-                // Create a server
-                let (server, server_transport, addr) = new_renet_server(true);
+                let name = if menu.new_world_name.trim().is_empty() {
+                    menu.saves.unique_name("New World")
+                } else {
+                    menu.new_world_name.trim().to_string()
+                };
+
+                let generator = match &menu.new_world_generator {
+                    GeneratorSettings::Superflat { .. } => {
+                        parse_flat_layers(&menu.new_world_layers)
+                            .map(|layers| GeneratorSettings::Superflat { layers })
+                    }
+                    GeneratorSettings::Heightmap(settings) => {
+                        let color_map = menu.new_world_color_map.trim();
+                        Ok(GeneratorSettings::Heightmap(ImageTerrainSettings {
+                            heightmap: menu.new_world_heightmap.trim().to_string(),
+                            color_map: (!color_map.is_empty()).then(|| color_map.to_string()),
+                            ..settings.clone()
+                        }))
+                    }
+                    generator => Ok(generator.clone()),
+                };
 
-                commands.insert_resource(server);
-                commands.insert_resource(server_transport);
-                commands.insert_resource(RenetServerVisualizer::<200>::default());
+                let seed = parse_seed(&menu.new_world_seed);
+                // Blocks missing from the data files or images that can't be read would only
+                // show up when loading the world
+                match generator.and_then(|generator| {
+                    load_world_generator(&generator, seed, &[])?;
+                    menu.saves.create_world(&name, seed, generator)
+                }) {
+                    Ok(world_directory) => {
+                        menu.new_world_name.clear();
+                        menu.new_world_seed.clear();
+                        menu.new_world_generator = GeneratorSettings::default();
+                        world_to_play = Some(world_directory);
+                    }
+                    Err(err) => menu.error = Some(err.to_string()),
+                }
+            }
 
-                next_server_state.set(ServerState::LoadingWorld);
+            if let Some(error) = &menu.error {
+                ui.colored_label(egui::Color32::from_rgb(255, 80, 80), error);
+            }
+
+            if let Some(world_directory) = world_to_play {
+                menu.refresh();
+                menu.error = None;
 
-                let (client, transport) = new_renet_client(addr);
-                commands.insert_resource(client);
-                commands.insert_resource(transport);
+                start_singleplayer_world(&mut commands, world_directory);
 
+                next_server_state.set(ServerState::LoadingWorld);
                 next_client_state.set(ClientState::JoiningServer);
                 next_main_menu_state.set(MainMenuState::MainMenu);
                 next_client_mode_state.set(ClientMode::SinglePlayer);
             }
 
             if ui.button("Back").clicked() {
+                menu.refresh();
+                menu.error = None;
                 next_main_menu_state.set(MainMenuState::MainMenu);
             }
         }
@@ -109,9 +385,7 @@ fn main_menu_system(
 
                 println!("Connecting to server: {}", server_addr);
 
-                let (client, transport) = new_renet_client(server_addr);
-                commands.insert_resource(client);
-                commands.insert_resource(transport);
+                connect_client(&mut commands, server_addr);
 
                 next_client_state.set(ClientState::JoiningServer);
                 next_main_menu_state.set(MainMenuState::MainMenu);
@@ -125,6 +399,14 @@ fn main_menu_system(
         MainMenuState::Settings => {
             ui.heading("Settings");
 
+            ui.add(
+                egui::Slider::new(
+                    &mut chunk_loading_settings.view_distance,
+                    MIN_VIEW_DISTANCE..=MAX_VIEW_DISTANCE,
+                )
+                .text("View Distance"),
+            );
+
             if ui.button("Back").clicked() {
                 next_main_menu_state.set(MainMenuState::MainMenu);
             }
@@ -185,6 +467,8 @@ fn debug_menu_system(
     mut contexts: EguiContexts,
     diagnostics: Res<DiagnosticsStore>,
     player_query: Query<(&Player, &Transform), (With<Player>, Without<PlayerCamera>)>,
+    // Only there when the server runs in this app
+    world_generator: Option<Res<GameWorldGenerator>>,
 ) {
     if let Ok((player, player_transform)) = player_query.get_single() {
         let fps = diagnostics
@@ -251,6 +535,17 @@ fn debug_menu_system(
                     ),
                 );
 
+                if let Some(biome) = world_generator.as_ref().and_then(|world_generator| {
+                    world_generator
+                        .generator
+                        .biome_at(player_pos.x, player_pos.z)
+                }) {
+                    ui.colored_label(
+                        egui::Color32::from_rgb(255, 255, 255),
+                        format!("Biome: {}", biome),
+                    );
+                }
+
                 if let Some(looking_at_pos) = player.looking_at_pos {
                     ui.colored_label(
                         egui::Color32::from_rgb(255, 255, 255),
@@ -4,6 +4,7 @@ mod core;
 mod discord_presence;
 mod multiplayer;
 mod prelude;
+mod storage;
 mod terrain;
 mod voxel;
 
@@ -1,13 +1,14 @@
-use crate::block::BlockType;
+use crate::block::Block;
+use crate::block_registry::{BlockRegistry, GameBlockRegistry};
 use crate::chunk::Chunk;
 use crate::multiplayer::{Channel, ClientMessage, ServerMessage};
-use crate::player::{OtherPlayer, PLAYER_HEIGHT, PLAYER_WIDTH};
+use crate::player::{OtherPlayer, PlayerSpawn, PLAYER_HEIGHT, PLAYER_WIDTH};
 use crate::world::GameWorld;
 use crate::{
     connection_config, Assets, Capsule3d, Color, Commands, Mesh, MeshMaterial3d,
     PendingServerMessage, Query, StandardMaterial, Transform, Vec3, With, PROTOCOL_ID,
 };
-use bevy::prelude::{Mesh3d, Res, ResMut};
+use bevy::prelude::{error, Mesh3d, Res, ResMut};
 use bevy_renet::netcode::ClientAuthentication;
 use bevy_renet::renet::RenetClient;
 use bincode::config;
@@ -43,8 +44,7 @@ pub fn client_receive_system(
         return;
     }
 
-    pending_messages.0.clear();
-
+    // Messages held by `client_handle_messages` stay until they can be handled
     for channel in [Channel::Reliable, Channel::Unreliable, Channel::Chunk] {
         while let Some(message) = client.receive_message(channel) {
             let server_message: ServerMessage =
@@ -61,6 +61,7 @@ pub fn client_handle_messages(
     mut client: ResMut<RenetClient>,
     transport: Res<NetcodeClientTransport>,
     game_world: Res<GameWorld>,
+    block_registry: Option<Res<GameBlockRegistry>>,
     mut pending_messages: ResMut<PendingServerMessage>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
@@ -72,8 +73,9 @@ pub fn client_handle_messages(
     }
 
     let client_id = transport.client_id();
+    let mut registry = block_registry.map(|block_registry| Arc::clone(&block_registry.registry));
 
-    for server_message in pending_messages.0.drain(..) {
+    for server_message in std::mem::take(&mut pending_messages.0) {
         match server_message {
             ServerMessage::Ping => {
                 println!("Client {} received ping.", client_id);
@@ -86,14 +88,53 @@ pub fn client_handle_messages(
             ServerMessage::Pong => {
                 println!("Client {} received pong.", client_id);
             }
+            ServerMessage::BlockRegistry(definitions) => {
+                match BlockRegistry::from_definitions(definitions) {
+                    Ok(new_registry) => {
+                        println!(
+                            "Client {} received {} blocks.",
+                            client_id,
+                            new_registry.len()
+                        );
+                        let block_registry = GameBlockRegistry::new(new_registry);
+                        registry = Some(Arc::clone(&block_registry.registry));
+                        commands.insert_resource(block_registry);
+                    }
+                    Err(err) => error!("Invalid block registry from the server: {}", err),
+                }
+            }
+            // The registry comes on another channel than the chunks and can arrive after them,
+            // nothing else is handled until it tells what their blocks are
+            server_message if registry.is_none() => pending_messages.0.push(server_message),
             ServerMessage::Chunk(chunk_pos, compressed_chunk) => {
-                let decompressed_chunk = Chunk::from_compressed(&compressed_chunk);
+                let registry = registry.as_deref().unwrap();
+                let world = game_world.world.read().unwrap();
 
-                game_world
-                    .world
-                    .write()
+                // The chunk went out of the view distance while it was requested
+                if !world
+                    .chunk_entities
+                    .read()
                     .unwrap()
-                    .set_chunk(chunk_pos, decompressed_chunk);
+                    .contains_key(&chunk_pos)
+                {
+                    continue;
+                }
+
+                match Chunk::try_from_compressed(&compressed_chunk, registry) {
+                    Ok(chunk) => world.set_chunk(chunk_pos, chunk, registry),
+                    Err(err) => {
+                        error!("Couldn't decode the chunk at {chunk_pos} from the server: {err}");
+                        // No longer waited for, so that it can be requested again
+                        world
+                            .pending_requested_chunks
+                            .write()
+                            .unwrap()
+                            .remove(&chunk_pos);
+                    }
+                }
+            }
+            ServerMessage::PlayerSpawn(pos) => {
+                commands.insert_resource(PlayerSpawn(pos));
             }
             ServerMessage::PlayerJoined(id, pos) => {
                 println!("Client {} received player joined: {}", client_id, id);
@@ -151,18 +192,29 @@ pub fn client_handle_messages(
                 }
             }
             ServerMessage::BlockBroken(pos) => {
-                game_world
-                    .world
-                    .write()
-                    .unwrap()
-                    .edit_voxel(&pos, BlockType::Void);
+                game_world.world.write().unwrap().edit_voxel(
+                    &pos,
+                    Block::new_empty(),
+                    registry.as_deref().unwrap(),
+                );
+            }
+            ServerMessage::BlockPlaced(pos, block) => {
+                game_world.world.write().unwrap().edit_voxel(
+                    &pos,
+                    block,
+                    registry.as_deref().unwrap(),
+                );
             }
-            ServerMessage::BlockPlaced(pos, block_type) => {
+            ServerMessage::BlockEntityCreated(pos, block_entity)
+            | ServerMessage::BlockEntityUpdated(pos, block_entity) => {
                 game_world
                     .world
-                    .write()
+                    .read()
                     .unwrap()
-                    .edit_voxel(&pos, block_type);
+                    .set_block_entity(&pos, block_entity);
+            }
+            ServerMessage::BlockEntityRemoved(pos) => {
+                game_world.world.read().unwrap().remove_block_entity(&pos);
             }
         }
     }
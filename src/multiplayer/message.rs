@@ -1,4 +1,6 @@
-use crate::block::BlockType;
+use crate::block::Block;
+use crate::block_entity::BlockEntity;
+use crate::block_registry::BlockDefinition;
 use crate::chunk::CompressedChunk;
 use crate::{IVec3, Vec3};
 use bevy::prelude::Resource;
@@ -15,8 +17,10 @@ pub enum ClientMessage {
     Ping,
     Pong,
     BreakBlock(IVec3),
-    PlaceBlock(IVec3, BlockType),
+    PlaceBlock(IVec3, Block),
+    /// Ask for the chunk at a chunk coordinate, on all three axes
     RequestChunk(IVec3),
+    ChunkUnloaded(IVec3),
     PlayerMoved(Vec3),
 }
 
@@ -24,10 +28,16 @@ pub enum ClientMessage {
 pub enum ServerMessage {
     Ping,
     Pong,
+    /// Every block definition of the server, indexed by block id, sent when a client connects
+    BlockRegistry(Vec<BlockDefinition>),
     Chunk(IVec3, CompressedChunk),
+    PlayerSpawn(Vec3),
     PlayerJoined(u64, Vec3),
     PlayerMoved(u64, Vec3),
     PlayerLeft(u64),
     BlockBroken(IVec3),
-    BlockPlaced(IVec3, BlockType),
+    BlockPlaced(IVec3, Block),
+    BlockEntityCreated(IVec3, BlockEntity),
+    BlockEntityUpdated(IVec3, BlockEntity),
+    BlockEntityRemoved(IVec3),
 }
@@ -1,12 +1,18 @@
-use crate::block::BlockType;
+use crate::block::Block;
+use crate::block_entity::BlockEntity;
+use crate::block_registry::{BlockRegistry, GameBlockRegistry};
 use crate::chunk::ServerChunkEntity;
+use crate::chunk_tracker::ChunkTracker;
+use crate::level::LevelData;
 use crate::multiplayer::PROTOCOL_ID;
 use crate::quad::HALF_SIZE;
-use crate::world::GameWorld;
+use crate::world::{GameWorld, World};
 use crate::{
-    connection_config, Channel, ClientMessage, Commands, EventReader, IVec2, Lobby, NetworkPlayer,
-    PendingClientMessage, Query, Res, ResMut, ServerMessage, Transform, Vec3,
+    connection_config, Channel, ClientMessage, Commands, EventReader, IVec2, IVec3, Lobby,
+    NetworkPlayer, PendingClientMessage, Query, Res, ResMut, ServerMessage, Transform, Vec3,
 };
+use bevy::ecs::system::SystemParam;
+use bevy::log::warn;
 use bevy_egui::EguiContexts;
 use bevy_renet::netcode::{NetcodeServerTransport, ServerAuthentication, ServerConfig};
 use bevy_renet::renet::{RenetServer, ServerEvent};
@@ -56,33 +62,58 @@ pub fn new_renet_server(singleplayer: bool) -> (RenetServer, NetcodeServerTransp
     (server, transport, public_addr)
 }
 
+/// The world hosted by the server, with what it takes to send it to the clients
+#[derive(SystemParam)]
+pub struct ServerWorld<'w> {
+    game_world: Res<'w, GameWorld>,
+    block_registry: Res<'w, GameBlockRegistry>,
+    chunk_tracker: ResMut<'w, ChunkTracker>,
+}
+
+/// The players connected to the server
+#[derive(SystemParam)]
+pub struct ConnectedPlayers<'w, 's> {
+    lobby: ResMut<'w, Lobby>,
+    players: Query<'w, 's, &'static NetworkPlayer>,
+}
+
 pub fn server_update_system(
     mut server_events: EventReader<ServerEvent>,
     mut server: ResMut<RenetServer>,
     mut visualizer: ResMut<RenetServerVisualizer<200>>,
-    mut lobby: ResMut<Lobby>,
+    connected_players: ConnectedPlayers,
     mut commands: Commands,
-    game_world: Res<GameWorld>,
-    players: Query<&NetworkPlayer>,
+    server_world: ServerWorld,
+    mut level: ResMut<LevelData>,
 ) {
+    let ServerWorld {
+        game_world,
+        block_registry,
+        mut chunk_tracker,
+    } = server_world;
+    let ConnectedPlayers { mut lobby, players } = connected_players;
+
     for event in server_events.read() {
         match event {
             ServerEvent::ClientConnected { client_id } => {
-                let highest_block = game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .get_highest_block_at_coord(&IVec2::new(0, 0))
-                    .as_vec3();
-
                 println!("Client {} connected.", client_id);
                 visualizer.add_client(*client_id);
 
-                let position = Vec3::new(
-                    highest_block.x,
-                    highest_block.y + HALF_SIZE + 2.,
-                    highest_block.z,
-                );
+                let spawn_point = *level.spawn_point.get_or_insert_with(|| {
+                    let highest_block = game_world
+                        .world
+                        .read()
+                        .unwrap()
+                        .get_highest_block_at_coord(&IVec2::new(0, 0))
+                        .as_vec3();
+
+                    Vec3::new(
+                        highest_block.x,
+                        highest_block.y + HALF_SIZE + 2.,
+                        highest_block.z,
+                    )
+                });
+                let position = level.get_player_position(*client_id).unwrap_or(spawn_point);
 
                 let player = commands
                     .spawn(NetworkPlayer {
@@ -99,6 +130,22 @@ pub fn server_update_system(
                         .unwrap();
                 server.send_message(*client_id, Channel::Reliable, message);
 
+                // The client holds the chunks and edits it gets until it knows what the ids of
+                // their blocks mean
+                let message = bincode::serde::encode_to_vec(
+                    ServerMessage::BlockRegistry(block_registry.registry.definitions().to_vec()),
+                    config::standard(),
+                )
+                .unwrap();
+                server.send_message(*client_id, Channel::Reliable, message);
+
+                let message = bincode::serde::encode_to_vec(
+                    ServerMessage::PlayerSpawn(position),
+                    config::standard(),
+                )
+                .unwrap();
+                server.send_message(*client_id, Channel::Reliable, message);
+
                 // Send all players to the new player
                 for (id, player) in lobby.players.iter() {
                     if *id == *client_id {
@@ -123,8 +170,12 @@ pub fn server_update_system(
             ServerEvent::ClientDisconnected { client_id, reason } => {
                 println!("Client {} disconnected: {}", client_id, reason);
                 visualizer.remove_client(*client_id);
+                chunk_tracker.remove_client(*client_id);
 
                 if let Some((_, player)) = lobby.players.remove_by_left(client_id) {
+                    if let Ok(player) = players.get(player) {
+                        level.set_player_position(*client_id, player.transform.translation);
+                    }
                     commands.entity(player).despawn();
                 }
 
@@ -161,20 +212,31 @@ pub fn server_receive_system(
 
 pub fn server_handle_messages_system(
     mut pending_messages: ResMut<PendingClientMessage>,
-    server_world: Res<GameWorld>,
+    world: ServerWorld,
     mut server: ResMut<RenetServer>,
     mut commands: Commands,
+    lobby: Res<Lobby>,
+    mut players: Query<&mut NetworkPlayer>,
 ) {
+    let ServerWorld {
+        game_world: server_world,
+        block_registry,
+        mut chunk_tracker,
+    } = world;
+    let registry = &block_registry.registry;
+
     for (client_id, message) in pending_messages.0.drain(..) {
         match message {
             ClientMessage::Ping => {}
             ClientMessage::Pong => {}
             ClientMessage::BreakBlock(pos) => {
+                chunk_tracker.touch(World::get_chunk_coord(&pos));
+                remove_block_entity(&server_world.world.read().unwrap(), &mut server, pos);
                 server_world
                     .world
                     .write()
                     .unwrap()
-                    .edit_voxel(&pos, BlockType::Void);
+                    .edit_voxel(&pos, Block::new_empty(), registry);
 
                 let message = bincode::serde::encode_to_vec(
                     ServerMessage::BlockBroken(pos),
@@ -184,6 +246,12 @@ pub fn server_handle_messages_system(
                 server.broadcast_message(Channel::Reliable, message);
             }
             ClientMessage::PlayerMoved(pos) => {
+                if let Some(player) = lobby.players.get_by_left(&client_id) {
+                    if let Ok(mut player) = players.get_mut(*player) {
+                        player.transform.translation = pos;
+                    }
+                }
+
                 let message = bincode::serde::encode_to_vec(
                     ServerMessage::PlayerMoved(client_id, pos),
                     config::standard(),
@@ -191,21 +259,37 @@ pub fn server_handle_messages_system(
                 .unwrap();
                 server.broadcast_message_except(client_id, Channel::Unreliable, message);
             }
-            ClientMessage::PlaceBlock(pos, block_type) => {
+            ClientMessage::PlaceBlock(pos, block) => {
+                if !registry.is_valid(block) {
+                    warn!("Client {} placed an invalid block {:?}", client_id, block);
+                    continue;
+                }
+
+                chunk_tracker.touch(World::get_chunk_coord(&pos));
                 server_world
                     .world
                     .write()
                     .unwrap()
-                    .edit_voxel(&pos, block_type);
+                    .edit_voxel(&pos, block, registry);
 
                 let message = bincode::serde::encode_to_vec(
-                    ServerMessage::BlockPlaced(pos, block_type),
+                    ServerMessage::BlockPlaced(pos, block),
                     config::standard(),
                 )
                 .unwrap();
                 server.broadcast_message(Channel::Reliable, message);
+
+                create_placed_block_entity(
+                    &server_world.world.read().unwrap(),
+                    &mut server,
+                    pos,
+                    block,
+                    registry,
+                );
             }
             ClientMessage::RequestChunk(coord) => {
+                chunk_tracker.watch(coord, client_id);
+
                 let chunk = server_world.world.read().unwrap().get_chunk(coord);
 
                 if let Some(chunk) = chunk {
@@ -223,28 +307,82 @@ pub fn server_handle_messages_system(
 
                     let mut pending_generating_chunks =
                         world.pending_generating_chunks.write().unwrap();
-                    let pending_generating_chunk = pending_generating_chunks.get_mut(&coord);
 
-                    if pending_generating_chunk.is_none() {
+                    if let Some(pending_generating_chunk) =
+                        pending_generating_chunks.get_mut(&coord)
+                    {
+                        // Already generating, the chunk will be sent once it is done
+                        pending_generating_chunk.insert(client_id);
+                    } else {
                         let mut pending_generating_chunk = HashSet::new();
                         pending_generating_chunk.insert(client_id);
 
                         pending_generating_chunks.insert(coord, pending_generating_chunk);
-                    } else if let Some(pending_generating_chunk) = pending_generating_chunk {
-                        pending_generating_chunk.insert(client_id);
+                        commands.spawn(ServerChunkEntity(coord));
                     }
-
-                    world
-                        .chunk_entities
-                        .write()
-                        .unwrap()
-                        .insert(coord, commands.spawn(ServerChunkEntity(coord)).id());
                 }
             }
+            ClientMessage::ChunkUnloaded(coord) => {
+                chunk_tracker.unwatch(coord, client_id);
+            }
         }
     }
 }
 
+/// Attach a block entity to the block at `pos`, on the server and on every client
+pub fn set_block_entity(
+    world: &World,
+    server: &mut RenetServer,
+    pos: IVec3,
+    block_entity: BlockEntity,
+) {
+    if world.get_chunk(World::get_chunk_coord(&pos)).is_none() {
+        return;
+    }
+
+    let message = if world.set_block_entity(&pos, block_entity.clone()).is_some() {
+        ServerMessage::BlockEntityUpdated(pos, block_entity)
+    } else {
+        ServerMessage::BlockEntityCreated(pos, block_entity)
+    };
+
+    let message = bincode::serde::encode_to_vec(message, config::standard()).unwrap();
+    server.broadcast_message(Channel::Reliable, message);
+}
+
+/// Give a block just placed at `pos` its empty block entity, on the server and on every client
+///
+/// Placing the same block again, like a chest with only its state changed, keeps the block entity
+/// it already had.
+pub fn create_placed_block_entity(
+    world: &World,
+    server: &mut RenetServer,
+    pos: IVec3,
+    block: Block,
+    registry: &BlockRegistry,
+) {
+    let Some(kind) = registry.get(block.voxel_type).block_entity else {
+        return;
+    };
+
+    // Replacing a block of another type already dropped its block entity
+    if world.get_block_entity(&pos).is_none() {
+        set_block_entity(world, server, pos, BlockEntity::new(kind));
+    }
+}
+
+/// Remove the block entity at `pos`, on the server and on every client
+pub fn remove_block_entity(world: &World, server: &mut RenetServer, pos: IVec3) {
+    if world.remove_block_entity(&pos).is_none() {
+        return;
+    }
+
+    let message =
+        bincode::serde::encode_to_vec(ServerMessage::BlockEntityRemoved(pos), config::standard())
+            .unwrap();
+    server.broadcast_message(Channel::Reliable, message);
+}
+
 pub fn update_visualizer_system(
     mut egui_contexts: EguiContexts,
     mut visualizer: ResMut<RenetServerVisualizer<200>>,
@@ -253,3 +391,46 @@ pub fn update_visualizer_system(
     visualizer.update(&server);
     visualizer.show_window(egui_contexts.ctx_mut());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_entity::{BlockEntityKind, ItemStack};
+    use crate::chunk::Chunk;
+
+    #[test]
+    fn test_placing_a_chest_again_keeps_its_contents() {
+        let registry = BlockRegistry::default();
+        let chest = Block::new(registry.block_type("chest").unwrap());
+        let stone = Block::new(registry.block_type("stone").unwrap());
+        let mut server = RenetServer::new(connection_config());
+        let world = World::new();
+        world.set_chunk(IVec3::ZERO, Chunk::default(), &registry);
+        let pos = IVec3::new(4, 20, 6);
+
+        world.edit_voxel(&pos, chest, &registry);
+        create_placed_block_entity(&world, &mut server, pos, chest, &registry);
+        let Some(BlockEntity::Chest { mut slots }) = world.get_block_entity(&pos) else {
+            panic!("the chest has no block entity");
+        };
+        slots[0] = Some(ItemStack {
+            block: stone.voxel_type,
+            count: 3,
+        });
+        let filled = BlockEntity::Chest { slots };
+        world.set_block_entity(&pos, filled.clone());
+
+        world.edit_voxel(&pos, chest, &registry);
+        create_placed_block_entity(&world, &mut server, pos, chest, &registry);
+        assert_eq!(world.get_block_entity(&pos), Some(filled));
+
+        // A chest replacing another block starts empty
+        world.edit_voxel(&pos, stone, &registry);
+        world.edit_voxel(&pos, chest, &registry);
+        create_placed_block_entity(&world, &mut server, pos, chest, &registry);
+        assert_eq!(
+            world.get_block_entity(&pos),
+            Some(BlockEntity::new(BlockEntityKind::Chest))
+        );
+    }
+}
@@ -3,5 +3,6 @@ pub use bevy::prelude::*;
 pub use crate::core::*;
 pub use crate::discord_presence::*;
 pub use crate::multiplayer::*;
+pub use crate::storage::*;
 pub use crate::terrain::*;
 pub use crate::voxel::*;
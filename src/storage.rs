@@ -0,0 +1,245 @@
+pub mod level;
+pub mod region;
+pub mod saves;
+pub mod server_settings;
+
+use crate::multiplayer::NetworkPlayer;
+use crate::storage::level::LevelData;
+use crate::storage::region::RegionStorage;
+use crate::terrain::world_generator::{GameWorldGenerator, GeneratorSettings};
+use crate::terrain::worldgen_data::{WorldgenData, WORLDGEN_FILE};
+use crate::voxel::block_registry::{BlockRegistry, GameBlockRegistry, BLOCKS_FILE};
+use crate::voxel::world::{GameWorld, World};
+use crate::{ClientState, ServerState};
+use bevy::prelude::*;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// On-disk storage of the world the server is running
+#[derive(Resource, Clone)]
+pub struct WorldStorage {
+    pub directory: PathBuf,
+    pub regions: Arc<RegionStorage>,
+}
+
+impl WorldStorage {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        let directory = directory.into();
+
+        Self {
+            regions: Arc::new(RegionStorage::new(directory.join("region"))),
+            directory,
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Write every chunk modified since the last save back to its region file
+    pub fn save_chunks(&self, world: &World) {
+        let unsaved_chunks: Vec<IVec3> = world.unsaved_chunks.write().unwrap().drain().collect();
+        let mut saved = 0;
+
+        for chunk_coord in unsaved_chunks {
+            if let Some(chunk) = world.get_chunk(chunk_coord) {
+                if let Err(err) = self.regions.save_chunk(&chunk.read().unwrap()) {
+                    error!("Failed to save chunk {:?}: {}", chunk_coord, err);
+                    // Try again on the next save
+                    world.unsaved_chunks.write().unwrap().insert(chunk_coord);
+                    continue;
+                }
+                saved += 1;
+            }
+        }
+
+        if let Err(err) = self.regions.flush() {
+            error!("Failed to flush region files: {}", err);
+        }
+
+        debug!("Saved {} chunks to {:?}", saved, self.directory);
+    }
+
+    /// Write a single chunk back to its region file if it was modified since the last save
+    pub fn save_chunk(&self, world: &World, chunk_coord: &IVec3) -> io::Result<()> {
+        if !world.unsaved_chunks.write().unwrap().remove(chunk_coord) {
+            return Ok(());
+        }
+
+        if let Some(chunk) = world.get_chunk(*chunk_coord) {
+            if let Err(err) = self.regions.save_chunk(&chunk.read().unwrap()) {
+                world.unsaved_chunks.write().unwrap().insert(*chunk_coord);
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the level file, with the position of every connected player
+    pub fn save_level(&self, level: &mut LevelData, players: &Query<&NetworkPlayer>) {
+        for player in players.iter() {
+            level.set_player_position(player.id, player.transform.translation);
+        }
+
+        if let Err(err) = level.save(&self.directory) {
+            error!("Failed to save level data: {}", err);
+        }
+    }
+}
+
+/// Why the last world failed to load, shown by the singleplayer menu
+#[derive(Resource)]
+pub struct WorldLoadError(pub String);
+
+/// Load the level file of the world, going back to the menus if it can't be read
+///
+/// A dedicated server has no menu to go back to, and exits instead.
+pub fn load_level_system(
+    mut commands: Commands,
+    storage: Res<WorldStorage>,
+    mut next_server_state: ResMut<NextState<ServerState>>,
+    // Only there when a client runs in this app
+    next_client_state: Option<ResMut<NextState<ClientState>>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Err(err) = load_level(&mut commands, &storage) else {
+        return;
+    };
+
+    error!("Failed to load world {:?}: {}", storage.directory(), err);
+    commands.remove_resource::<LevelData>();
+    next_server_state.set(ServerState::MainMenu);
+
+    match next_client_state {
+        Some(mut next_client_state) => {
+            commands.insert_resource(WorldLoadError(err.to_string()));
+            next_client_state.set(ClientState::MainMenu);
+        }
+        None => {
+            exit.send(AppExit::error());
+        }
+    }
+}
+
+fn load_level(commands: &mut Commands, storage: &WorldStorage) -> io::Result<()> {
+    let mut level = LevelData::load(storage.directory())
+        .map_err(|err| io::Error::new(err.kind(), format!("level data: {}", err)))?
+        .unwrap_or_default();
+
+    println!(
+        "Loading world with seed {} and generator {}",
+        level.seed, level.generator
+    );
+
+    // Blocks the world already uses keep their id, so its saved chunks stay valid
+    let (registry, world_generator) =
+        load_world_generator(&level.generator, level.seed, &level.block_names)?;
+    println!("Loaded {} blocks", registry.len());
+    level.block_names = registry.names();
+
+    level.last_played = Some(SystemTime::now());
+    if let Err(err) = level.save(storage.directory()) {
+        error!("Failed to save level data: {}", err);
+    }
+
+    commands.insert_resource(GameBlockRegistry { registry });
+    commands.insert_resource(world_generator);
+
+    commands.insert_resource(level);
+
+    Ok(())
+}
+
+/// Load the block and world generation data files, and create the generator of a world with them
+///
+/// Blocks named in `block_names` keep their id, the other ones are added after them.
+pub fn load_world_generator(
+    settings: &GeneratorSettings,
+    seed: i32,
+    block_names: &[String],
+) -> io::Result<(Arc<BlockRegistry>, GameWorldGenerator)> {
+    let registry = BlockRegistry::load(Path::new(BLOCKS_FILE), block_names)
+        .map(Arc::new)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", BLOCKS_FILE, err)))?;
+    let worldgen = WorldgenData::load(Path::new(WORLDGEN_FILE))
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", WORLDGEN_FILE, err)))?;
+    let world_generator = settings
+        .build(seed, &worldgen, &registry)
+        .map_err(|err| io::Error::new(err.kind(), format!("world generator: {}", err)))?;
+
+    Ok((registry, world_generator))
+}
+
+pub fn advance_world_time_system(mut level: ResMut<LevelData>, time: Res<Time>) {
+    level.world_time += time.delta();
+}
+
+pub fn autosave_world_system(
+    game_world: Res<GameWorld>,
+    storage: Res<WorldStorage>,
+    mut level: ResMut<LevelData>,
+    players: Query<&NetworkPlayer>,
+    mut timer: Local<Timer>,
+    time: Res<Time>,
+) {
+    timer.set_duration(AUTOSAVE_INTERVAL);
+    timer.set_mode(TimerMode::Repeating);
+
+    if timer.tick(time.delta()).just_finished() {
+        storage.save_chunks(&game_world.world.read().unwrap());
+        storage.save_level(&mut level, &players);
+    }
+}
+
+pub fn save_world_on_exit_system(
+    mut exit_events: EventReader<AppExit>,
+    game_world: Res<GameWorld>,
+    storage: Option<Res<WorldStorage>>,
+    level: Option<ResMut<LevelData>>,
+    players: Query<&NetworkPlayer>,
+) {
+    if exit_events.read().next().is_none() {
+        return;
+    }
+
+    if let Some(storage) = storage {
+        println!("Saving world to {:?}", storage.directory());
+        storage.save_chunks(&game_world.world.read().unwrap());
+
+        if let Some(mut level) = level {
+            level.last_played = Some(SystemTime::now());
+            storage.save_level(&mut level, &players);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::image_terrain::ImageTerrainSettings;
+    use crate::terrain::world_generator::FlatLayer;
+
+    #[test]
+    fn test_load_world_generator_errors() {
+        assert!(load_world_generator(&GeneratorSettings::superflat(), 0, &[]).is_ok());
+
+        let unknown_block = GeneratorSettings::Superflat {
+            layers: vec![FlatLayer::new("unknown", 1)],
+        };
+        let Err(err) = load_world_generator(&unknown_block, 0, &[]) else {
+            panic!("the superflat world uses a block missing from the registry");
+        };
+        assert!(err.to_string().starts_with("world generator: "));
+
+        let missing_image = GeneratorSettings::Heightmap(ImageTerrainSettings {
+            heightmap: "assets/heightmaps/missing.png".to_string(),
+            ..Default::default()
+        });
+        assert!(load_world_generator(&missing_image, 0, &[]).is_err());
+    }
+}
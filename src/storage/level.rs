@@ -0,0 +1,149 @@
+use crate::terrain::world_generator::GeneratorSettings;
+use bevy::math::Vec3;
+use bevy::prelude::Resource;
+use bincode::config;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+pub const LEVEL_FILE_NAME: &str = "level.dat";
+pub const LEVEL_FORMAT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PlayerData {
+    pub position: Vec3,
+}
+
+/// Metadata of a world, stored next to its region files
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct LevelData {
+    pub version: u32,
+    pub seed: i32,
+    pub generator: GeneratorSettings,
+    /// Computed from the terrain the first time a player joins
+    pub spawn_point: Option<Vec3>,
+    pub world_time: Duration,
+    /// Last known state of each player, by client id
+    pub players: HashMap<u64, PlayerData>,
+    /// Name of the block of each id used in the saved chunks, empty until the blocks are loaded
+    pub block_names: Vec<String>,
+    /// When the world was last opened or closed, `None` until it is first played
+    pub last_played: Option<SystemTime>,
+}
+
+fn invalid_data(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+impl Default for LevelData {
+    fn default() -> Self {
+        Self::new(0, GeneratorSettings::Default)
+    }
+}
+
+impl LevelData {
+    pub fn new(seed: i32, generator: GeneratorSettings) -> Self {
+        Self {
+            version: LEVEL_FORMAT_VERSION,
+            seed,
+            generator,
+            spawn_point: None,
+            world_time: Duration::ZERO,
+            players: HashMap::new(),
+            block_names: Vec::new(),
+            last_played: None,
+        }
+    }
+
+    /// Read the level file of a world, returns `None` if the world has none yet
+    pub fn load(directory: &Path) -> io::Result<Option<Self>> {
+        let path = directory.join(LEVEL_FILE_NAME);
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let bytes = fs::read(path)?;
+        // Every version starts with its version number
+        let version: u32 = bincode::serde::decode_from_slice(&bytes, config::standard())
+            .map_err(invalid_data)?
+            .0;
+
+        let level = match version {
+            LEVEL_FORMAT_VERSION => {
+                bincode::serde::decode_from_slice(&bytes, config::standard())
+                    .map_err(invalid_data)?
+                    .0
+            }
+            _ => {
+                return Err(invalid_data(format!(
+                    "level format version {} is not supported, the latest is {}",
+                    version, LEVEL_FORMAT_VERSION
+                )))
+            }
+        };
+
+        Ok(Some(level))
+    }
+
+    pub fn save(&self, directory: &Path) -> io::Result<()> {
+        let bytes =
+            bincode::serde::encode_to_vec(self, config::standard()).map_err(invalid_data)?;
+
+        fs::create_dir_all(directory)?;
+
+        // Write next to the level file first so a crash mid-write never loses the previous one
+        let temp_path = directory.join(format!("{}.tmp", LEVEL_FILE_NAME));
+        fs::write(&temp_path, bytes)?;
+        fs::rename(temp_path, directory.join(LEVEL_FILE_NAME))
+    }
+
+    pub fn get_player_position(&self, client_id: u64) -> Option<Vec3> {
+        self.players.get(&client_id).map(|player| player.position)
+    }
+
+    pub fn set_player_position(&mut self, client_id: u64, position: Vec3) {
+        self.players.entry(client_id).or_default().position = position;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_data_roundtrip() {
+        let directory =
+            std::env::temp_dir().join(format!("voxel_game_level_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        assert!(LevelData::load(&directory).unwrap().is_none());
+
+        let mut level = LevelData::new(1234, GeneratorSettings::superflat());
+        level.spawn_point = Some(Vec3::new(0.5, 70.0, 0.5));
+        level.world_time = Duration::from_secs(42);
+        level.set_player_position(7, Vec3::new(10.0, 64.0, -3.0));
+        level.block_names = vec!["air".to_string(), "stone".to_string()];
+        level.last_played = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        level.save(&directory).unwrap();
+
+        let loaded = LevelData::load(&directory).unwrap().unwrap();
+        assert_eq!(loaded.version, LEVEL_FORMAT_VERSION);
+        assert_eq!(loaded.seed, 1234);
+        assert_eq!(loaded.generator, level.generator);
+        assert_eq!(loaded.spawn_point, level.spawn_point);
+        assert_eq!(loaded.world_time, level.world_time);
+        assert_eq!(
+            loaded.get_player_position(7),
+            Some(Vec3::new(10.0, 64.0, -3.0))
+        );
+        assert_eq!(loaded.get_player_position(8), None);
+        assert_eq!(loaded.block_names, level.block_names);
+        assert_eq!(loaded.last_played, level.last_played);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
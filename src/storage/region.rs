@@ -0,0 +1,392 @@
+use crate::voxel::block_registry::BlockRegistry;
+use crate::voxel::chunk::{Chunk, CompressedChunk};
+use bevy::math::{IVec2, IVec3};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Number of chunks along each horizontal axis of a region file, a region only holds chunks
+/// with the same y coordinate
+pub const REGION_SIZE: i32 = 32;
+pub const REGION_CHUNK_COUNT: usize = (REGION_SIZE * REGION_SIZE) as usize;
+
+/// Chunk payloads are stored aligned on sectors, so a chunk that shrinks or grows a little
+/// can be rewritten in place
+pub const SECTOR_SIZE: u64 = 4096;
+
+const ENTRY_SIZE: u64 = 8;
+const HEADER_SIZE: u64 = REGION_CHUNK_COUNT as u64 * ENTRY_SIZE;
+const HEADER_SECTORS: u32 = HEADER_SIZE.div_ceil(SECTOR_SIZE) as u32;
+
+/// Location of a chunk inside a region file
+///
+/// `offset` is expressed in sectors and `length` in bytes, a `length` of 0 means the chunk
+/// was never written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegionEntry {
+    pub offset: u32,
+    pub length: u32,
+}
+
+impl RegionEntry {
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn sector_count(&self) -> u32 {
+        Self::sectors_for(self.length as u64)
+    }
+
+    fn sectors_for(length: u64) -> u32 {
+        length.div_ceil(SECTOR_SIZE) as u32
+    }
+
+    fn to_bytes(self) -> [u8; ENTRY_SIZE as usize] {
+        let mut bytes = [0; ENTRY_SIZE as usize];
+        bytes[..4].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[4..].copy_from_slice(&self.length.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            offset: u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+            length: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+        }
+    }
+}
+
+/// A file holding up to `REGION_SIZE * REGION_SIZE` compressed chunks
+///
+/// The file starts with an offset table of one [`RegionEntry`] per chunk, followed by the
+/// chunk payloads aligned on [`SECTOR_SIZE`].
+pub struct RegionFile {
+    file: File,
+    entries: Vec<RegionEntry>,
+}
+
+impl RegionFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let mut entries = vec![RegionEntry::default(); REGION_CHUNK_COUNT];
+
+        if file.metadata()?.len() < HEADER_SECTORS as u64 * SECTOR_SIZE {
+            // New (or truncated) region, write an empty offset table
+            file.set_len(HEADER_SECTORS as u64 * SECTOR_SIZE)?;
+        } else {
+            let mut header = vec![0; HEADER_SIZE as usize];
+            file.seek(SeekFrom::Start(0))?;
+            file.read_exact(&mut header)?;
+
+            for (entry, bytes) in entries
+                .iter_mut()
+                .zip(header.chunks_exact(ENTRY_SIZE as usize))
+            {
+                *entry = RegionEntry::from_bytes(bytes);
+            }
+        }
+
+        Ok(Self { file, entries })
+    }
+
+    /// Index of a chunk in the offset table, from its position relative to the region
+    pub fn get_index(local_pos: &IVec2) -> usize {
+        (local_pos.y * REGION_SIZE + local_pos.x) as usize
+    }
+
+    pub fn get_entry(&self, local_pos: &IVec2) -> RegionEntry {
+        self.entries[Self::get_index(local_pos)]
+    }
+
+    pub fn read_chunk(&mut self, local_pos: &IVec2) -> io::Result<Option<CompressedChunk>> {
+        let entry = self.get_entry(local_pos);
+
+        if entry.is_empty() {
+            return Ok(None);
+        }
+
+        // The entry comes from disk, its sectors must be past the offset table and inside the file
+        // before its length is trusted
+        let end = (entry.offset as u64 + entry.sector_count() as u64) * SECTOR_SIZE;
+        if entry.offset < HEADER_SECTORS || end > self.file.metadata()?.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "region entry at sector {} with {} bytes is outside of the file",
+                    entry.offset, entry.length
+                ),
+            ));
+        }
+
+        let mut data = vec![0; entry.length as usize];
+        self.file
+            .seek(SeekFrom::Start(entry.offset as u64 * SECTOR_SIZE))?;
+        self.file.read_exact(&mut data)?;
+
+        Ok(Some(data))
+    }
+
+    pub fn write_chunk(&mut self, local_pos: &IVec2, data: &[u8]) -> io::Result<()> {
+        let index = Self::get_index(local_pos);
+        let previous = self.entries[index];
+        let sectors_needed = RegionEntry::sectors_for(data.len() as u64);
+
+        // Reuse the previous sectors when the chunk still fits, otherwise append at the end
+        let offset = if !previous.is_empty() && previous.sector_count() >= sectors_needed {
+            previous.offset
+        } else {
+            self.file.metadata()?.len().div_ceil(SECTOR_SIZE) as u32
+        };
+
+        self.file
+            .seek(SeekFrom::Start(offset as u64 * SECTOR_SIZE))?;
+        self.file.write_all(data)?;
+
+        let end = (offset + sectors_needed) as u64 * SECTOR_SIZE;
+        if self.file.metadata()?.len() < end {
+            self.file.set_len(end)?;
+        }
+
+        let entry = RegionEntry {
+            offset,
+            length: data.len() as u32,
+        };
+        self.file.seek(SeekFrom::Start(index as u64 * ENTRY_SIZE))?;
+        self.file.write_all(&entry.to_bytes())?;
+        self.entries[index] = entry;
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+/// Region files of a world, opened lazily and kept open once used
+pub struct RegionStorage {
+    directory: PathBuf,
+    regions: Mutex<HashMap<IVec3, RegionFile>>,
+}
+
+impl RegionStorage {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            regions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Region containing a chunk, and the position of the chunk inside that region
+    pub fn chunk_to_region(chunk_pos: &IVec3) -> (IVec3, IVec2) {
+        (
+            IVec3::new(
+                chunk_pos.x.div_euclid(REGION_SIZE),
+                chunk_pos.y,
+                chunk_pos.z.div_euclid(REGION_SIZE),
+            ),
+            IVec2::new(
+                chunk_pos.x.rem_euclid(REGION_SIZE),
+                chunk_pos.z.rem_euclid(REGION_SIZE),
+            ),
+        )
+    }
+
+    pub fn region_path(&self, region_pos: &IVec3) -> PathBuf {
+        self.directory.join(format!(
+            "r.{}.{}.{}.region",
+            region_pos.x, region_pos.y, region_pos.z
+        ))
+    }
+
+    fn with_region<T>(
+        &self,
+        region_pos: IVec3,
+        create: bool,
+        f: impl FnOnce(&mut RegionFile) -> io::Result<T>,
+    ) -> io::Result<Option<T>> {
+        let mut regions = self.regions.lock().unwrap();
+
+        let region = match regions.entry(region_pos) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let path = self.region_path(&region_pos);
+
+                if !create && !path.exists() {
+                    return Ok(None);
+                }
+
+                fs::create_dir_all(&self.directory)?;
+                entry.insert(RegionFile::open(&path)?)
+            }
+        };
+
+        f(region).map(Some)
+    }
+
+    pub fn read_chunk(&self, chunk_pos: &IVec3) -> io::Result<Option<CompressedChunk>> {
+        let (region_pos, local_pos) = Self::chunk_to_region(chunk_pos);
+
+        self.with_region(region_pos, false, |region| region.read_chunk(&local_pos))
+            .map(Option::flatten)
+    }
+
+    pub fn write_chunk(&self, chunk_pos: &IVec3, data: &[u8]) -> io::Result<()> {
+        let (region_pos, local_pos) = Self::chunk_to_region(chunk_pos);
+
+        self.with_region(region_pos, true, |region| {
+            region.write_chunk(&local_pos, data)
+        })
+        .map(|_| ())
+    }
+
+    /// Load a chunk from disk, returns `None` if it was never saved
+    pub fn load_chunk(
+        &self,
+        chunk_pos: &IVec3,
+        registry: &BlockRegistry,
+    ) -> io::Result<Option<Chunk>> {
+        self.read_chunk(chunk_pos)?
+            .map(|data| Chunk::try_from_compressed(&data, registry))
+            .transpose()
+    }
+
+    pub fn save_chunk(&self, chunk: &Chunk) -> io::Result<()> {
+        self.write_chunk(&chunk.pos, &chunk.compress())
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        for region in self.regions.lock().unwrap().values_mut() {
+            region.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::block::{Block, BlockType};
+
+    const STONE: BlockType = BlockType::from_id(3);
+
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("voxel_game_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    #[test]
+    fn test_chunk_to_region() {
+        assert_eq!(
+            RegionStorage::chunk_to_region(&IVec3::new(33, 0, 5)),
+            (IVec3::new(1, 0, 0), IVec2::new(1, 5))
+        );
+        assert_eq!(
+            RegionStorage::chunk_to_region(&IVec3::new(-1, -2, -32)),
+            (IVec3::new(-1, -2, -1), IVec2::new(31, 0))
+        );
+    }
+
+    #[test]
+    fn test_region_file_roundtrip() {
+        let directory = temp_directory("region_roundtrip");
+        let registry = BlockRegistry::default();
+        let storage = RegionStorage::new(&directory);
+
+        let mut chunk = Chunk::new(IVec3::new(-3, 0, 40));
+        chunk.set_voxel(&IVec3::new(1, 2, 3), Block::new(STONE));
+
+        assert!(storage.load_chunk(&chunk.pos, &registry).unwrap().is_none());
+        storage.save_chunk(&chunk).unwrap();
+
+        // Reopen the region from disk
+        let storage = RegionStorage::new(&directory);
+        let loaded = storage.load_chunk(&chunk.pos, &registry).unwrap().unwrap();
+
+        assert_eq!(loaded.pos, chunk.pos);
+        assert_eq!(loaded.voxel_at(&IVec3::new(1, 2, 3)).voxel_type, STONE);
+        assert!(storage
+            .load_chunk(&IVec3::new(-2, 0, 40), &registry)
+            .unwrap()
+            .is_none());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_region_file_rewrite_grows() {
+        let directory = temp_directory("region_rewrite");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("r.0.0.region");
+
+        let mut region = RegionFile::open(&path).unwrap();
+        region.write_chunk(&IVec2::new(0, 0), &[1; 10]).unwrap();
+        region.write_chunk(&IVec2::new(1, 0), &[2; 10]).unwrap();
+        // Does not fit in its sector anymore, so it has to move to the end of the file
+        region
+            .write_chunk(&IVec2::new(0, 0), &[3; SECTOR_SIZE as usize + 1])
+            .unwrap();
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(
+            region.read_chunk(&IVec2::new(0, 0)).unwrap().unwrap(),
+            vec![3; SECTOR_SIZE as usize + 1]
+        );
+        assert_eq!(
+            region.read_chunk(&IVec2::new(1, 0)).unwrap().unwrap(),
+            vec![2; 10]
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_region_entry_outside_of_file_is_rejected() {
+        let directory = temp_directory("region_bad_entry");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("r.0.0.region");
+
+        let mut region = RegionFile::open(&path).unwrap();
+        region.write_chunk(&IVec2::new(0, 0), &[1; 10]).unwrap();
+
+        let bad_entries = [
+            // Far past the end of the file
+            RegionEntry {
+                offset: HEADER_SECTORS,
+                length: u32::MAX,
+            },
+            // Inside of the offset table
+            RegionEntry {
+                offset: 0,
+                length: 10,
+            },
+        ];
+        for entry in bad_entries {
+            let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+            file.write_all(&entry.to_bytes()).unwrap();
+            drop(file);
+
+            let mut region = RegionFile::open(&path).unwrap();
+            let error = region.read_chunk(&IVec2::new(0, 0)).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
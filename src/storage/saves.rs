@@ -0,0 +1,318 @@
+use crate::storage::level::LevelData;
+use crate::terrain::world_generator::GeneratorSettings;
+use bevy::log::warn;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub const SAVES_DIRECTORY: &str = "saves";
+
+/// Characters that can't be used in a world name, as it is also the name of its directory
+const INVALID_NAME_CHARACTERS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+#[derive(Clone, Debug)]
+pub struct WorldSummary {
+    pub name: String,
+    pub directory: PathBuf,
+    pub last_played: Option<SystemTime>,
+    /// Size of the world on disk, in bytes
+    pub size: u64,
+}
+
+/// Directory holding every singleplayer world, one sub-directory per world
+pub struct Saves {
+    directory: PathBuf,
+}
+
+impl Default for Saves {
+    fn default() -> Self {
+        Self::new(SAVES_DIRECTORY)
+    }
+}
+
+impl Saves {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    pub fn world_directory(&self, name: &str) -> PathBuf {
+        self.directory.join(name)
+    }
+
+    /// Worlds found in the saves directory, most recently played first
+    pub fn list_worlds(&self) -> io::Result<Vec<WorldSummary>> {
+        if !self.directory.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut worlds = Vec::new();
+
+        // A folder that can't be read is left out, without hiding the other worlds
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!("Failed to read an entry of {:?}: {}", self.directory, err);
+                    continue;
+                }
+            };
+            let directory = entry.path();
+
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => {}
+                Ok(_) => continue,
+                Err(err) => {
+                    warn!("Failed to read the type of {:?}: {}", directory, err);
+                    continue;
+                }
+            }
+
+            let size = match directory_size(&directory) {
+                Ok(size) => size,
+                Err(err) => {
+                    warn!("Failed to read the size of world {:?}: {}", directory, err);
+                    continue;
+                }
+            };
+            // Still listed, so that it can be deleted
+            let last_played = match LevelData::load(&directory) {
+                Ok(level) => level.and_then(|level| level.last_played),
+                Err(err) => {
+                    warn!(
+                        "Failed to read the level data of world {:?}: {}",
+                        directory, err
+                    );
+                    None
+                }
+            };
+
+            worlds.push(WorldSummary {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                size,
+                directory,
+                last_played,
+            });
+        }
+
+        worlds.sort_by(|a, b| b.last_played.cmp(&a.last_played).then(a.name.cmp(&b.name)));
+
+        Ok(worlds)
+    }
+
+    /// First free world name, adding a number after `name` if it is already taken
+    pub fn unique_name(&self, name: &str) -> String {
+        let mut candidate = name.to_string();
+        let mut index = 2;
+
+        while self.world_directory(&candidate).exists() {
+            candidate = format!("{} ({})", name, index);
+            index += 1;
+        }
+
+        candidate
+    }
+
+    pub fn create_world(
+        &self,
+        name: &str,
+        seed: i32,
+        generator: GeneratorSettings,
+    ) -> io::Result<PathBuf> {
+        let directory = self.new_world_directory(name)?;
+
+        fs::create_dir_all(&directory)?;
+        LevelData::new(seed, generator).save(&directory)?;
+
+        Ok(directory)
+    }
+
+    pub fn rename_world(&self, name: &str, new_name: &str) -> io::Result<()> {
+        let new_directory = self.new_world_directory(new_name)?;
+
+        fs::rename(self.existing_world_directory(name)?, new_directory)
+    }
+
+    pub fn duplicate_world(&self, name: &str, new_name: &str) -> io::Result<()> {
+        let new_directory = self.new_world_directory(new_name)?;
+
+        copy_directory(&self.existing_world_directory(name)?, &new_directory)
+    }
+
+    pub fn delete_world(&self, name: &str) -> io::Result<()> {
+        fs::remove_dir_all(self.existing_world_directory(name)?)
+    }
+
+    fn existing_world_directory(&self, name: &str) -> io::Result<PathBuf> {
+        validate_name(name)?;
+        let directory = self.world_directory(name);
+
+        if !directory.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("World \"{}\" does not exist", name),
+            ));
+        }
+
+        Ok(directory)
+    }
+
+    fn new_world_directory(&self, name: &str) -> io::Result<PathBuf> {
+        validate_name(name)?;
+        let directory = self.world_directory(name);
+
+        if directory.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("World \"{}\" already exists", name),
+            ));
+        }
+
+        Ok(directory)
+    }
+}
+
+fn validate_name(name: &str) -> io::Result<()> {
+    if name.trim().is_empty()
+        || name != name.trim()
+        || name.starts_with('.')
+        || name
+            .chars()
+            .any(|c| c.is_control() || INVALID_NAME_CHARACTERS.contains(&c))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("\"{}\" is not a valid world name", name),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Seed typed in the create world screen
+///
+/// Numbers are used as is, any other text is hashed, and an empty seed picks a random one
+pub fn parse_seed(input: &str) -> i32 {
+    let input = input.trim();
+
+    if input.is_empty() {
+        return SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .subsec_nanos() as i32;
+    }
+
+    input.parse().unwrap_or_else(|_| {
+        input
+            .chars()
+            .fold(0i32, |hash, c| hash.wrapping_mul(31).wrapping_add(c as i32))
+    })
+}
+
+fn directory_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        size += if metadata.is_dir() {
+            directory_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+
+    Ok(size)
+}
+
+fn copy_directory(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_directory(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::level::LEVEL_FILE_NAME;
+
+    #[test]
+    fn test_parse_seed() {
+        assert_eq!(parse_seed("42"), 42);
+        assert_eq!(parse_seed(" -7 "), -7);
+        assert_eq!(parse_seed("voxel"), parse_seed("voxel"));
+        assert_ne!(parse_seed("voxel"), parse_seed("voxels"));
+    }
+
+    #[test]
+    fn test_world_management() {
+        let directory =
+            std::env::temp_dir().join(format!("voxel_game_saves_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let saves = Saves::new(&directory);
+
+        assert!(saves.list_worlds().unwrap().is_empty());
+
+        let world_directory = saves
+            .create_world("My World", 12, GeneratorSettings::Void)
+            .unwrap();
+        let level = LevelData::load(&world_directory).unwrap().unwrap();
+        assert_eq!(level.seed, 12);
+        assert_eq!(level.generator, GeneratorSettings::Void);
+        assert!(saves
+            .create_world("My World", 12, GeneratorSettings::Default)
+            .is_err());
+        assert!(saves
+            .create_world("../outside", 12, GeneratorSettings::Default)
+            .is_err());
+        assert_eq!(saves.unique_name("My World"), "My World (2)");
+
+        saves.duplicate_world("My World", "Copy").unwrap();
+        saves.rename_world("My World", "Renamed").unwrap();
+
+        let worlds = saves.list_worlds().unwrap();
+        let mut names: Vec<&str> = worlds.iter().map(|world| world.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["Copy", "Renamed"]);
+        assert!(worlds
+            .iter()
+            .all(|world| world.size > 0 && world.last_played.is_none()));
+
+        // The world played last comes first
+        let copy_directory = saves.world_directory("Copy");
+        let mut level = LevelData::load(&copy_directory).unwrap().unwrap();
+        level.last_played = Some(SystemTime::now());
+        level.save(&copy_directory).unwrap();
+        let worlds = saves.list_worlds().unwrap();
+        assert_eq!(worlds[0].name, "Copy");
+        assert_eq!(worlds[0].last_played, level.last_played);
+
+        // Neither a stray file nor a broken world hides the others
+        fs::write(directory.join("notes.txt"), "not a world").unwrap();
+        fs::write(copy_directory.join(LEVEL_FILE_NAME), "not a level").unwrap();
+        let worlds = saves.list_worlds().unwrap();
+        assert_eq!(worlds.len(), 2);
+        assert!(worlds.iter().all(|world| world.last_played.is_none()));
+        fs::remove_file(directory.join("notes.txt")).unwrap();
+
+        saves.delete_world("Copy").unwrap();
+        assert!(saves.delete_world("Copy").is_err());
+        assert_eq!(saves.list_worlds().unwrap().len(), 1);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+use crate::storage::level::LevelData;
+use crate::storage::saves::parse_seed;
+use crate::storage::WorldStorage;
+use crate::terrain::world_generator::GeneratorSettings;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const SERVER_SETTINGS_FILE: &str = "server.ron";
+
+/// Settings of the dedicated server, read from `server.ron` next to it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerSettings {
+    pub world_directory: PathBuf,
+    /// Seed of the world created when `world_directory` has none, as read by [`parse_seed`]
+    pub seed: String,
+    /// Generator of the world created when `world_directory` has none
+    pub generator: GeneratorSettings,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            world_directory: PathBuf::from("world"),
+            seed: String::new(),
+            generator: GeneratorSettings::Default,
+        }
+    }
+}
+
+impl ServerSettings {
+    /// Read the settings file, writing the default settings to it if there is none yet
+    pub fn load(path: &Path) -> io::Result<Self> {
+        if !path.exists() {
+            let settings = Self::default();
+            settings.save(path)?;
+            return Ok(settings);
+        }
+
+        ron::from_str(&fs::read_to_string(path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let source = ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+        fs::write(path, source)
+    }
+
+    /// Storage of the world to run, creating the world with the seed and generator of the
+    /// settings if it doesn't exist yet
+    pub fn open_world(&self) -> io::Result<WorldStorage> {
+        if LevelData::load(&self.world_directory)?.is_none() {
+            println!(
+                "Creating world {:?} with generator {}",
+                self.world_directory, self.generator
+            );
+            LevelData::new(parse_seed(&self.seed), self.generator.clone())
+                .save(&self.world_directory)?;
+        }
+
+        Ok(WorldStorage::new(&self.world_directory))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::world_generator::FlatLayer;
+
+    #[test]
+    fn test_server_settings() {
+        let directory =
+            std::env::temp_dir().join(format!("voxel_game_server_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(SERVER_SETTINGS_FILE);
+
+        assert_eq!(
+            ServerSettings::load(&path).unwrap(),
+            ServerSettings::default()
+        );
+        assert!(path.exists());
+
+        fs::write(
+            &path,
+            "(seed: \"42\", generator: Superflat(layers: [(block: \"stone\", height: 3)]))",
+        )
+        .unwrap();
+        let mut settings = ServerSettings::load(&path).unwrap();
+        assert_eq!(settings.world_directory, PathBuf::from("world"));
+        assert_eq!(
+            settings.generator,
+            GeneratorSettings::Superflat {
+                layers: vec![FlatLayer::new("stone", 3)]
+            }
+        );
+
+        settings.world_directory = directory.join("world");
+        settings.open_world().unwrap();
+        let level = LevelData::load(&settings.world_directory).unwrap().unwrap();
+        assert_eq!(level.seed, 42);
+        assert_eq!(level.generator, settings.generator);
+
+        // An existing world keeps its own seed and generator
+        settings.seed = "7".to_string();
+        settings.generator = GeneratorSettings::Void;
+        settings.open_world().unwrap();
+        let level = LevelData::load(&settings.world_directory).unwrap().unwrap();
+        assert_eq!(level.seed, 42);
+        assert_ne!(level.generator, GeneratorSettings::Void);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
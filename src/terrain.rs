@@ -1,3 +1,13 @@
+pub mod biome;
+pub mod caves;
 pub mod chunk_generation;
+pub mod decoration;
+pub mod generators;
+pub mod image_terrain;
 pub mod meshing;
-pub mod terrain_generator;
+pub mod noise;
+pub mod ores;
+pub mod pipeline;
+pub mod random;
+pub mod world_generator;
+pub mod worldgen_data;
@@ -0,0 +1,235 @@
+//! Biomes picked from temperature and humidity noise maps, defined in `assets/worldgen.ron`
+
+use crate::terrain::decoration::{Decoration, DecorationDefinition};
+use crate::terrain::noise::NoiseMap;
+use crate::terrain::worldgen_data::{find_block, WorldgenData};
+use crate::voxel::block::BlockType;
+use crate::voxel::block_registry::BlockRegistry;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+
+const CLIMATE_FREQUENCY: f32 = 0.0015;
+const TEMPERATURE_SALT: u64 = 1;
+const HUMIDITY_SALT: u64 = 2;
+/// Distance in the climate space over which two neighbor biomes are blended
+const BIOME_BLEND_DISTANCE: f32 = 0.2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Mountains,
+    Tundra,
+    Ocean,
+}
+
+impl fmt::Display for Biome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Top block used instead of the surface of a biome above a height, like snow on mountains
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HighSurface {
+    pub above: i32,
+    pub block: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BiomeDefinition {
+    pub biome: Biome,
+    pub temperature: f32,
+    pub humidity: f32,
+    /// Average height of the surface
+    pub height: f32,
+    /// Height of the hills above and below the average height
+    pub hills: f32,
+    /// Height of the small bumps on the hills
+    pub roughness: f32,
+    pub surface: String,
+    pub filler: String,
+    /// Blocks of filler below the surface, before reaching stone
+    pub filler_depth: u32,
+    #[serde(default)]
+    pub high_surface: Option<HighSurface>,
+    #[serde(default)]
+    pub decorations: Vec<DecorationDefinition>,
+}
+
+/// A [`BiomeDefinition`] with its blocks resolved
+pub struct BiomeBlocks {
+    pub definition: BiomeDefinition,
+    pub surface: BlockType,
+    pub filler: BlockType,
+    pub high_surface: Option<(i32, BlockType)>,
+    pub decorations: Vec<Decoration>,
+}
+
+impl BiomeBlocks {
+    fn new(definition: &BiomeDefinition, registry: &BlockRegistry) -> io::Result<Self> {
+        Ok(Self {
+            surface: find_block(registry, &definition.surface)?,
+            filler: find_block(registry, &definition.filler)?,
+            high_surface: definition
+                .high_surface
+                .as_ref()
+                .map(|high| Ok::<_, io::Error>((high.above, find_block(registry, &high.block)?)))
+                .transpose()?,
+            decorations: definition
+                .decorations
+                .iter()
+                .map(|decoration| Decoration::new(decoration, registry))
+                .collect::<io::Result<_>>()?,
+            definition: definition.clone(),
+        })
+    }
+
+    /// Top block of a column whose surface is at `height`
+    pub fn surface_at(&self, height: i32) -> BlockType {
+        match self.high_surface {
+            Some((above, block)) if height > above => block,
+            _ => self.surface,
+        }
+    }
+}
+
+/// How much each biome shapes a column, the closest biome giving its blocks
+pub struct BiomeWeights {
+    /// Index of the closest biome
+    pub closest: usize,
+    /// Index and weight of every biome shaping the column, the weights adding up to 1
+    pub weights: Vec<(usize, f32)>,
+}
+
+/// Picks the biome of every column of a world
+pub struct BiomeSource {
+    temperature: NoiseMap,
+    humidity: NoiseMap,
+    biomes: Vec<BiomeBlocks>,
+}
+
+impl BiomeSource {
+    pub fn new(seed: i32, data: &WorldgenData, registry: &BlockRegistry) -> io::Result<Self> {
+        Ok(Self {
+            temperature: NoiseMap::new(seed, TEMPERATURE_SALT, CLIMATE_FREQUENCY),
+            humidity: NoiseMap::new(seed, HUMIDITY_SALT, CLIMATE_FREQUENCY),
+            biomes: data
+                .biomes
+                .iter()
+                .map(|definition| BiomeBlocks::new(definition, registry))
+                .collect::<io::Result<_>>()?,
+        })
+    }
+
+    pub fn biome(&self, index: usize) -> &BiomeBlocks {
+        &self.biomes[index]
+    }
+
+    pub fn biomes(&self) -> &[BiomeBlocks] {
+        &self.biomes
+    }
+
+    /// Blocks of a biome of this source
+    pub fn blocks(&self, biome: Biome) -> &BiomeBlocks {
+        self.biomes
+            .iter()
+            .find(|blocks| blocks.definition.biome == biome)
+            .unwrap()
+    }
+
+    fn weights(&self, temperature: f32, humidity: f32) -> BiomeWeights {
+        let distances: Vec<f32> = self
+            .biomes
+            .iter()
+            .map(|biome| {
+                (biome.definition.temperature - temperature)
+                    .hypot(biome.definition.humidity - humidity)
+            })
+            .collect();
+        let (closest, closest_distance) = distances
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+
+        // Biomes about as close as the closest one get some weight, going down to 0 at the
+        // blend distance so that the height stays continuous when the closest biome changes
+        let mut weights: Vec<(usize, f32)> = distances
+            .iter()
+            .enumerate()
+            .filter_map(|(i, distance)| {
+                let weight = (1.0 - (distance - closest_distance) / BIOME_BLEND_DISTANCE).max(0.0);
+                (weight > 0.0).then_some((i, weight * weight))
+            })
+            .collect();
+        let total: f32 = weights.iter().map(|(_, weight)| weight).sum();
+        weights.iter_mut().for_each(|(_, weight)| *weight /= total);
+
+        BiomeWeights { closest, weights }
+    }
+
+    /// Biome weights of a square of columns starting at (`x`, `z`), indexed by `z * width + x`
+    pub fn square_weights(&self, x: i32, z: i32, width: usize) -> Vec<BiomeWeights> {
+        self.temperature
+            .square(x, z, width)
+            .into_iter()
+            .zip(self.humidity.square(x, z, width))
+            .map(|(temperature, humidity)| self.weights(temperature, humidity))
+            .collect()
+    }
+
+    pub fn column_weights(&self, x: i32, z: i32) -> BiomeWeights {
+        self.weights(self.temperature.get(x, z), self.humidity.get(x, z))
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        self.biomes[self.column_weights(x, z).closest]
+            .definition
+            .biome
+    }
+
+    /// Value of a column blended from each biome shaping it
+    pub fn blend(&self, weights: &BiomeWeights, value: impl Fn(&BiomeDefinition) -> f32) -> f32 {
+        weights
+            .weights
+            .iter()
+            .map(|(i, weight)| value(&self.biomes[*i].definition) * weight)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::chunk::CHUNK_SIZE;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_every_biome_is_found() {
+        let source =
+            BiomeSource::new(0, &WorldgenData::default(), &BlockRegistry::default()).unwrap();
+
+        let biomes: HashSet<Biome> = (-32..32)
+            .flat_map(|x| (-32..32).map(move |z| (x * 128, z * 128)))
+            .map(|(x, z)| source.biome_at(x, z))
+            .collect();
+        assert_eq!(biomes.len(), 5, "only found {:?}", biomes);
+    }
+
+    #[test]
+    fn test_square_weights_match_columns() {
+        let source =
+            BiomeSource::new(5, &WorldgenData::default(), &BlockRegistry::default()).unwrap();
+
+        let chunk_weights = source.square_weights(-48, 16, CHUNK_SIZE as usize);
+        for (x, z) in [(0, 0), (5, 9), (15, 15)] {
+            let column = source.column_weights(-48 + x, 16 + z);
+            let in_chunk = &chunk_weights[(z * CHUNK_SIZE + x) as usize];
+            assert_eq!(column.closest, in_chunk.closest);
+            assert_eq!(column.weights, in_chunk.weights);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Caves and ravines carved out of the terrain
+//!
+//! Whether a block is carved only depends on its world position and the surface height of its
+//! column, so caves line up across chunk borders whatever order the chunks are generated in.
+
+use crate::terrain::noise::{NoiseMap, NoiseVolume};
+use crate::voxel::block::Block;
+use crate::voxel::chunk::{Chunk, CHUNK_HEIGHT, CHUNK_SIZE, SECTION_SIZE};
+use bevy::math::IVec3;
+use serde::{Deserialize, Serialize};
+
+const CHEESE_SALT: u64 = 10;
+const SPAGHETTI_SALTS: [u64; 2] = [11, 12];
+const RAVINE_SALT: u64 = 13;
+const RAVINE_MASK_SALT: u64 = 14;
+/// Ravines only appear in a few large areas
+const RAVINE_MASK_FREQUENCY: f32 = 0.002;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CaveSettings {
+    /// Large caverns where a 3D noise is above the threshold
+    pub cheese_frequency: f32,
+    pub cheese_threshold: f32,
+    /// Winding tunnels where two 3D noises are both close to 0
+    pub spaghetti_frequency: f32,
+    pub spaghetti_radius: f32,
+    /// Narrow cracks opening on the surface, along the lines where a 2D noise is close to 0
+    pub ravine_frequency: f32,
+    /// Half width of the ravines at the surface, in noise units
+    pub ravine_width: f32,
+    pub ravine_depth: i32,
+    /// Part of the world with ravines, from 0 (none) to 1 (everywhere)
+    pub ravine_coverage: f32,
+    /// Caverns stop this many blocks below the surface, only tunnels and ravines open on it.
+    /// Nothing opens on the ground under the sea.
+    pub surface_margin: i32,
+}
+
+/// Noise of a column used to carve the ravines
+#[derive(Copy, Clone)]
+struct RavineNoise {
+    ravine: f32,
+    mask: f32,
+}
+
+pub struct CaveCarver {
+    settings: CaveSettings,
+    sea_level: i32,
+    cheese: NoiseVolume,
+    spaghetti: [NoiseVolume; 2],
+    ravines: NoiseMap,
+    ravine_mask: NoiseMap,
+}
+
+impl CaveCarver {
+    pub fn new(seed: i32, settings: &CaveSettings, sea_level: i32) -> Self {
+        Self {
+            settings: settings.clone(),
+            sea_level,
+            cheese: NoiseVolume::new(seed, CHEESE_SALT, settings.cheese_frequency),
+            spaghetti: SPAGHETTI_SALTS
+                .map(|salt| NoiseVolume::new(seed, salt, settings.spaghetti_frequency)),
+            ravines: NoiseMap::new(seed, RAVINE_SALT, settings.ravine_frequency),
+            ravine_mask: NoiseMap::new(seed, RAVINE_MASK_SALT, RAVINE_MASK_FREQUENCY),
+        }
+    }
+
+    fn is_carved(
+        &self,
+        y: i32,
+        height: i32,
+        cheese: f32,
+        spaghetti: [f32; 2],
+        ravine: RavineNoise,
+    ) -> bool {
+        if y >= height {
+            return false;
+        }
+
+        let depth = height - 1 - y;
+        let settings = &self.settings;
+
+        // The sea doesn't flow, keep it from floating above the caves
+        if height < self.sea_level && depth < settings.surface_margin {
+            return false;
+        }
+
+        if depth < settings.ravine_depth && ravine.mask > 1.0 - 2.0 * settings.ravine_coverage {
+            // Narrower as it goes down
+            let width = settings.ravine_width * (1.0 - depth as f32 / settings.ravine_depth as f32);
+            if ravine.ravine.abs() < width {
+                return true;
+            }
+        }
+
+        if spaghetti[0].powi(2) + spaghetti[1].powi(2) < settings.spaghetti_radius.powi(2) {
+            return true;
+        }
+
+        depth >= settings.surface_margin && cheese > settings.cheese_threshold
+    }
+
+    /// Whether the block at a world position is carved out, `height` being the height of the
+    /// first block above the ground of its column
+    pub fn is_carved_at(&self, pos: IVec3, height: i32) -> bool {
+        self.is_carved(
+            pos.y,
+            height,
+            self.cheese.get(pos),
+            self.spaghetti.each_ref().map(|noise| noise.get(pos)),
+            RavineNoise {
+                ravine: self.ravines.get(pos.x, pos.z),
+                mask: self.ravine_mask.get(pos.x, pos.z),
+            },
+        )
+    }
+
+    /// Carve the caves out of a chunk, `heights` being the height of each of its columns as
+    /// indexed by `z * CHUNK_SIZE + x`
+    pub fn carve(&self, chunk: &mut Chunk, heights: &[i32]) {
+        let chunk_world_pos = chunk.pos * IVec3::new(CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE);
+        let width = CHUNK_SIZE as usize;
+        let ravines: Vec<RavineNoise> = self
+            .ravines
+            .square(chunk_world_pos.x, chunk_world_pos.z, width)
+            .into_iter()
+            .zip(
+                self.ravine_mask
+                    .square(chunk_world_pos.x, chunk_world_pos.z, width),
+            )
+            .map(|(ravine, mask)| RavineNoise { ravine, mask })
+            .collect();
+        let max_height = heights.iter().copied().max().unwrap_or(i32::MIN);
+
+        // Sections are as wide as they are high, so each one is a single noise cube
+        for section_y in (0..CHUNK_HEIGHT).step_by(SECTION_SIZE as usize) {
+            let section_pos = chunk_world_pos + IVec3::new(0, section_y, 0);
+            if section_pos.y >= max_height {
+                break;
+            }
+
+            let cheese = self.cheese.cube(section_pos, width);
+            let spaghetti = self
+                .spaghetti
+                .each_ref()
+                .map(|noise| noise.cube(section_pos, width));
+
+            for z in 0..CHUNK_SIZE {
+                for y in 0..SECTION_SIZE {
+                    for x in 0..CHUNK_SIZE {
+                        let column = (z * CHUNK_SIZE + x) as usize;
+                        let i = ((z * SECTION_SIZE + y) * CHUNK_SIZE + x) as usize;
+
+                        if self.is_carved(
+                            section_pos.y + y,
+                            heights[column],
+                            cheese[i],
+                            [spaghetti[0][i], spaghetti[1][i]],
+                            ravines[column],
+                        ) {
+                            chunk.set_voxel(&IVec3::new(x, section_y + y, z), Block::new_empty());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::generators::NoiseGenerator;
+    use crate::terrain::pipeline::generate_chunk;
+    use crate::terrain::world_generator::WorldGenerator;
+    use crate::terrain::worldgen_data::WorldgenData;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::thread;
+
+    const SEED: i32 = 42;
+
+    fn generator() -> NoiseGenerator {
+        NoiseGenerator::new(SEED, &WorldgenData::default(), &Arc::default()).unwrap()
+    }
+
+    fn generate(generator: &NoiseGenerator, pos: IVec3) -> Chunk {
+        generate_chunk(generator, pos)
+    }
+
+    #[test]
+    fn test_caves_do_not_depend_on_generation_order() {
+        let positions: Vec<IVec3> = (-1..=1)
+            .flat_map(|x| (-1..=1).map(move |z| IVec3::new(x, 0, z)))
+            .collect();
+
+        // Reference: one generator, one chunk after the other
+        let generator = generator();
+        let golden: HashMap<IVec3, Chunk> = positions
+            .iter()
+            .map(|pos| (*pos, generate(&generator, *pos)))
+            .collect();
+
+        // Other generators, in reverse order and all at once
+        let reversed: Vec<Chunk> = {
+            let generator = self::generator();
+            positions
+                .iter()
+                .rev()
+                .map(|pos| generate(&generator, *pos))
+                .collect()
+        };
+        let parallel: Vec<Chunk> = thread::scope(|scope| {
+            let handles: Vec<_> = positions
+                .iter()
+                .map(|pos| scope.spawn(move || generate(&self::generator(), *pos)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        let mut carved = 0;
+        let mut carved_on_borders = 0;
+        let mut underground = 0;
+
+        for chunk in reversed.iter().chain(&parallel) {
+            let expected = &golden[&chunk.pos];
+
+            for x in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    for y in 0..CHUNK_HEIGHT {
+                        let local = IVec3::new(x, y, z);
+                        assert_eq!(
+                            chunk.voxel_at(&local),
+                            expected.voxel_at(&local),
+                            "chunk {:?} differs at {:?}",
+                            chunk.pos,
+                            local
+                        );
+                    }
+                }
+            }
+        }
+
+        // Carving only depends on the world position, even right at the chunk borders
+        for (pos, chunk) in &golden {
+            for x in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let world_x = pos.x * CHUNK_SIZE + x;
+                    let world_z = pos.z * CHUNK_SIZE + z;
+                    let height = generator.surface_height(world_x, world_z).unwrap();
+                    let on_border = x == 0 || x == CHUNK_SIZE - 1 || z == 0 || z == CHUNK_SIZE - 1;
+
+                    for y in 0..height.min(CHUNK_HEIGHT) {
+                        let is_air = chunk.voxel_at(&IVec3::new(x, y, z)).is_air();
+                        let world_pos = IVec3::new(world_x, y, world_z);
+
+                        if on_border {
+                            assert_eq!(
+                                is_air,
+                                generator.caves().is_carved_at(world_pos, height),
+                                "carving differs at {:?}",
+                                world_pos
+                            );
+                        }
+
+                        underground += 1;
+                        if is_air {
+                            carved += 1;
+                            carved_on_borders += on_border as i32;
+                        }
+                    }
+                }
+            }
+        }
+
+        assert!(carved_on_borders > 0);
+        let carved_part = carved as f32 / underground as f32;
+        assert!(
+            (0.01..0.3).contains(&carved_part),
+            "{:.1}% of the underground is carved",
+            carved_part * 100.0
+        );
+    }
+}
@@ -1,6 +1,10 @@
-use crate::terrain::terrain_generator::TERRAIN_GENERATOR;
+use crate::storage::WorldStorage;
+use crate::terrain::pipeline::{ChunkPipeline, StageJob};
+use crate::terrain::world_generator::GameWorldGenerator;
+use crate::voxel::block_registry::{BlockRegistry, GameBlockRegistry};
 use crate::voxel::chunk::{Chunk, ServerChunkEntity};
-use crate::voxel::world::GameWorld;
+use crate::voxel::chunk_tracker::ChunkTracker;
+use crate::voxel::world::{GameWorld, World};
 use crate::{Channel, ServerMessage};
 use bevy::prelude::*;
 use bevy::tasks::{AsyncComputeTaskPool, Task};
@@ -9,107 +13,182 @@ use bincode::config;
 use futures_lite::future;
 use std::sync::{Arc, RwLock};
 
+/// Resolves to the chunk if it was saved, otherwise it is generated
 #[derive(Component)]
-pub struct TerrainGenTask(Task<Arc<RwLock<Chunk>>>);
+pub struct ChunkLoadTask(Task<Option<Chunk>>);
 
+/// Chunks being generated and the stages running on them
+#[derive(Resource, Default)]
+pub struct ServerChunkPipeline {
+    pub pipeline: ChunkPipeline,
+    tasks: Vec<Task<StageJob>>,
+}
+
+impl ServerChunkPipeline {
+    /// Whether every chunk to generate is complete
+    pub fn is_idle(&self) -> bool {
+        self.pipeline.is_idle()
+    }
+}
+
+/// Start loading the chunks of the new [`ServerChunkEntity`]s
+///
+/// A chunk keeps its entity until it is evicted, a second entity spawned for it is despawned
+/// right away.
 pub fn queue_chunk_generation(
     mut commands: Commands,
     new_chunks: Query<(Entity, &ServerChunkEntity), Added<ServerChunkEntity>>,
+    storage: Res<WorldStorage>,
+    block_registry: Res<GameBlockRegistry>,
+    mut tracker: ResMut<ChunkTracker>,
 ) {
-    new_chunks
-        .iter()
-        .map(|(entity, key)| (entity, key.0))
-        .map(|(entity, chunk_coord)| {
-            (
-                entity,
-                (TerrainGenTask(AsyncComputeTaskPool::get().spawn(async move {
-                    let mut chunk: Chunk = Chunk {
-                        pos: chunk_coord,
-                        ..Default::default()
-                    };
-                    TERRAIN_GENERATOR
-                        .read()
-                        .unwrap()
-                        .generate(chunk_coord, &mut chunk.voxels);
-
-                    Arc::new(RwLock::new(chunk))
-                }))),
-            )
-        })
-        .for_each(|(entity, gen_task)| {
-            commands.entity(entity).insert(gen_task);
+    for (entity, chunk_entity) in new_chunks.iter() {
+        let chunk_coord = chunk_entity.0;
+
+        if tracker.entity(&chunk_coord).is_some() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        tracker.set_entity(chunk_coord, entity);
+
+        let regions = Arc::clone(&storage.regions);
+        let registry = Arc::clone(&block_registry.registry);
+
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            regions
+                .load_chunk(&chunk_coord, &registry)
+                .unwrap_or_else(|err| {
+                    error!("Failed to load chunk {:?}: {}", chunk_coord, err);
+                    None
+                })
         });
+        commands.entity(entity).insert(ChunkLoadTask(task));
+    }
 }
 
-pub fn process_chunk_generation(
+/// Add the chunks found on disk to the world, and send the other ones through the pipeline
+pub fn process_chunk_loading(
     game_world: Res<GameWorld>,
     mut commands: Commands,
-    mut gen_chunks: Query<(Entity, &ServerChunkEntity, &mut TerrainGenTask)>,
+    mut load_tasks: Query<(Entity, &ServerChunkEntity, &mut ChunkLoadTask)>,
+    mut pipeline: ResMut<ServerChunkPipeline>,
+    block_registry: Res<GameBlockRegistry>,
+    mut server: ResMut<RenetServer>,
+) {
+    for (entity, chunk_entity, mut task) in load_tasks.iter_mut() {
+        let Some(chunk) = future::block_on(future::poll_once(&mut task.0)) else {
+            continue;
+        };
+
+        match chunk {
+            Some(chunk) => add_chunk(
+                &game_world.world.read().unwrap(),
+                &mut server,
+                chunk,
+                false,
+                &block_registry.registry,
+            ),
+            None => pipeline.pipeline.request(chunk_entity.0),
+        }
+
+        commands.entity(entity).remove::<ChunkLoadTask>();
+    }
+}
+
+/// Collect the finished generation stages and start the ones that became ready
+pub fn run_generation_stages(
+    game_world: Res<GameWorld>,
+    mut pipeline: ResMut<ServerChunkPipeline>,
+    world_generator: Res<GameWorldGenerator>,
+    block_registry: Res<GameBlockRegistry>,
     mut server: ResMut<RenetServer>,
 ) {
-    gen_chunks
-        .iter_mut()
-        .for_each(|(entity, chunk_entity, mut gen_task)| {
-            if let Some(chunk) = future::block_on(future::poll_once(&mut gen_task.0)) {
-                let neighbors = game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .get_neighbors_chunks(&chunk_entity.0);
-
-                for i in 0..neighbors.len() {
-                    let neighbor = neighbors.get(i).unwrap();
-                    if let Some(ref neighbor) = neighbor {
-                        chunk.write().unwrap().set_neighbor(i, neighbor.clone());
-
-                        let neighbor = neighbor.upgrade().unwrap();
-                        let mut neighbor = neighbor.write().unwrap();
-                        // i ^ 1 is the opposite direction of i (i.e. 0 ^ 1 = 1, 1 ^ 1 = 0, 2 ^ 1 = 3, 3 ^ 1 = 2)
-                        neighbor.set_neighbor(i ^ 1, Arc::downgrade(&chunk));
-
-                        game_world
-                            .world
-                            .read()
-                            .unwrap()
-                            .dirty_chunks
-                            .write()
-                            .unwrap()
-                            .insert(neighbor.pos);
-                    }
-                }
-
-                let players_waiting_for_chunk = game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .pending_generating_chunks
-                    .write()
-                    .unwrap()
-                    .remove(&chunk_entity.0);
-
-                if let Some(players_waiting_for_chunk) = players_waiting_for_chunk {
-                    for client_id in players_waiting_for_chunk.iter() {
-                        let message = bincode::serde::encode_to_vec(
-                            ServerMessage::Chunk(chunk_entity.0, chunk.read().unwrap().compress()),
-                            config::standard(),
-                        )
-                        .unwrap();
-                        server.send_message(*client_id, Channel::Chunk, message);
-                    }
-                }
-
-                game_world
-                    .world
-                    .read()
-                    .unwrap()
-                    .chunk_data_map
-                    .write()
-                    .unwrap()
-                    .insert(chunk_entity.0, chunk);
-
-                commands.entity(entity).remove::<TerrainGenTask>();
+    let pipeline = &mut *pipeline;
+    let mut finished = Vec::new();
+    pipeline
+        .tasks
+        .retain_mut(|task| match future::block_on(future::poll_once(task)) {
+            Some(job) => {
+                finished.push(job);
+                false
             }
-        })
+            None => true,
+        });
+
+    for job in finished {
+        if let Some(chunk) = pipeline.pipeline.finish(job) {
+            add_chunk(
+                &game_world.world.read().unwrap(),
+                &mut server,
+                chunk,
+                true,
+                &block_registry.registry,
+            );
+        }
+    }
+
+    for job in pipeline.pipeline.ready_jobs() {
+        let generator = Arc::clone(&world_generator.generator);
+        pipeline
+            .tasks
+            .push(AsyncComputeTaskPool::get().spawn(async move { job.run(generator.as_ref()) }));
+    }
+}
+
+/// Link a complete chunk to its neighbors and send it to the players waiting for it
+fn add_chunk(
+    world: &World,
+    server: &mut RenetServer,
+    chunk: Chunk,
+    generated: bool,
+    registry: &BlockRegistry,
+) {
+    let chunk_coord = chunk.pos;
+    let chunk = Arc::new(RwLock::new(chunk));
+    let neighbors = world.get_neighbors_chunks(&chunk_coord);
+
+    for i in 0..neighbors.len() {
+        let neighbor = neighbors.get(i).unwrap();
+        if let Some(ref neighbor) = neighbor {
+            chunk.write().unwrap().set_neighbor(i, neighbor.clone());
+
+            let neighbor = neighbor.upgrade().unwrap();
+            let mut neighbor = neighbor.write().unwrap();
+            // i ^ 1 is the opposite direction of i: left and right (0 and 1), back and front
+            // (2 and 3), below and above (4 and 5)
+            neighbor.set_neighbor(i ^ 1, Arc::downgrade(&chunk));
+
+            world.mark_chunk_dirty(&neighbor.pos);
+        }
+    }
+
+    let players_waiting_for_chunk = world
+        .pending_generating_chunks
+        .write()
+        .unwrap()
+        .remove(&chunk_coord);
+
+    if let Some(players_waiting_for_chunk) = players_waiting_for_chunk {
+        for client_id in players_waiting_for_chunk.iter() {
+            let message = bincode::serde::encode_to_vec(
+                ServerMessage::Chunk(chunk_coord, chunk.read().unwrap().compress()),
+                config::standard(),
+            )
+            .unwrap();
+            server.send_message(*client_id, Channel::Chunk, message);
+        }
+    }
+
+    world
+        .chunk_data_map
+        .write()
+        .unwrap()
+        .insert(chunk_coord, chunk);
+    world.spread_light_between(&chunk_coord, registry);
+
+    if generated {
+        world.unsaved_chunks.write().unwrap().insert(chunk_coord);
+    }
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
@@ -0,0 +1,373 @@
+//! Trees, boulders and small structures placed on the surface of the biomes
+//!
+//! Features can reach into the chunks around the column they are anchored in. Rather than
+//! keeping aside the blocks landing in chunks that are not generated yet, each chunk also places
+//! the features anchored in the columns around it, only keeping their blocks inside itself. The
+//! anchors and shapes only depend on the world seed and the noise, so whichever chunk is
+//! generated first, a feature is whole once every chunk it touches is generated.
+
+use crate::terrain::random::WorldRandom;
+use crate::terrain::worldgen_data::find_block;
+use crate::voxel::block::{Block, BlockType};
+use crate::voxel::block_registry::BlockRegistry;
+use crate::voxel::chunk::{Chunk, CHUNK_HEIGHT, CHUNK_SIZE};
+use bevy::math::IVec3;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StructureBlock {
+    /// Position from the first air block above the surface
+    pub offset: (i32, i32, i32),
+    pub block: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FeatureDefinition {
+    /// Column of a block, like a cactus
+    Column {
+        block: String,
+        min_height: u32,
+        max_height: u32,
+    },
+    /// Trunk topped with leaves spreading `radius` blocks around it
+    Tree {
+        trunk: String,
+        leaves: String,
+        min_height: u32,
+        max_height: u32,
+        radius: u32,
+    },
+    Boulder {
+        block: String,
+        min_radius: u32,
+        max_radius: u32,
+    },
+    /// Fixed set of blocks
+    Structure { blocks: Vec<StructureBlock> },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DecorationDefinition {
+    pub feature: FeatureDefinition,
+    /// Chance of each column of the biome to get the feature
+    pub chance: f32,
+}
+
+impl DecorationDefinition {
+    /// Name of the feature, for error messages
+    pub fn name(&self) -> &'static str {
+        match self.feature {
+            FeatureDefinition::Column { .. } => "column",
+            FeatureDefinition::Tree { .. } => "tree",
+            FeatureDefinition::Boulder { .. } => "boulder",
+            FeatureDefinition::Structure { .. } => "structure",
+        }
+    }
+
+    /// Whether every minimum is below its maximum
+    pub fn is_valid(&self) -> bool {
+        match self.feature {
+            FeatureDefinition::Column {
+                min_height,
+                max_height,
+                ..
+            }
+            | FeatureDefinition::Tree {
+                min_height,
+                max_height,
+                ..
+            } => min_height <= max_height,
+            FeatureDefinition::Boulder {
+                min_radius,
+                max_radius,
+                ..
+            } => min_radius <= max_radius,
+            FeatureDefinition::Structure { .. } => true,
+        }
+    }
+}
+
+/// A [`FeatureDefinition`] with its blocks resolved
+enum Feature {
+    Column {
+        block: BlockType,
+        min_height: i32,
+        max_height: i32,
+    },
+    Tree {
+        trunk: BlockType,
+        leaves: BlockType,
+        min_height: i32,
+        max_height: i32,
+        radius: i32,
+    },
+    Boulder {
+        block: BlockType,
+        min_radius: i32,
+        max_radius: i32,
+    },
+    Structure {
+        blocks: Vec<(IVec3, BlockType)>,
+    },
+}
+
+/// Part of a feature inside the chunk being generated
+struct ChunkPart<'a> {
+    chunk: &'a mut Chunk,
+    chunk_world_pos: IVec3,
+}
+
+impl ChunkPart<'_> {
+    /// Set a block if it is inside the chunk and one of `replaced` or air
+    fn set(&mut self, pos: IVec3, block: BlockType, replaced: &[BlockType]) {
+        let local = pos - self.chunk_world_pos;
+
+        if Chunk::is_in_chunk(&local) {
+            let current = self.chunk.voxel_at(&local);
+            if current.is_air() || replaced.contains(&current.voxel_type) {
+                self.chunk.set_voxel(&local, Block::new(block));
+            }
+        }
+    }
+}
+
+pub struct Decoration {
+    feature: Feature,
+    pub chance: f32,
+}
+
+impl Decoration {
+    pub fn new(definition: &DecorationDefinition, registry: &BlockRegistry) -> io::Result<Self> {
+        let feature = match &definition.feature {
+            FeatureDefinition::Column {
+                block,
+                min_height,
+                max_height,
+            } => Feature::Column {
+                block: find_block(registry, block)?,
+                min_height: *min_height as i32,
+                max_height: *max_height as i32,
+            },
+            FeatureDefinition::Tree {
+                trunk,
+                leaves,
+                min_height,
+                max_height,
+                radius,
+            } => Feature::Tree {
+                trunk: find_block(registry, trunk)?,
+                leaves: find_block(registry, leaves)?,
+                min_height: *min_height as i32,
+                max_height: *max_height as i32,
+                radius: *radius as i32,
+            },
+            FeatureDefinition::Boulder {
+                block,
+                min_radius,
+                max_radius,
+            } => Feature::Boulder {
+                block: find_block(registry, block)?,
+                min_radius: *min_radius as i32,
+                max_radius: *max_radius as i32,
+            },
+            FeatureDefinition::Structure { blocks } => Feature::Structure {
+                blocks: blocks
+                    .iter()
+                    .map(|block| {
+                        let (x, y, z) = block.offset;
+                        Ok((IVec3::new(x, y, z), find_block(registry, &block.block)?))
+                    })
+                    .collect::<io::Result<_>>()?,
+            },
+        };
+
+        Ok(Self {
+            feature,
+            chance: definition.chance,
+        })
+    }
+
+    /// Farthest a feature goes horizontally from the column it is anchored in
+    pub fn reach(&self) -> i32 {
+        match &self.feature {
+            Feature::Column { .. } => 0,
+            Feature::Tree { radius, .. } => *radius,
+            Feature::Boulder { max_radius, .. } => *max_radius,
+            Feature::Structure { blocks } => blocks
+                .iter()
+                .map(|(offset, _)| offset.x.abs().max(offset.z.abs()))
+                .max()
+                .unwrap_or(0),
+        }
+    }
+
+    /// Place the blocks of the feature anchored on the first air block above a surface that fall
+    /// inside the chunk
+    ///
+    /// Every random number is drawn whether or not its block is inside the chunk, so that each
+    /// chunk places the same feature.
+    pub fn place(&self, chunk: &mut Chunk, anchor: IVec3, random: &mut WorldRandom) {
+        let mut part = ChunkPart {
+            chunk_world_pos: chunk.pos * IVec3::new(CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE),
+            chunk,
+        };
+
+        match self.feature {
+            Feature::Column {
+                block,
+                min_height,
+                max_height,
+            } => {
+                for y in 0..random.range(min_height, max_height) {
+                    part.set(anchor + IVec3::Y * y, block, &[]);
+                }
+            }
+            Feature::Tree {
+                trunk,
+                leaves,
+                min_height,
+                max_height,
+                radius,
+            } => {
+                let top = anchor.y + random.range(min_height, max_height);
+
+                for y in top - 3..=top {
+                    // Smaller at the top of the tree
+                    let radius = if y >= top - 1 { radius - 1 } else { radius };
+
+                    for x in -radius..=radius {
+                        for z in -radius..=radius {
+                            let corner = x.abs() == radius && z.abs() == radius && radius > 0;
+                            if corner && random.next_f32() < 0.5 {
+                                continue;
+                            }
+
+                            part.set(IVec3::new(anchor.x + x, y, anchor.z + z), leaves, &[]);
+                        }
+                    }
+                }
+
+                for y in anchor.y..top {
+                    part.set(IVec3::new(anchor.x, y, anchor.z), trunk, &[leaves]);
+                }
+            }
+            Feature::Boulder {
+                block,
+                min_radius,
+                max_radius,
+            } => {
+                let radius = random.range(min_radius, max_radius);
+                // Sunk one block into the ground
+                let center = anchor + IVec3::Y * (radius - 1);
+
+                for x in -radius..=radius {
+                    for y in -radius..=radius {
+                        for z in -radius..=radius {
+                            let offset = IVec3::new(x, y, z);
+                            if offset.length_squared() <= radius * radius + radius {
+                                part.set(center + offset, block, &[]);
+                            }
+                        }
+                    }
+                }
+            }
+            Feature::Structure { ref blocks } => {
+                for (offset, block) in blocks {
+                    part.set(anchor + *offset, *block, &[]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::generators::NoiseGenerator;
+    use crate::terrain::pipeline::{generate_chunk, generate_chunks};
+    use crate::terrain::worldgen_data::WorldgenData;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    /// Plains covered with trees
+    fn forest() -> WorldgenData {
+        let mut data = WorldgenData::default();
+        data.biomes.truncate(1);
+        data.biomes[0].decorations = vec![DecorationDefinition {
+            feature: FeatureDefinition::Tree {
+                trunk: "log".to_string(),
+                leaves: "leaves".to_string(),
+                min_height: 4,
+                max_height: 6,
+                radius: 2,
+            },
+            chance: 0.05,
+        }];
+        data
+    }
+
+    #[test]
+    fn test_trees_cross_chunk_borders() {
+        let registry = Arc::new(BlockRegistry::default());
+        let generator = NoiseGenerator::new(9, &forest(), &registry).unwrap();
+        let log = registry.block_type("log").unwrap();
+        let leaves = registry.block_type("leaves").unwrap();
+        let positions: Vec<IVec3> = (-1..=1)
+            .flat_map(|x| (-1..=1).map(move |z| IVec3::new(x, 0, z)))
+            .collect();
+
+        // Each chunk gets the same blocks whether it is generated along with its neighbors or
+        // on its own
+        let chunks: HashMap<IVec3, Chunk> = generate_chunks(&generator, positions.clone())
+            .into_iter()
+            .map(|chunk| (chunk.pos, chunk))
+            .collect();
+        for pos in positions.iter().rev() {
+            assert_eq!(
+                generate_chunk(&generator, *pos).compress(),
+                chunks[pos].compress(),
+                "chunk {:?} depends on the generation order",
+                pos
+            );
+        }
+
+        let block_at = |pos: IVec3| {
+            let chunk_pos = pos.div_euclid(IVec3::new(CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE));
+            chunks.get(&chunk_pos).map(|chunk| {
+                chunk
+                    .voxel_at(&(pos - chunk_pos * IVec3::new(CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE)))
+                    .voxel_type
+            })
+        };
+
+        // Leaves of the trees of the middle chunk, found in the neighbor chunks
+        let mut trees = 0;
+        let mut leaves_in_neighbors = 0;
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let Some(top) = (0..CHUNK_HEIGHT)
+                    .rev()
+                    .find(|y| block_at(IVec3::new(x, *y, z)) == Some(log))
+                else {
+                    continue;
+                };
+                trees += 1;
+
+                for dx in -2..=2 {
+                    for dz in -2..=2 {
+                        let pos = IVec3::new(x + dx, top, z + dz);
+                        let in_neighbor =
+                            !(0..CHUNK_SIZE).contains(&pos.x) || !(0..CHUNK_SIZE).contains(&pos.z);
+                        if in_neighbor && block_at(pos) == Some(leaves) {
+                            leaves_in_neighbors += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        assert!(trees > 0);
+        assert!(leaves_in_neighbors > 0, "no tree crosses a chunk border");
+    }
+}
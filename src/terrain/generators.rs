@@ -0,0 +1,482 @@
+//! The world generators shipped with the game, see [`GeneratorSettings`](crate::terrain::world_generator::GeneratorSettings)
+
+use crate::terrain::biome::{Biome, BiomeBlocks, BiomeSource, BiomeWeights};
+use crate::terrain::caves::CaveCarver;
+use crate::terrain::image_terrain::ImageTerrain;
+use crate::terrain::noise::NoiseMap;
+use crate::terrain::ores::OrePlacer;
+use crate::terrain::pipeline::{GenerationStage, Heightmap, Neighborhood, ProtoChunk};
+use crate::terrain::random::WorldRandom;
+use crate::terrain::world_generator::{FlatLayer, WorldGenerator};
+use crate::terrain::worldgen_data::{find_block, WorldgenData};
+use crate::voxel::block::{Block, BlockType};
+use crate::voxel::block_registry::BlockRegistry;
+use crate::voxel::chunk::{Chunk, CHUNK_HEIGHT, CHUNK_SIZE};
+use bevy::math::IVec3;
+use std::io;
+use std::sync::Arc;
+
+fn chunk_world_pos(chunk: &Chunk) -> IVec3 {
+    chunk.pos * IVec3::new(CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE)
+}
+
+const HILLS_FREQUENCY: f32 = 0.008;
+const ROUGHNESS_FREQUENCY: f32 = 0.04;
+const HILLS_SALT: u64 = 3;
+const ROUGHNESS_SALT: u64 = 4;
+const DECORATION_SALT: u64 = 5;
+
+/// Biomes shaped by a noise heightmap, see [`WorldgenData`]
+pub struct NoiseGenerator {
+    seed: i32,
+    /// Multiplies the hills and roughness of every biome
+    amplification: f32,
+    biomes: BiomeSource,
+    hills: NoiseMap,
+    roughness: NoiseMap,
+    caves: CaveCarver,
+    ores: OrePlacer,
+    stone: BlockType,
+    sea_level: i32,
+    water: BlockType,
+    /// Farthest a decoration of any biome goes from its column
+    decoration_reach: i32,
+    /// Heights and surface blocks replacing the noise where the image covers the world
+    image: Option<ImageTerrain>,
+    /// Blocks of the world, giving the light they let through and emit
+    registry: Arc<BlockRegistry>,
+}
+
+impl NoiseGenerator {
+    pub fn new(seed: i32, data: &WorldgenData, registry: &Arc<BlockRegistry>) -> io::Result<Self> {
+        let biomes = BiomeSource::new(seed, data, registry)?;
+        let decoration_reach = biomes
+            .biomes()
+            .iter()
+            .flat_map(|biome| &biome.decorations)
+            .map(|decoration| decoration.reach())
+            .max()
+            .unwrap_or(0);
+
+        // Decorating only reads the heightmaps of the chunks right next to the decorated one
+        if decoration_reach > CHUNK_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "decorations reach {} blocks away, at most {} are supported",
+                    decoration_reach, CHUNK_SIZE
+                ),
+            ));
+        }
+
+        Ok(Self {
+            seed,
+            amplification: 1.0,
+            decoration_reach,
+            biomes,
+            hills: NoiseMap::new(seed, HILLS_SALT, HILLS_FREQUENCY),
+            roughness: NoiseMap::new(seed, ROUGHNESS_SALT, ROUGHNESS_FREQUENCY),
+            caves: CaveCarver::new(seed, &data.caves, data.sea_level),
+            ores: OrePlacer::new(seed, &data.ores, registry)?,
+            stone: find_block(registry, "stone")?,
+            sea_level: data.sea_level,
+            water: find_block(registry, "water")?,
+            image: None,
+            registry: Arc::clone(registry),
+        })
+    }
+
+    pub fn amplified(
+        seed: i32,
+        data: &WorldgenData,
+        registry: &Arc<BlockRegistry>,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            amplification: 4.0,
+            ..Self::new(seed, data, registry)?
+        })
+    }
+
+    /// Terrain shaped by an image, the noise only shaping the world around it
+    pub fn with_image(
+        seed: i32,
+        data: &WorldgenData,
+        registry: &Arc<BlockRegistry>,
+        image: ImageTerrain,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            image: Some(image),
+            ..Self::new(seed, data, registry)?
+        })
+    }
+
+    pub fn seed(&self) -> i32 {
+        self.seed
+    }
+
+    pub fn biomes(&self) -> &BiomeSource {
+        &self.biomes
+    }
+
+    /// Height of the first block above the ground
+    fn height(&self, weights: &BiomeWeights, hills: f32, roughness: f32) -> i32 {
+        self.biomes
+            .blend(weights, |biome| {
+                biome.height
+                    + (biome.hills * hills + biome.roughness * roughness) * self.amplification
+            })
+            .round() as i32
+    }
+
+    pub fn caves(&self) -> &CaveCarver {
+        &self.caves
+    }
+
+    /// Biome weights and height of a square of columns starting at (`x`, `z`), indexed by
+    /// `z * width + x`
+    fn columns(&self, x: i32, z: i32, width: usize) -> Vec<(BiomeWeights, i32)> {
+        let hills = self.hills.square(x, z, width);
+        let roughness = self.roughness.square(x, z, width);
+
+        self.biomes
+            .square_weights(x, z, width)
+            .into_iter()
+            .enumerate()
+            .map(|(i, weights)| {
+                let (column_x, column_z) = (x + (i % width) as i32, z + (i / width) as i32);
+                let noise_height = self.height(&weights, hills[i], roughness[i]);
+
+                // Blended into the noise height near the edges of the image
+                let height = match self.image.as_ref().and_then(|image| {
+                    Some((
+                        image.height(column_x, column_z)?,
+                        image.weight(column_x, column_z),
+                    ))
+                }) {
+                    Some((image_height, weight)) => (noise_height as f32
+                        + (image_height - noise_height) as f32 * weight)
+                        .round() as i32,
+                    None => noise_height,
+                };
+                (weights, height)
+            })
+            .collect()
+    }
+
+    /// Top block of a world column, picked by the color map of the image if it covers the column,
+    /// otherwise the filler of its biome when it is under the sea
+    fn top_block(&self, biome: &BiomeBlocks, x: i32, z: i32, height: i32) -> BlockType {
+        if let Some(surface) = self.image.as_ref().and_then(|image| image.surface(x, z)) {
+            surface
+        } else if height < self.sea_level {
+            biome.filler
+        } else {
+            biome.surface_at(height)
+        }
+    }
+
+    /// Place the part inside the chunk of the decoration anchored on a column, if it gets one,
+    /// is above the sea and no cave opens under it
+    fn decorate(&self, chunk: &mut Chunk, biome: &BiomeBlocks, x: i32, z: i32, height: i32) {
+        if height < self.sea_level {
+            return;
+        }
+
+        let anchor = IVec3::new(x, height, z);
+        let mut random = WorldRandom::new(self.seed, IVec3::new(x, 0, z), DECORATION_SALT);
+
+        for decoration in &biome.decorations {
+            if random.next_f32() >= decoration.chance {
+                continue;
+            }
+
+            if !self.caves.is_carved_at(anchor - IVec3::Y, height) {
+                decoration.place(chunk, anchor, &mut random);
+            }
+
+            break;
+        }
+    }
+}
+
+impl WorldGenerator for NoiseGenerator {
+    fn generate(&self, stage: GenerationStage, chunk: &mut ProtoChunk, neighbors: &Neighborhood) {
+        let chunk_world_pos = chunk_world_pos(&chunk.chunk);
+
+        match stage {
+            GenerationStage::Heightmap => {
+                let columns =
+                    self.columns(chunk_world_pos.x, chunk_world_pos.z, CHUNK_SIZE as usize);
+                let heightmap = Heightmap {
+                    heights: columns.iter().map(|(_, height)| *height).collect(),
+                    biomes: columns
+                        .iter()
+                        .map(|(weights, _)| self.biomes.biome(weights.closest).definition.biome)
+                        .collect(),
+                };
+
+                for x in 0..CHUNK_SIZE {
+                    for z in 0..CHUNK_SIZE {
+                        // Part of the column from the bottom of the chunk up to the surface
+                        let top =
+                            (heightmap.height(x, z).unwrap() - chunk_world_pos.y).min(CHUNK_HEIGHT);
+
+                        for y in 0..top {
+                            chunk
+                                .chunk
+                                .set_voxel(&IVec3::new(x, y, z), Block::new(self.stone));
+                        }
+                    }
+                }
+
+                chunk.heightmap = Arc::new(heightmap);
+            }
+            GenerationStage::Carve => self.caves.carve(&mut chunk.chunk, &chunk.heightmap.heights),
+            GenerationStage::Surface => {
+                for x in 0..CHUNK_SIZE {
+                    for z in 0..CHUNK_SIZE {
+                        let height = chunk.heightmap.height(x, z).unwrap();
+                        let biome = self.biomes.blocks(chunk.heightmap.biome(x, z).unwrap());
+                        let filler_start = height - 1 - biome.definition.filler_depth as i32;
+
+                        for y in filler_start.max(chunk_world_pos.y)
+                            ..height.min(chunk_world_pos.y + CHUNK_HEIGHT)
+                        {
+                            let local = IVec3::new(x, y - chunk_world_pos.y, z);
+                            if chunk.chunk.voxel_at(&local).is_air() {
+                                continue;
+                            }
+
+                            let voxel_type = if y == height - 1 {
+                                self.top_block(
+                                    biome,
+                                    chunk_world_pos.x + x,
+                                    chunk_world_pos.z + z,
+                                    height,
+                                )
+                            } else {
+                                biome.filler
+                            };
+                            chunk.chunk.set_voxel(&local, Block::new(voxel_type));
+                        }
+
+                        for y in height.max(chunk_world_pos.y)
+                            ..self.sea_level.min(chunk_world_pos.y + CHUNK_HEIGHT)
+                        {
+                            chunk.chunk.set_voxel(
+                                &IVec3::new(x, y - chunk_world_pos.y, z),
+                                Block::new(self.water),
+                            );
+                        }
+                    }
+                }
+            }
+            GenerationStage::Decorate => {
+                self.ores.place(&mut chunk.chunk);
+
+                // The columns of the chunk and the ones around it whose decorations can reach
+                // into it, always in the same order so that overlapping decorations end up the
+                // same whichever chunk places them
+                let reach = self.decoration_reach;
+                for z in -reach..CHUNK_SIZE + reach {
+                    for x in -reach..CHUNK_SIZE + reach {
+                        let (Some(height), Some(biome)) =
+                            (neighbors.height(x, z), neighbors.biome(x, z))
+                        else {
+                            panic!("decorating needs the heightmaps of the neighbor chunks");
+                        };
+
+                        self.decorate(
+                            &mut chunk.chunk,
+                            self.biomes.blocks(biome),
+                            chunk_world_pos.x + x,
+                            chunk_world_pos.z + z,
+                            height,
+                        );
+                    }
+                }
+            }
+            // The sky reaches the chunk in the columns whose surface is below its top
+            GenerationStage::Light => {
+                let heightmap = chunk.heightmap.clone();
+                chunk.chunk.compute_light(&self.registry, |x, z| {
+                    heightmap
+                        .height(x, z)
+                        .is_none_or(|height| height <= chunk_world_pos.y + CHUNK_HEIGHT)
+                });
+            }
+        }
+    }
+
+    fn biome_at(&self, x: i32, z: i32) -> Option<Biome> {
+        Some(self.biomes.biome_at(x, z))
+    }
+
+    fn surface_height(&self, x: i32, z: i32) -> Option<i32> {
+        Some(self.columns(x, z, 1)[0].1)
+    }
+}
+
+/// Layers of blocks from y = 0 up, the same everywhere
+pub struct SuperflatGenerator {
+    /// Block of each height, starting at y = 0
+    blocks: Vec<BlockType>,
+    registry: Arc<BlockRegistry>,
+}
+
+impl SuperflatGenerator {
+    pub fn new(layers: &[FlatLayer], registry: &Arc<BlockRegistry>) -> io::Result<Self> {
+        let mut blocks = Vec::new();
+
+        for layer in layers {
+            let block = find_block(registry, &layer.block)?;
+            blocks.extend(std::iter::repeat_n(block, layer.height as usize));
+        }
+
+        Ok(Self {
+            blocks,
+            registry: Arc::clone(registry),
+        })
+    }
+}
+
+impl WorldGenerator for SuperflatGenerator {
+    /// Everything is placed by the heightmap stage, then lit by the light stage
+    fn generate(&self, stage: GenerationStage, chunk: &mut ProtoChunk, _neighbors: &Neighborhood) {
+        let top = self.blocks.len() as i32;
+        if stage == GenerationStage::Light {
+            let sky_above = chunk_world_pos(&chunk.chunk).y + CHUNK_HEIGHT >= top;
+            chunk.chunk.compute_light(&self.registry, |_, _| sky_above);
+            return;
+        }
+        if stage != GenerationStage::Heightmap {
+            return;
+        }
+
+        chunk.heightmap = Arc::new(Heightmap {
+            heights: vec![self.blocks.len() as i32; (CHUNK_SIZE * CHUNK_SIZE) as usize],
+            biomes: Vec::new(),
+        });
+
+        let chunk_world_pos = chunk_world_pos(&chunk.chunk);
+
+        for local_y in 0..CHUNK_HEIGHT {
+            let Some(&block) = usize::try_from(chunk_world_pos.y + local_y)
+                .ok()
+                .and_then(|y| self.blocks.get(y))
+            else {
+                continue;
+            };
+
+            if block == BlockType::AIR {
+                continue;
+            }
+
+            for x in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    chunk
+                        .chunk
+                        .set_voxel(&IVec3::new(x, local_y, z), Block::new(block));
+                }
+            }
+        }
+    }
+
+    fn surface_height(&self, _x: i32, _z: i32) -> Option<i32> {
+        Some(self.blocks.len() as i32)
+    }
+}
+
+/// Empty world, every chunk is left as air
+pub struct VoidGenerator;
+
+impl WorldGenerator for VoidGenerator {
+    fn generate(
+        &self,
+        _stage: GenerationStage,
+        _chunk: &mut ProtoChunk,
+        _neighbors: &Neighborhood,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::pipeline::generate_chunk;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_columns_match_generated_terrain() {
+        let registry = Arc::new(BlockRegistry::default());
+        let generator = NoiseGenerator::new(3, &WorldgenData::default(), &registry).unwrap();
+
+        for (x, z) in [(0, 0), (-700, 230), (1500, -90), (-2200, -1800)] {
+            let height = generator.surface_height(x, z).unwrap();
+            let pos = IVec3::new(x, height - 1, z);
+            let chunk_pos = pos.div_euclid(IVec3::new(CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE));
+            let local = pos - chunk_pos * IVec3::new(CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE);
+
+            let chunk = generate_chunk(&generator, chunk_pos);
+
+            let biome = generator.biome_at(x, z).unwrap();
+            let weights = generator.biomes().column_weights(x, z);
+            let blocks = generator.biomes().biome(weights.closest);
+            assert_eq!(blocks.definition.biome, biome);
+            assert_eq!(
+                chunk.voxel_at(&local).voxel_type,
+                generator.top_block(blocks, x, z, height)
+            );
+        }
+    }
+
+    #[test]
+    fn test_sea_fills_low_columns() {
+        let registry = Arc::new(BlockRegistry::default());
+        let data = WorldgenData::default();
+        let generator = NoiseGenerator::new(3, &data, &registry).unwrap();
+        let water = registry.block_type("water").unwrap();
+
+        let (x, height) = (0..20_000)
+            .step_by(16)
+            .map(|x| (x, generator.surface_height(x, 0).unwrap()))
+            .find(|(_, height)| *height < data.sea_level - 2)
+            .expect("no column under the sea");
+        let chunk_pos = IVec3::new(x.div_euclid(CHUNK_SIZE), 0, 0);
+        let chunk = generate_chunk(&generator, chunk_pos);
+        let local_x = x.rem_euclid(CHUNK_SIZE);
+
+        for y in height..data.sea_level {
+            assert_eq!(chunk.voxel_at(&IVec3::new(local_x, y, 0)).voxel_type, water);
+        }
+        assert!(chunk
+            .voxel_at(&IVec3::new(local_x, data.sea_level, 0))
+            .is_air());
+        assert!(!chunk
+            .voxel_at(&IVec3::new(local_x, height - 1, 0))
+            .is_fluid(&registry));
+    }
+
+    #[test]
+    fn test_heights_blend_between_biomes() {
+        let generator = NoiseGenerator::new(11, &WorldgenData::default(), &Arc::default()).unwrap();
+
+        let mut biomes = HashSet::new();
+        let mut previous = generator.surface_height(-4000, 0).unwrap();
+        for x in -3999..4000 {
+            biomes.insert(generator.biome_at(x, 0).unwrap());
+
+            let height = generator.surface_height(x, 0).unwrap();
+            assert!(
+                (height - previous).abs() <= 6,
+                "height goes from {} to {} at x = {}",
+                previous,
+                height,
+                x
+            );
+            previous = height;
+        }
+
+        assert!(biomes.len() >= 3, "only crossed {:?}", biomes);
+    }
+}
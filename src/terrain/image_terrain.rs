@@ -0,0 +1,377 @@
+//! Terrain sculpted in an image editor rather than by the noise
+//!
+//! A grayscale PNG gives the height of each column, black being the lowest ground and white the
+//! highest, and an optional color map of the same size picks the surface block of each column
+//! from the `surface_colors` of `worldgen.ron`. Everything else (caves, ores, water, decorations
+//! and the terrain outside the image) comes from the [`NoiseGenerator`](crate::terrain::generators::NoiseGenerator),
+//! the heights of the image blending into the noise over the last few pixels of its edges.
+
+use crate::terrain::worldgen_data::find_block;
+use crate::voxel::block::BlockType;
+use crate::voxel::block_registry::BlockRegistry;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+/// Pixels from the edge of an image over which its heights blend into the noise around it
+const EDGE_BLEND_PIXELS: f32 = 4.0;
+
+/// Block picked by the pixels of the color map closest to `color`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SurfaceColor {
+    pub color: (u8, u8, u8),
+    pub block: String,
+}
+
+/// Images of a heightmap world and where they are laid in it, saved in its level file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImageTerrainSettings {
+    /// Path of the grayscale PNG, from the game directory
+    pub heightmap: String,
+    /// Path of the PNG picking the surface blocks, transparent pixels keeping the block of the
+    /// biome
+    pub color_map: Option<String>,
+    /// Blocks covered by a pixel, horizontally
+    pub scale: f32,
+    /// Height of white pixels above black ones
+    pub height_scale: f32,
+    /// World position of the top left corner of the image, at the height of black pixels
+    pub offset: (i32, i32, i32),
+    /// Repeat the image over the whole world instead of falling back to the noise around it
+    pub tiling: bool,
+}
+
+impl Default for ImageTerrainSettings {
+    /// Example island shipped with the game
+    fn default() -> Self {
+        Self {
+            heightmap: "assets/heightmaps/island.png".to_string(),
+            color_map: Some("assets/heightmaps/island_colors.png".to_string()),
+            scale: 2.0,
+            height_scale: 64.0,
+            offset: (-128, 16, -128),
+            tiling: false,
+        }
+    }
+}
+
+pub struct ImageTerrain {
+    settings: ImageTerrainSettings,
+    width: u32,
+    height: u32,
+    /// Height of each pixel from 0 to 1, indexed by `y * width + x`
+    heights: Vec<f32>,
+    /// Surface block of each pixel, if there is a color map
+    surfaces: Option<Vec<Option<BlockType>>>,
+}
+
+impl ImageTerrain {
+    /// Read the images of the settings
+    pub fn load(
+        settings: &ImageTerrainSettings,
+        colors: &[SurfaceColor],
+        registry: &BlockRegistry,
+    ) -> io::Result<Self> {
+        let color_map = settings.color_map.as_deref().map(open_image).transpose()?;
+
+        Self::new(
+            settings,
+            &open_image(&settings.heightmap)?,
+            color_map.as_ref(),
+            colors,
+            registry,
+        )
+    }
+
+    pub fn new(
+        settings: &ImageTerrainSettings,
+        heightmap: &DynamicImage,
+        color_map: Option<&DynamicImage>,
+        colors: &[SurfaceColor],
+        registry: &BlockRegistry,
+    ) -> io::Result<Self> {
+        if settings.scale <= 0.0 || heightmap.width() == 0 || heightmap.height() == 0 {
+            return Err(invalid_data(
+                "the heightmap needs pixels and a scale above 0",
+            ));
+        }
+
+        let heights = heightmap
+            .to_luma16()
+            .pixels()
+            .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32)
+            .collect();
+
+        let surfaces = match color_map {
+            Some(color_map) => {
+                if (color_map.width(), color_map.height())
+                    != (heightmap.width(), heightmap.height())
+                {
+                    return Err(invalid_data(
+                        "the color map must be the size of the heightmap",
+                    ));
+                }
+
+                let colors = colors
+                    .iter()
+                    .map(|color| Ok((color.color, find_block(registry, &color.block)?)))
+                    .collect::<io::Result<Vec<_>>>()?;
+                if colors.is_empty() {
+                    return Err(invalid_data("a color map needs surface colors"));
+                }
+
+                Some(
+                    color_map
+                        .to_rgba8()
+                        .pixels()
+                        .map(|pixel| {
+                            let [r, g, b, a] = pixel.0;
+                            (a >= 128).then(|| closest_block(&colors, (r, g, b)))
+                        })
+                        .collect(),
+                )
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            settings: settings.clone(),
+            width: heightmap.width(),
+            height: heightmap.height(),
+            heights,
+            surfaces,
+        })
+    }
+
+    /// Position in pixels of a world column, `None` outside the image when it is not tiled
+    fn pixel_pos(&self, x: i32, z: i32) -> Option<(f32, f32)> {
+        let (offset_x, _, offset_z) = self.settings.offset;
+        let u = (x - offset_x) as f32 / self.settings.scale;
+        let v = (z - offset_z) as f32 / self.settings.scale;
+
+        if self.settings.tiling {
+            Some((
+                u.rem_euclid(self.width as f32),
+                v.rem_euclid(self.height as f32),
+            ))
+        } else if (0.0..self.width as f32).contains(&u) && (0.0..self.height as f32).contains(&v) {
+            Some((u, v))
+        } else {
+            None
+        }
+    }
+
+    /// Index of the pixel at (`x`, `y`), wrapped around or clamped to the image
+    fn pixel_index(&self, x: i32, y: i32) -> usize {
+        let (x, y) = if self.settings.tiling {
+            (
+                x.rem_euclid(self.width as i32),
+                y.rem_euclid(self.height as i32),
+            )
+        } else {
+            (
+                x.clamp(0, self.width as i32 - 1),
+                y.clamp(0, self.height as i32 - 1),
+            )
+        };
+        (y * self.width as i32 + x) as usize
+    }
+
+    /// Height of the first block above the ground of a world column, if the image covers it
+    ///
+    /// Pixels are interpolated, so that scaled up images give slopes rather than steps.
+    pub fn height(&self, x: i32, z: i32) -> Option<i32> {
+        let (u, v) = self.pixel_pos(x, z)?;
+        // Pixel values are at their centers
+        let (u, v) = (u - 0.5, v - 0.5);
+        let (left, top) = (u.floor() as i32, v.floor() as i32);
+        let (tx, ty) = (u - u.floor(), v - v.floor());
+
+        let value = |dx, dy| self.heights[self.pixel_index(left + dx, top + dy)];
+        let top_row = value(0, 0) * (1.0 - tx) + value(1, 0) * tx;
+        let bottom_row = value(0, 1) * (1.0 - tx) + value(1, 1) * tx;
+        let value = top_row * (1.0 - ty) + bottom_row * ty;
+
+        Some(self.settings.offset.1 + (value * self.settings.height_scale).round() as i32)
+    }
+
+    /// How much the height of the image counts in a world column, from 1 in the image down to 0
+    /// at its edges, so the terrain doesn't end in a cliff where the noise takes over
+    pub fn weight(&self, x: i32, z: i32) -> f32 {
+        if self.settings.tiling {
+            return 1.0;
+        }
+        let Some((u, v)) = self.pixel_pos(x, z) else {
+            return 0.0;
+        };
+
+        let edge_distance = u
+            .min(self.width as f32 - u)
+            .min(v)
+            .min(self.height as f32 - v);
+        (edge_distance / EDGE_BLEND_PIXELS).min(1.0)
+    }
+
+    /// Surface block the color map picks for a world column
+    pub fn surface(&self, x: i32, z: i32) -> Option<BlockType> {
+        let surfaces = self.surfaces.as_ref()?;
+        let (u, v) = self.pixel_pos(x, z)?;
+        surfaces[self.pixel_index(u as i32, v as i32)]
+    }
+}
+
+fn closest_block(colors: &[((u8, u8, u8), BlockType)], (r, g, b): (u8, u8, u8)) -> BlockType {
+    let distance = |(other_r, other_g, other_b): (u8, u8, u8)| {
+        let (dr, dg, db) = (
+            r as i32 - other_r as i32,
+            g as i32 - other_g as i32,
+            b as i32 - other_b as i32,
+        );
+        dr * dr + dg * dg + db * db
+    };
+
+    colors
+        .iter()
+        .min_by_key(|(color, _)| distance(*color))
+        .map(|(_, block)| *block)
+        .unwrap()
+}
+
+fn open_image(path: &str) -> io::Result<DynamicImage> {
+    image::open(Path::new(path)).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Failed to read the image {}: {}", path, err),
+        )
+    })
+}
+
+fn invalid_data(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::generators::NoiseGenerator;
+    use crate::terrain::world_generator::WorldGenerator;
+    use crate::terrain::worldgen_data::WorldgenData;
+    use image::{GrayImage, Luma, Rgba, RgbaImage};
+    use std::sync::Arc;
+
+    /// 4 by 2 pixels, getting brighter to the right
+    fn ramp() -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(4, 2, |x, _| Luma([x as u8 * 85])))
+    }
+
+    fn settings(tiling: bool) -> ImageTerrainSettings {
+        ImageTerrainSettings {
+            heightmap: String::new(),
+            color_map: None,
+            scale: 4.0,
+            height_scale: 30.0,
+            offset: (100, 10, -8),
+            tiling,
+        }
+    }
+
+    #[test]
+    fn test_image_heights() {
+        let registry = Arc::new(BlockRegistry::default());
+        let terrain = ImageTerrain::new(&settings(false), &ramp(), None, &[], &registry).unwrap();
+
+        // Centers of the first and last pixels
+        assert_eq!(terrain.height(102, -6), Some(10));
+        assert_eq!(terrain.height(114, -2), Some(40));
+        // Halfway between the centers of the second and third pixels
+        assert_eq!(terrain.height(108, -6), Some(25));
+        // Outside the image
+        assert_eq!(terrain.height(99, -6), None);
+        assert_eq!(terrain.height(116, -6), None);
+        assert_eq!(terrain.height(102, 0), None);
+
+        let tiled = ImageTerrain::new(&settings(true), &ramp(), None, &[], &registry).unwrap();
+        assert_eq!(tiled.height(102 + 16 * 3, -6 - 8 * 5), Some(10));
+        assert_eq!(tiled.height(114 - 16 * 7, -6), Some(40));
+
+        let mut no_scale = settings(false);
+        no_scale.scale = 0.0;
+        assert!(ImageTerrain::new(&no_scale, &ramp(), None, &[], &registry).is_err());
+    }
+
+    #[test]
+    fn test_color_map_picks_surface() {
+        let registry = Arc::new(BlockRegistry::default());
+        let colors = [
+            SurfaceColor {
+                color: (40, 200, 40),
+                block: "grass".to_string(),
+            },
+            SurfaceColor {
+                color: (230, 210, 150),
+                block: "sand".to_string(),
+            },
+        ];
+        let color_map = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 2, |x, _| match x {
+            0 => Rgba([50, 180, 60, 255]),
+            1 => Rgba([255, 230, 140, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        }));
+
+        let terrain = ImageTerrain::new(
+            &settings(false),
+            &ramp(),
+            Some(&color_map),
+            &colors,
+            &registry,
+        )
+        .unwrap();
+        assert_eq!(terrain.surface(101, -7), registry.block_type("grass"));
+        assert_eq!(terrain.surface(105, -7), registry.block_type("sand"));
+        assert_eq!(terrain.surface(109, -7), None);
+        assert_eq!(terrain.surface(90, -7), None);
+
+        let small = DynamicImage::ImageRgba8(RgbaImage::new(2, 2));
+        assert!(
+            ImageTerrain::new(&settings(false), &ramp(), Some(&small), &colors, &registry).is_err()
+        );
+    }
+
+    #[test]
+    fn test_noise_outside_image() {
+        let registry = Arc::new(BlockRegistry::default());
+        let data = WorldgenData::default();
+        let terrain = ImageTerrain::new(&settings(false), &ramp(), None, &[], &registry).unwrap();
+        let noise = NoiseGenerator::new(5, &data, &registry).unwrap();
+        let generator = NoiseGenerator::with_image(5, &data, &registry, terrain).unwrap();
+
+        for (x, z) in [(99, -6), (300, 40), (-50, -50)] {
+            assert_eq!(generator.surface_height(x, z), noise.surface_height(x, z));
+        }
+    }
+
+    #[test]
+    fn test_image_blends_into_noise() {
+        let registry = Arc::new(BlockRegistry::default());
+        let data = WorldgenData::default();
+        let white = DynamicImage::ImageLuma8(GrayImage::from_pixel(16, 16, Luma([255])));
+        let settings = ImageTerrainSettings {
+            scale: 1.0,
+            offset: (0, 100, 0),
+            ..settings(false)
+        };
+        let terrain = ImageTerrain::new(&settings, &white, None, &[], &registry).unwrap();
+        let noise = NoiseGenerator::new(5, &data, &registry).unwrap();
+        let generator = NoiseGenerator::with_image(5, &data, &registry, terrain).unwrap();
+
+        // Only the image in the middle, only the noise right at the edge
+        assert_eq!(generator.surface_height(8, 8), Some(130));
+        assert_eq!(generator.surface_height(0, 8), noise.surface_height(0, 8));
+
+        // Between both in the margin
+        let noise_height = noise.surface_height(2, 8).unwrap();
+        let height = generator.surface_height(2, 8).unwrap();
+        assert!(noise_height < height && height < 130);
+    }
+}
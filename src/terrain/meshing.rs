@@ -1,9 +1,14 @@
-use crate::chunk::ServerChunkEntity;
-use crate::terrain::chunk_generation::TerrainGenTask;
-use crate::voxel::chunk::{ChunkEntity, CHUNK_SIZE};
-use crate::voxel::mesh_builder::create_chunk_mesh;
-use crate::voxel::texture::ResourcePack;
+use crate::core::player::PlayerCamera;
+use crate::terrain::chunk_generation::{ChunkLoadTask, ServerChunkPipeline};
+use crate::voxel::block_registry::GameBlockRegistry;
+use crate::voxel::chunk::{
+    ChunkEntity, ChunkSectionEntity, ChunkSections, SectionTranslucentEntity, CHUNK_HEIGHT,
+    CHUNK_SIZE, SECTION_SIZE,
+};
+use crate::voxel::mesh_builder::{create_section_mesh, sort_quads_back_to_front, SectionMeshes};
+use crate::voxel::texture::{BlockMaterial, ResourcePack};
 use crate::voxel::world::GameWorld;
+use crate::voxel::world::World;
 use crate::{ClientState, ServerState};
 use bevy::asset::Assets;
 use bevy::prelude::*;
@@ -12,7 +17,7 @@ use futures_lite::future;
 use std::sync::Arc;
 
 #[derive(Component)]
-pub struct ChunkMeshTask(Task<Mesh>);
+pub struct ChunkMeshTask(Task<SectionMeshes>);
 
 pub fn prepare_chunks(
     chunks: Query<(Entity, &ChunkEntity), Added<ChunkEntity>>,
@@ -23,52 +28,85 @@ pub fn prepare_chunks(
         entity_commands.insert((
             Transform::from_xyz(
                 (chunk_key.0.x * CHUNK_SIZE) as f32,
-                0.0,
+                (chunk_key.0.y * CHUNK_HEIGHT) as f32,
                 (chunk_key.0.z * CHUNK_SIZE) as f32,
             ),
-            Visibility::Hidden,
+            Visibility::default(),
         ));
         debug!("Prepared chunk entity placeholder for {:?}", chunk_key.0);
     }
 }
 
-pub fn clear_dirty_chunks(game_world: Res<GameWorld>) {
+pub fn clear_dirty_sections(game_world: Res<GameWorld>) {
     game_world
         .world
         .write()
         .unwrap()
-        .dirty_chunks
+        .dirty_sections
         .write()
         .unwrap()
         .clear();
 }
 
-pub fn queue_mesh_tasks(mut commands: Commands, game_world: Res<GameWorld>) {
-    for chunk_coord in game_world
+pub fn queue_mesh_tasks(
+    mut commands: Commands,
+    game_world: Res<GameWorld>,
+    block_registry: Res<GameBlockRegistry>,
+    mut chunk_sections: Query<&mut ChunkSections>,
+) {
+    for section_coord in game_world
         .world
         .read()
         .unwrap()
-        .dirty_chunks
+        .dirty_sections
         .read()
         .unwrap()
         .clone()
         .into_iter()
     {
         let pool = AsyncComputeTaskPool::get();
+        let (chunk_coord, section_index) = World::section_chunk(&section_coord);
 
         let chunk_entities = Arc::clone(&game_world.world.read().unwrap().chunk_entities);
         let chunk_entities = chunk_entities.read().unwrap();
         let chunk_entity = chunk_entities.get(&chunk_coord);
 
         if let Some(entity) = chunk_entity {
-            let chunk_data_map = Arc::clone(&game_world.world.read().unwrap().chunk_data_map);
+            // The chunk may have been unloaded since it was marked dirty
+            let Some(chunk) = game_world.world.read().unwrap().get_chunk(chunk_coord) else {
+                continue;
+            };
+            let Ok(mut sections) = chunk_sections.get_mut(*entity) else {
+                continue;
+            };
+
+            let section_entity = match sections.0[section_index] {
+                Some(section_entity) => section_entity,
+                // Empty sections don't need an entity until something is placed in them
+                None if chunk.read().unwrap().section(section_index).is_none() => continue,
+                None => {
+                    let section_entity = commands
+                        .spawn((
+                            ChunkSectionEntity(section_coord),
+                            Transform::from_xyz(
+                                0.0,
+                                (section_index as i32 * SECTION_SIZE) as f32,
+                                0.0,
+                            ),
+                            Visibility::Hidden,
+                        ))
+                        .set_parent(*entity)
+                        .id();
+                    sections.0[section_index] = Some(section_entity);
+                    section_entity
+                }
+            };
 
+            let registry = Arc::clone(&block_registry.registry);
             commands
-                .entity(*entity)
+                .entity(section_entity)
                 .insert(ChunkMeshTask(pool.spawn(async move {
-                    let chunk_data_map = chunk_data_map.read().unwrap();
-                    let chunk = chunk_data_map.get(&chunk_coord).unwrap().read().unwrap();
-                    create_chunk_mesh(&chunk)
+                    create_section_mesh(&chunk.read().unwrap(), section_index, &registry)
                 })));
         } else {
             println!("Chunk {:?} not found", chunk_coord);
@@ -76,93 +114,107 @@ pub fn queue_mesh_tasks(mut commands: Commands, game_world: Res<GameWorld>) {
     }
 }
 
+fn is_empty(mesh: &Mesh) -> bool {
+    mesh.count_vertices() == 0 || mesh.indices().is_none_or(|indices| indices.is_empty())
+}
+
+/// Sections waiting for their meshes, with their translucent child if they have one
+type SectionMeshTaskQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static ChunkSectionEntity,
+        &'static mut Visibility,
+        &'static mut ChunkMeshTask,
+        Option<&'static Children>,
+    ),
+    With<ChunkSectionEntity>,
+>;
+
 pub fn process_mesh_tasks(
     mut meshes: ResMut<Assets<Mesh>>,
-    mut task_query: Query<
-        (Entity, &ChunkEntity, &mut Visibility, &mut ChunkMeshTask),
-        With<ChunkEntity>,
-    >,
-    // Query only Mesh3d and the Material
-    mut mesh_query: Query<(
-        Option<&mut Mesh3d>,
-        Option<&mut MeshMaterial3d<StandardMaterial>>, // Still need the material
-    )>,
+    mut task_query: SectionMeshTaskQuery,
+    translucent_query: Query<(), With<SectionTranslucentEntity>>,
     mut commands: Commands,
     resource_pack: Res<ResourcePack>,
 ) {
-    for (entity, chunk_key, mut visibility, mut mesh_task) in task_query.iter_mut() {
-        if let Some(new_mesh) = future::block_on(future::poll_once(&mut mesh_task.0)) {
-            let vertex_count = new_mesh.count_vertices();
-            let index_count = new_mesh.indices().map_or(0, |indices| indices.len());
-
-            debug!(
-                "Processing mesh task for chunk {:?}: Vertices={}, Indices={}",
-                chunk_key.0, vertex_count, index_count
-            );
+    for (entity, section_key, mut visibility, mut mesh_task, children) in task_query.iter_mut() {
+        let Some(new_meshes) = future::block_on(future::poll_once(&mut mesh_task.0)) else {
+            continue;
+        };
 
-            if vertex_count == 0 || index_count == 0 {
-                warn!(
-                    "Generated mesh for chunk {:?} is empty. Setting visibility to hidden.",
-                    chunk_key.0
-                );
-                // Only remove Mesh3d and the material if they existed
-                if mesh_query.get(entity).is_ok() {
-                    commands
-                        .entity(entity)
-                        .remove::<(Mesh3d, MeshMaterial3d<StandardMaterial>)>();
-                }
-                *visibility = Visibility::Hidden;
-            } else {
-                let new_mesh_handle = meshes.add(new_mesh);
-
-                // Try to get existing components mutably
-                if let Ok((maybe_mesh_3d, maybe_material)) = mesh_query.get_mut(entity) {
-                    // Entity already has components (or some of them)
-                    if let Some(mut mesh_3d) = maybe_mesh_3d {
-                        // Update existing mesh handle
-                        debug!("Updating existing mesh for chunk {:?}", chunk_key.0);
-                        if mesh_3d.0 != new_mesh_handle {
-                            // Only update if handle actually changed
-                            mesh_3d.0 = new_mesh_handle.clone();
-                        }
-                    } else {
-                        // Mesh component missing, insert it
-                        debug!("Inserting Mesh3d for chunk {:?}", chunk_key.0);
-                        commands
-                            .entity(entity)
-                            .insert(Mesh3d(new_mesh_handle.clone()));
-                    }
-
-                    // Check and insert material if missing
-                    if maybe_material.is_none() {
-                        debug!("Inserting MeshMaterial3d for chunk {:?}", chunk_key.0);
-                        commands
-                            .entity(entity)
-                            .insert(MeshMaterial3d(resource_pack.handle.clone()));
-                    }
-                } else {
-                    // Entity likely had no mesh components before, insert both
-                    debug!("Attaching new mesh and material to chunk {:?}", chunk_key.0);
-                    commands.entity(entity).insert((
-                        MeshMaterial3d(resource_pack.handle.clone()),
-                        Mesh3d(new_mesh_handle),
-                    ));
-                }
-                // Make it visible
-                *visibility = Visibility::Visible;
+        debug!(
+            "Processing mesh task for section {:?}: Vertices={}, Translucent vertices={}",
+            section_key.0,
+            new_meshes.opaque.count_vertices(),
+            new_meshes.translucent.count_vertices()
+        );
+
+        let opaque_empty = is_empty(&new_meshes.opaque);
+        let translucent_empty = is_empty(&new_meshes.translucent);
+
+        if opaque_empty {
+            commands
+                .entity(entity)
+                .remove::<(Mesh3d, MeshMaterial3d<BlockMaterial>)>();
+        } else {
+            // Replacing the handle drops the previous mesh
+            commands.entity(entity).insert((
+                Mesh3d(meshes.add(new_meshes.opaque)),
+                MeshMaterial3d(resource_pack.handle.clone()),
+            ));
+        }
+
+        // Translucent blocks are drawn by a child entity, so they get their own material
+        let translucent_entity = children.and_then(|children| {
+            children
+                .iter()
+                .find(|child| translucent_query.contains(**child))
+                .copied()
+        });
+        match (translucent_entity, translucent_empty) {
+            (Some(translucent_entity), true) => commands.entity(translucent_entity).despawn(),
+            (Some(translucent_entity), false) => {
+                commands
+                    .entity(translucent_entity)
+                    .insert(Mesh3d(meshes.add(new_meshes.translucent)));
+            }
+            (None, false) => {
+                commands
+                    .spawn((
+                        SectionTranslucentEntity,
+                        Mesh3d(meshes.add(new_meshes.translucent)),
+                        MeshMaterial3d(resource_pack.translucent.clone()),
+                        Transform::default(),
+                        Visibility::Inherited,
+                    ))
+                    .set_parent(entity);
             }
+            (None, true) => {}
+        }
 
-            // Remove the task component once processed
-            commands.entity(entity).remove::<ChunkMeshTask>();
+        if opaque_empty && translucent_empty {
+            warn!(
+                "Generated mesh for section {:?} is empty. Setting visibility to hidden.",
+                section_key.0
+            );
+            *visibility = Visibility::Hidden;
+        } else {
+            *visibility = Visibility::Visible;
         }
+
+        // Remove the task component once processed
+        commands.entity(entity).remove::<ChunkMeshTask>();
     }
 }
 
 pub fn check_server_loading_world_ended(
-    gen_tasks: Query<(Entity, &ServerChunkEntity, &mut TerrainGenTask)>,
+    load_tasks: Query<(), With<ChunkLoadTask>>,
+    pipeline: Res<ServerChunkPipeline>,
     mut next_state: ResMut<NextState<ServerState>>,
 ) {
-    if gen_tasks.is_empty() {
+    if load_tasks.is_empty() && pipeline.is_idle() {
         println!("Server is ready!");
         next_state.set(ServerState::Running);
     }
@@ -185,5 +237,46 @@ pub fn check_loading_world_ended(
     }
 }
 
+/// Sort the faces of the translucent meshes back to front as seen from the camera
+///
+/// Every mesh is sorted again when the camera moves into another block, and new meshes as soon
+/// as they are built.
+pub fn sort_translucent_faces(
+    camera: Query<&GlobalTransform, With<PlayerCamera>>,
+    translucent_sections: Query<(Ref<Mesh3d>, &Parent), With<SectionTranslucentEntity>>,
+    sections: Query<&ChunkSectionEntity>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut last_camera_block: Local<Option<IVec3>>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let camera_position = camera.translation();
+    let camera_block = camera_position.floor().as_ivec3();
+    let camera_moved = *last_camera_block != Some(camera_block);
+    *last_camera_block = Some(camera_block);
+
+    for (mesh, parent) in translucent_sections.iter() {
+        if !camera_moved && !mesh.is_changed() {
+            continue;
+        }
+        let Ok(section) = sections.get(parent.get()) else {
+            continue;
+        };
+        let Some(mesh) = meshes.get_mut(&mesh.0) else {
+            continue;
+        };
+
+        // Meshes are positioned relative to the bottom of their section
+        let (chunk_coord, section_index) = World::section_chunk(&section.0);
+        let section_origin = IVec3::new(
+            chunk_coord.x * CHUNK_SIZE,
+            chunk_coord.y * CHUNK_HEIGHT + section_index as i32 * SECTION_SIZE,
+            chunk_coord.z * CHUNK_SIZE,
+        );
+        sort_quads_back_to_front(mesh, camera_position - section_origin.as_vec3());
+    }
+}
+
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
 pub struct ChunkMeshingSet;
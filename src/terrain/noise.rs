@@ -0,0 +1,96 @@
+use crate::terrain::random::WorldRandom;
+use bevy::math::IVec3;
+use simdnoise::NoiseBuilder;
+
+/// Largest absolute value of the simdnoise gradient noise, used to bring it between -1 and 1
+const GRADIENT_NOISE_AMPLITUDE: f32 = 0.0221;
+const GRADIENT_NOISE_3D_AMPLITUDE: f32 = 0.0306;
+/// Noise maps are moved up to this distance away from the origin
+const MAX_OFFSET: i32 = 100_000;
+
+/// 2D gradient noise going from -1 to 1
+///
+/// The simdnoise seed barely changes its output (seeds 1 and 1001 give the same noise, 1 and 2
+/// opposite ones), so each map is instead moved away from the origin by an offset picked from
+/// the world seed and a salt telling apart the maps of a world.
+pub struct NoiseMap {
+    offset: IVec3,
+    frequency: f32,
+}
+
+fn random_offset(seed: i32, salt: u64) -> IVec3 {
+    let mut random = WorldRandom::new(seed, IVec3::ZERO, salt);
+    let x = random.range(-MAX_OFFSET, MAX_OFFSET);
+    let z = random.range(-MAX_OFFSET, MAX_OFFSET);
+
+    IVec3::new(x, random.range(-MAX_OFFSET, MAX_OFFSET), z)
+}
+
+impl NoiseMap {
+    pub fn new(seed: i32, salt: u64, frequency: f32) -> Self {
+        Self {
+            offset: random_offset(seed, salt),
+            frequency,
+        }
+    }
+
+    /// Noise of a square of columns starting at (`x`, `z`), indexed by `z * width + x`
+    pub fn square(&self, x: i32, z: i32, width: usize) -> Vec<f32> {
+        NoiseBuilder::gradient_2d_offset(
+            (x + self.offset.x) as f32,
+            width,
+            (z + self.offset.z) as f32,
+            width,
+        )
+        .with_freq(self.frequency)
+        .generate()
+        .0
+        .into_iter()
+        .map(|value| (value / GRADIENT_NOISE_AMPLITUDE).clamp(-1.0, 1.0))
+        .collect()
+    }
+
+    pub fn get(&self, x: i32, z: i32) -> f32 {
+        self.square(x, z, 1)[0]
+    }
+}
+
+/// 3D gradient noise going from -1 to 1, seeded like [`NoiseMap`]
+pub struct NoiseVolume {
+    offset: IVec3,
+    frequency: f32,
+}
+
+impl NoiseVolume {
+    pub fn new(seed: i32, salt: u64, frequency: f32) -> Self {
+        Self {
+            offset: random_offset(seed, salt),
+            frequency,
+        }
+    }
+
+    /// Noise of a cube of blocks starting at `pos`, indexed by
+    /// `z * width * width + y * width + x`
+    pub fn cube(&self, pos: IVec3, width: usize) -> Vec<f32> {
+        let start = pos + self.offset;
+
+        NoiseBuilder::gradient_3d_offset(
+            start.x as f32,
+            width,
+            start.y as f32,
+            width,
+            start.z as f32,
+            width,
+        )
+        .with_freq(self.frequency)
+        .generate()
+        .0
+        .into_iter()
+        .map(|value| (value / GRADIENT_NOISE_3D_AMPLITUDE).clamp(-1.0, 1.0))
+        .collect()
+    }
+
+    pub fn get(&self, pos: IVec3) -> f32 {
+        self.cube(pos, 1)[0]
+    }
+}
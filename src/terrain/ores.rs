@@ -0,0 +1,206 @@
+//! Ore veins placed in the stone once the terrain and its caves are generated
+
+use crate::terrain::random::WorldRandom;
+use crate::terrain::worldgen_data::find_block;
+use crate::voxel::block::{Block, BlockType};
+use crate::voxel::block_registry::BlockRegistry;
+use crate::voxel::chunk::{Chunk, CHUNK_HEIGHT, CHUNK_SIZE};
+use bevy::math::IVec3;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Salt of the first ore, the next ones use the following numbers
+const ORE_SALT: u64 = 100;
+
+const VEIN_STEPS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OreDefinition {
+    pub block: String,
+    /// Heights between which veins start, both included
+    pub min_height: i32,
+    pub max_height: i32,
+    /// Blocks in a vein
+    pub vein_size: u32,
+    /// Veins in each chunk column
+    pub veins: u32,
+}
+
+struct Ore {
+    definition: OreDefinition,
+    block: BlockType,
+    salt: u64,
+}
+
+/// Places the ores of a world, veins staying inside their chunk
+pub struct OrePlacer {
+    seed: i32,
+    ores: Vec<Ore>,
+    stone: BlockType,
+}
+
+impl OrePlacer {
+    pub fn new(seed: i32, ores: &[OreDefinition], registry: &BlockRegistry) -> io::Result<Self> {
+        Ok(Self {
+            seed,
+            ores: ores
+                .iter()
+                .enumerate()
+                .map(|(i, definition)| {
+                    Ok(Ore {
+                        definition: definition.clone(),
+                        block: find_block(registry, &definition.block)?,
+                        salt: ORE_SALT + i as u64,
+                    })
+                })
+                .collect::<io::Result<_>>()?,
+            stone: find_block(registry, "stone")?,
+        })
+    }
+
+    /// Replace some of the stone of a chunk with ore veins
+    ///
+    /// Each chunk draws the veins of its whole column and only keeps the ones starting within
+    /// its height, so the amount of ore doesn't depend on how the column is split in chunks.
+    pub fn place(&self, chunk: &mut Chunk) {
+        let chunk_world_y = chunk.pos.y * CHUNK_HEIGHT;
+
+        for ore in &self.ores {
+            let definition = &ore.definition;
+            if definition.max_height < chunk_world_y
+                || definition.min_height >= chunk_world_y + CHUNK_HEIGHT
+            {
+                continue;
+            }
+
+            let mut random = WorldRandom::new(self.seed, chunk.pos, ore.salt);
+
+            for _ in 0..definition.veins {
+                let start = IVec3::new(
+                    random.range(0, CHUNK_SIZE - 1),
+                    random.range(definition.min_height, definition.max_height) - chunk_world_y,
+                    random.range(0, CHUNK_SIZE - 1),
+                );
+
+                if !(0..CHUNK_HEIGHT).contains(&start.y) {
+                    continue;
+                }
+
+                self.place_vein(chunk, ore, start, &mut random);
+            }
+        }
+    }
+
+    fn place_vein(&self, chunk: &mut Chunk, ore: &Ore, start: IVec3, random: &mut WorldRandom) {
+        let mut pos = start;
+
+        for _ in 0..ore.definition.vein_size {
+            if Chunk::is_in_chunk(&pos) && chunk.voxel_at(&pos).voxel_type == self.stone {
+                chunk.set_voxel(&pos, Block::new(ore.block));
+            }
+
+            pos += VEIN_STEPS[random.range(0, VEIN_STEPS.len() as i32 - 1) as usize];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::worldgen_data::WorldgenData;
+    use std::collections::HashMap;
+
+    const CHUNK_COLUMNS: i32 = 6;
+
+    /// Chunk made only of stone, so every vein is fully placed
+    fn stone_chunk(pos: IVec3, stone: BlockType) -> Chunk {
+        let mut chunk = Chunk::new(pos);
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_HEIGHT {
+                for z in 0..CHUNK_SIZE {
+                    chunk.set_voxel(&IVec3::new(x, y, z), Block::new(stone));
+                }
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn test_ore_distribution() {
+        let registry = BlockRegistry::default();
+        let data = WorldgenData::default();
+        let placer = OrePlacer::new(7, &data.ores, &registry).unwrap();
+        let stone = registry.block_type("stone").unwrap();
+
+        // Ore blocks found at each height, by ore
+        let mut found: HashMap<BlockType, Vec<i32>> = HashMap::new();
+
+        for x in 0..CHUNK_COLUMNS {
+            for z in 0..CHUNK_COLUMNS {
+                for y in -1..=0 {
+                    let mut chunk = stone_chunk(IVec3::new(x, y, z), stone);
+                    placer.place(&mut chunk);
+
+                    let mut again = stone_chunk(IVec3::new(x, y, z), stone);
+                    placer.place(&mut again);
+                    assert_eq!(chunk.compress(), again.compress());
+
+                    for local_x in 0..CHUNK_SIZE {
+                        for local_y in 0..CHUNK_HEIGHT {
+                            for local_z in 0..CHUNK_SIZE {
+                                let block = chunk.voxel_at(&IVec3::new(local_x, local_y, local_z));
+                                if block.voxel_type != stone {
+                                    found
+                                        .entry(block.voxel_type)
+                                        .or_default()
+                                        .push(y * CHUNK_HEIGHT + local_y);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let columns = (CHUNK_COLUMNS * CHUNK_COLUMNS) as f32;
+        for ore in &data.ores {
+            let heights = &found[&registry.block_type(&ore.block).unwrap()];
+            let spread = ore.vein_size as i32;
+
+            // Veins start within the range and can only wander a vein size away from it
+            assert!(heights
+                .iter()
+                .all(|y| (ore.min_height - spread..=ore.max_height + spread).contains(y)));
+
+            // A vein is a random walk, so it comes back on its own blocks and some of its blocks
+            // end up outside the chunk
+            let per_column = heights.len() as f32 / columns;
+            let full_veins = (ore.veins * ore.vein_size) as f32;
+            assert!(
+                (full_veins * 0.35..=full_veins).contains(&per_column),
+                "{} {} blocks per chunk column, {} expected at most",
+                per_column,
+                ore.block,
+                full_veins
+            );
+
+            // Spread evenly over the height range
+            let middle = (ore.min_height + ore.max_height) / 2;
+            let below = heights.iter().filter(|y| **y < middle).count() as f32;
+            let ratio = below / heights.len() as f32;
+            assert!(
+                (0.3..=0.7).contains(&ratio),
+                "{:.0}% of the {} is in the lower half of its range",
+                ratio * 100.0,
+                ore.block
+            );
+        }
+    }
+}
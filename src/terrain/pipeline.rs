@@ -0,0 +1,506 @@
+//! Chunk generation split in stages
+//!
+//! A chunk goes through the [`GenerationStage`]s in order, its [`ChunkStatus`] being the last
+//! one it went through. Stages reading the chunks around wait for them to go through the
+//! previous stages, generating them if needed: decorating a chunk needs the heightmaps of its
+//! neighbors. Chunks only generated for their neighbors stay in the pipeline, so that they don't
+//! go through the same stages again when they are requested in turn, up to
+//! [`MAX_CACHED_CHUNKS`] of them no requested chunk needs anymore. Only complete chunks leave it.
+
+use crate::terrain::biome::Biome;
+use crate::terrain::world_generator::WorldGenerator;
+use crate::voxel::chunk::{Chunk, CHUNK_SIZE};
+use bevy::math::IVec3;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Chunks kept once no requested chunk needs them, the least recently needed ones are dropped
+/// first
+pub const MAX_CACHED_CHUNKS: usize = 1024;
+
+/// Chunks around a chunk read by the stages needing the neighbors, at the same height
+const NEIGHBOR_OFFSETS: [IVec3; 8] = [
+    IVec3::new(-1, 0, -1),
+    IVec3::new(0, 0, -1),
+    IVec3::new(1, 0, -1),
+    IVec3::new(-1, 0, 0),
+    IVec3::new(1, 0, 0),
+    IVec3::new(-1, 0, 1),
+    IVec3::new(0, 0, 1),
+    IVec3::new(1, 0, 1),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GenerationStage {
+    /// Find the height and biome of each column and fill the ground with stone
+    Heightmap,
+    /// Carve the caves
+    Carve,
+    /// Replace the top of the ground with the blocks of the biome
+    Surface,
+    /// Place the ores and the features of the biomes, which can cross chunk borders
+    Decorate,
+    Light,
+}
+
+impl GenerationStage {
+    pub const ALL: [Self; 5] = [
+        Self::Heightmap,
+        Self::Carve,
+        Self::Surface,
+        Self::Decorate,
+        Self::Light,
+    ];
+
+    /// Whether the stage reads the chunks around
+    pub fn needs_neighbors(self) -> bool {
+        matches!(self, Self::Decorate)
+    }
+
+    /// Status a chunk has when the stage runs on it, and that its neighbors need if the stage
+    /// reads them
+    pub fn required_status(self) -> ChunkStatus {
+        ChunkStatus::ALL[self as usize]
+    }
+
+    /// Status of a chunk once it went through the stage
+    pub fn status(self) -> ChunkStatus {
+        ChunkStatus::ALL[self as usize + 1]
+    }
+}
+
+/// Last stage a chunk went through
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChunkStatus {
+    #[default]
+    Empty,
+    Heightmap,
+    Carved,
+    Surfaced,
+    Decorated,
+    /// Went through every stage
+    Full,
+}
+
+impl ChunkStatus {
+    pub const ALL: [Self; 6] = [
+        Self::Empty,
+        Self::Heightmap,
+        Self::Carved,
+        Self::Surfaced,
+        Self::Decorated,
+        Self::Full,
+    ];
+
+    /// Stage bringing a chunk with this status to the next one
+    pub fn next_stage(self) -> Option<GenerationStage> {
+        GenerationStage::ALL.get(self as usize).copied()
+    }
+
+    /// Status the chunks around a chunk need for it to reach this status
+    pub fn neighbor_status(self) -> Option<ChunkStatus> {
+        GenerationStage::ALL
+            .iter()
+            .filter(|stage| stage.status() <= self && stage.needs_neighbors())
+            .map(|stage| stage.required_status())
+            .max()
+    }
+}
+
+/// Columns of a chunk, found by the heightmap stage
+#[derive(Clone, Debug, Default)]
+pub struct Heightmap {
+    /// Height of the first block above the ground, indexed by `z * CHUNK_SIZE + x`
+    pub heights: Vec<i32>,
+    /// Biome of each column, indexed like the heights, empty for generators without biomes
+    pub biomes: Vec<Biome>,
+}
+
+impl Heightmap {
+    fn index(x: i32, z: i32) -> usize {
+        (z * CHUNK_SIZE + x) as usize
+    }
+
+    pub fn height(&self, x: i32, z: i32) -> Option<i32> {
+        self.heights.get(Self::index(x, z)).copied()
+    }
+
+    pub fn biome(&self, x: i32, z: i32) -> Option<Biome> {
+        self.biomes.get(Self::index(x, z)).copied()
+    }
+}
+
+/// Chunk going through the generation stages
+pub struct ProtoChunk {
+    pub chunk: Chunk,
+    /// Set by the heightmap stage
+    pub heightmap: Arc<Heightmap>,
+}
+
+/// Heightmaps of a chunk and of the 8 chunks around it
+#[derive(Default)]
+pub struct Neighborhood {
+    /// Indexed by `(z + 1) * 3 + x + 1`, with x and z the offset of the chunk
+    heightmaps: [Option<Arc<Heightmap>>; 9],
+}
+
+impl Neighborhood {
+    /// Heightmap holding a column given relative to the chunk, up to a chunk away from it
+    fn column(&self, x: i32, z: i32) -> Option<(&Heightmap, i32, i32)> {
+        let chunk_x = x.div_euclid(CHUNK_SIZE);
+        let chunk_z = z.div_euclid(CHUNK_SIZE);
+        if chunk_x.abs() > 1 || chunk_z.abs() > 1 {
+            return None;
+        }
+
+        let heightmap = self.heightmaps[((chunk_z + 1) * 3 + chunk_x + 1) as usize].as_deref()?;
+        Some((
+            heightmap,
+            x.rem_euclid(CHUNK_SIZE),
+            z.rem_euclid(CHUNK_SIZE),
+        ))
+    }
+
+    pub fn height(&self, x: i32, z: i32) -> Option<i32> {
+        let (heightmap, x, z) = self.column(x, z)?;
+        heightmap.height(x, z)
+    }
+
+    pub fn biome(&self, x: i32, z: i32) -> Option<Biome> {
+        let (heightmap, x, z) = self.column(x, z)?;
+        heightmap.biome(x, z)
+    }
+}
+
+/// A stage to run on a chunk, taken out of the pipeline until it is done
+pub struct StageJob {
+    pub stage: GenerationStage,
+    pub chunk: ProtoChunk,
+    pub neighbors: Neighborhood,
+}
+
+impl StageJob {
+    pub fn run(mut self, generator: &dyn WorldGenerator) -> Self {
+        generator.generate(self.stage, &mut self.chunk, &self.neighbors);
+        self
+    }
+}
+
+struct PipelineChunk {
+    status: ChunkStatus,
+    heightmap: Arc<Heightmap>,
+    /// Taken out while a stage runs on it
+    chunk: Option<Chunk>,
+    /// Last time a requested chunk needed it, see [`ChunkPipeline::clock`]
+    last_needed: u64,
+}
+
+/// Chunks being generated, see the [module documentation](self)
+pub struct ChunkPipeline {
+    chunks: HashMap<IVec3, PipelineChunk>,
+    /// Chunks to bring to [`ChunkStatus::Full`]
+    requested: HashSet<IVec3>,
+    /// Whether a chunk was requested or a stage finished since the last jobs were handed out
+    changed: bool,
+    /// Number of times jobs were handed out
+    clock: u64,
+    /// Chunks kept once they aren't needed anymore
+    max_cached: usize,
+}
+
+impl Default for ChunkPipeline {
+    fn default() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            requested: HashSet::new(),
+            changed: false,
+            clock: 0,
+            max_cached: MAX_CACHED_CHUNKS,
+        }
+    }
+}
+
+impl ChunkPipeline {
+    pub fn request(&mut self, pos: IVec3) {
+        self.changed |= self.requested.insert(pos);
+    }
+
+    /// Whether every requested chunk is complete
+    pub fn is_idle(&self) -> bool {
+        self.requested.is_empty()
+    }
+
+    /// Status of a chunk still in the pipeline
+    pub fn status(&self, pos: IVec3) -> Option<ChunkStatus> {
+        self.chunks.get(&pos).map(|chunk| chunk.status)
+    }
+
+    /// Status each chunk needs to reach for the requested chunks to be complete
+    fn needed_statuses(&self) -> HashMap<IVec3, ChunkStatus> {
+        let mut needed = HashMap::new();
+        let mut queue: Vec<(IVec3, ChunkStatus)> = self
+            .requested
+            .iter()
+            .map(|pos| (*pos, ChunkStatus::Full))
+            .collect();
+
+        while let Some((pos, status)) = queue.pop() {
+            if needed.get(&pos).is_some_and(|needed| *needed >= status) {
+                continue;
+            }
+            needed.insert(pos, status);
+
+            if let Some(neighbor_status) = status.neighbor_status() {
+                queue.extend(
+                    NEIGHBOR_OFFSETS
+                        .iter()
+                        .map(|offset| (pos + *offset, neighbor_status)),
+                );
+            }
+        }
+
+        needed
+    }
+
+    fn neighborhood(&self, pos: IVec3) -> Neighborhood {
+        let mut neighborhood = Neighborhood::default();
+
+        for x in -1..=1 {
+            for z in -1..=1 {
+                neighborhood.heightmaps[((z + 1) * 3 + x + 1) as usize] = self
+                    .chunks
+                    .get(&(pos + IVec3::new(x, 0, z)))
+                    .map(|chunk| Arc::clone(&chunk.heightmap));
+            }
+        }
+
+        neighborhood
+    }
+
+    /// Take out the chunks ready for their next stage
+    pub fn ready_jobs(&mut self) -> Vec<StageJob> {
+        if !self.changed {
+            return Vec::new();
+        }
+        self.changed = false;
+
+        let needed = self.needed_statuses();
+        self.clock += 1;
+        for pos in needed.keys() {
+            self.chunks
+                .entry(*pos)
+                .or_insert_with(|| PipelineChunk {
+                    status: ChunkStatus::Empty,
+                    heightmap: Arc::default(),
+                    chunk: Some(Chunk::new(*pos)),
+                    last_needed: 0,
+                })
+                .last_needed = self.clock;
+        }
+        self.drop_cached_chunks(&needed);
+
+        let ready: Vec<(IVec3, GenerationStage)> = needed
+            .iter()
+            .filter_map(|(pos, needed_status)| {
+                let chunk = &self.chunks[pos];
+                if chunk.chunk.is_none() || chunk.status >= *needed_status {
+                    return None;
+                }
+
+                let stage = chunk.status.next_stage()?;
+                let neighbors_ready = !stage.needs_neighbors()
+                    || NEIGHBOR_OFFSETS.iter().all(|offset| {
+                        self.status(*pos + *offset)
+                            .is_some_and(|status| status >= chunk.status)
+                    });
+
+                neighbors_ready.then_some((*pos, stage))
+            })
+            .collect();
+
+        ready
+            .into_iter()
+            .map(|(pos, stage)| {
+                let neighbors = self.neighborhood(pos);
+                let chunk = self.chunks.get_mut(&pos).unwrap();
+
+                StageJob {
+                    stage,
+                    chunk: ProtoChunk {
+                        chunk: chunk.chunk.take().unwrap(),
+                        heightmap: Arc::clone(&chunk.heightmap),
+                    },
+                    neighbors,
+                }
+            })
+            .collect()
+    }
+
+    /// Drop the chunks needed the longest time ago once there are too many chunks no requested
+    /// chunk needs, unless a stage is running on them
+    fn drop_cached_chunks(&mut self, needed: &HashMap<IVec3, ChunkStatus>) {
+        let mut cached: Vec<(u64, IVec3)> = self
+            .chunks
+            .iter()
+            .filter(|(pos, chunk)| !needed.contains_key(pos) && chunk.chunk.is_some())
+            .map(|(pos, chunk)| (chunk.last_needed, *pos))
+            .collect();
+        if cached.len() <= self.max_cached {
+            return;
+        }
+
+        cached.sort_by_key(|(last_needed, pos)| (*last_needed, pos.x, pos.y, pos.z));
+        for (_, pos) in &cached[..cached.len() - self.max_cached] {
+            self.chunks.remove(pos);
+        }
+    }
+
+    /// Put back a chunk once its stage ran, returning it if it is a complete requested chunk
+    pub fn finish(&mut self, job: StageJob) -> Option<Chunk> {
+        let pos = job.chunk.chunk.pos;
+        let status = job.stage.status();
+        self.changed = true;
+
+        if status == ChunkStatus::Full && self.requested.remove(&pos) {
+            self.chunks.remove(&pos);
+            return Some(job.chunk.chunk);
+        }
+
+        let chunk = self.chunks.get_mut(&pos)?;
+        chunk.status = status;
+        chunk.heightmap = job.chunk.heightmap;
+        chunk.chunk = Some(job.chunk.chunk);
+        None
+    }
+}
+
+/// Generate chunks right away on the current thread, in no particular order
+pub fn generate_chunks(
+    generator: &dyn WorldGenerator,
+    positions: impl IntoIterator<Item = IVec3>,
+) -> Vec<Chunk> {
+    let mut pipeline = ChunkPipeline::default();
+    positions.into_iter().for_each(|pos| pipeline.request(pos));
+
+    let mut chunks = Vec::new();
+    while !pipeline.is_idle() {
+        let jobs = pipeline.ready_jobs();
+        assert!(!jobs.is_empty(), "chunk generation is stuck");
+
+        for job in jobs {
+            chunks.extend(pipeline.finish(job.run(generator)));
+        }
+    }
+
+    chunks
+}
+
+pub fn generate_chunk(generator: &dyn WorldGenerator, pos: IVec3) -> Chunk {
+    generate_chunks(generator, [pos]).pop().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::generators::NoiseGenerator;
+    use crate::terrain::worldgen_data::WorldgenData;
+
+    #[test]
+    fn test_neighbor_status() {
+        assert_eq!(ChunkStatus::Surfaced.neighbor_status(), None);
+        assert_eq!(
+            ChunkStatus::Decorated.neighbor_status(),
+            Some(ChunkStatus::Surfaced)
+        );
+        assert_eq!(
+            ChunkStatus::Full.neighbor_status(),
+            Some(ChunkStatus::Surfaced)
+        );
+        assert_eq!(ChunkStatus::Full.next_stage(), None);
+    }
+
+    #[test]
+    fn test_stages_wait_for_neighbors() {
+        let generator = NoiseGenerator::new(4, &WorldgenData::default(), &Arc::default()).unwrap();
+        let requested = [IVec3::new(0, 0, 0), IVec3::new(10, 0, 0)];
+
+        let mut pipeline = ChunkPipeline::default();
+        requested.iter().for_each(|pos| pipeline.request(*pos));
+
+        let mut complete = Vec::new();
+        let mut stages = 0;
+        while !pipeline.is_idle() {
+            let jobs = pipeline.ready_jobs();
+            assert!(!jobs.is_empty());
+
+            for job in &jobs {
+                let pos = job.chunk.chunk.pos;
+                if job.stage.needs_neighbors() {
+                    for offset in NEIGHBOR_OFFSETS {
+                        let status = pipeline.status(pos + offset).unwrap();
+                        assert!(
+                            status >= job.stage.required_status(),
+                            "{:?} ran on {:?} with a neighbor {:?}",
+                            job.stage,
+                            pos,
+                            status
+                        );
+                    }
+                }
+            }
+
+            // Finish them out of order, like tasks would
+            stages += jobs.len();
+            for job in jobs.into_iter().rev() {
+                complete.extend(pipeline.finish(job.run(&generator)));
+            }
+        }
+
+        let mut complete: Vec<IVec3> = complete.iter().map(|chunk| chunk.pos).collect();
+        complete.sort_by_key(|pos| pos.x);
+        assert_eq!(complete, requested);
+
+        // The ring of chunks around each requested chunk is generated up to the surface
+        assert_eq!(stages, 2 * (5 + 8 * 3));
+
+        // The chunks around are kept, but nothing runs on them anymore
+        assert!(pipeline.ready_jobs().is_empty());
+        assert_eq!(pipeline.chunks.len(), 2 * 8);
+        assert!(pipeline
+            .chunks
+            .values()
+            .all(|chunk| chunk.status == ChunkStatus::Surfaced));
+    }
+
+    #[test]
+    fn test_cached_chunks_are_reused() {
+        let generator = NoiseGenerator::new(4, &WorldgenData::default(), &Arc::default()).unwrap();
+        let mut pipeline = ChunkPipeline {
+            max_cached: 8,
+            ..Default::default()
+        };
+
+        let run = |pipeline: &mut ChunkPipeline, pos: IVec3| {
+            pipeline.request(pos);
+            let mut stages = 0;
+            while !pipeline.is_idle() {
+                for job in pipeline.ready_jobs() {
+                    stages += 1;
+                    pipeline.finish(job.run(&generator));
+                }
+            }
+            stages
+        };
+
+        assert_eq!(run(&mut pipeline, IVec3::ZERO), 5 + 8 * 3);
+        // The requested chunk and 4 of the chunks around it were surfaced already
+        assert_eq!(run(&mut pipeline, IVec3::X), 2 + 4 * 3);
+
+        // 11 chunks are left, the 3 needed the longest time ago are dropped on the next hand out
+        pipeline.changed = true;
+        assert!(pipeline.ready_jobs().is_empty());
+        assert_eq!(pipeline.chunks.len(), 8);
+        assert!((-1..=1).all(|z| pipeline.status(IVec3::new(-1, 0, z)).is_none()));
+        assert!(pipeline.status(IVec3::new(2, 0, 0)).is_some());
+    }
+}
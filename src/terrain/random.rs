@@ -0,0 +1,43 @@
+use bevy::math::IVec3;
+
+/// Small random number generator for the world generation
+///
+/// Seeded from the world seed and a position, so whatever order chunks are generated in, the
+/// same position always gets the same numbers. `salt` tells apart the features using the same
+/// position.
+pub struct WorldRandom(u64);
+
+impl WorldRandom {
+    pub fn new(seed: i32, pos: IVec3, salt: u64) -> Self {
+        let mut random = Self(seed as u32 as u64 ^ salt.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+
+        for coordinate in [pos.x, pos.y, pos.z] {
+            random.0 ^= random.next_u64() ^ coordinate as u32 as u64;
+        }
+
+        random
+    }
+
+    /// SplitMix64
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Number between 0 (included) and 1 (excluded)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Number between `min` and `max`, both included
+    pub fn range(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+
+        min + (self.next_u64() % (max - min + 1) as u64) as i32
+    }
+}
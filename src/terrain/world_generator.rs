@@ -0,0 +1,285 @@
+//! Pluggable terrain generation
+//!
+//! Each world stores its [`GeneratorSettings`] and seed in its level file. When the world is
+//! loaded they are turned into a [`WorldGenerator`], kept in the [`GameWorldGenerator`] resource
+//! and shared with the chunk generation tasks, which run its stages through the
+//! [`ChunkPipeline`](crate::terrain::pipeline::ChunkPipeline). Generators only depend on the
+//! seed and the position of the chunk, so the same seed always gives the same terrain.
+
+use crate::terrain::biome::Biome;
+use crate::terrain::generators::{NoiseGenerator, SuperflatGenerator, VoidGenerator};
+use crate::terrain::image_terrain::{ImageTerrain, ImageTerrainSettings};
+use crate::terrain::pipeline::{GenerationStage, Neighborhood, ProtoChunk};
+use crate::terrain::worldgen_data::WorldgenData;
+use crate::voxel::block_registry::BlockRegistry;
+use bevy::prelude::Resource;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+pub trait WorldGenerator: Send + Sync {
+    /// Run a stage of the generation on a chunk, at any height
+    ///
+    /// The heightmap stage sets the heightmap of the chunk. When a stage reading the neighbors
+    /// runs, their heightmaps are in `neighbors`.
+    fn generate(&self, stage: GenerationStage, chunk: &mut ProtoChunk, neighbors: &Neighborhood);
+
+    /// Biome of a world column, for generators using biomes
+    fn biome_at(&self, _x: i32, _z: i32) -> Option<Biome> {
+        None
+    }
+
+    /// Height of the first block above the ground of a world column, if the generator knows it
+    /// without generating the chunk
+    fn surface_height(&self, _x: i32, _z: i32) -> Option<i32> {
+        None
+    }
+}
+
+/// Generator of the world the server is running
+#[derive(Resource, Clone)]
+pub struct GameWorldGenerator {
+    pub generator: Arc<dyn WorldGenerator>,
+}
+
+impl GameWorldGenerator {
+    pub fn new(generator: impl WorldGenerator + 'static) -> Self {
+        Self {
+            generator: Arc::new(generator),
+        }
+    }
+}
+
+/// A layer of a superflat world, `height` blocks thick
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlatLayer {
+    pub block: String,
+    pub height: u32,
+}
+
+impl FlatLayer {
+    pub fn new(block: &str, height: u32) -> Self {
+        Self {
+            block: block.to_string(),
+            height,
+        }
+    }
+}
+
+/// Kind of terrain of a world, saved in its level file
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum GeneratorSettings {
+    /// Biomes shaped by a noise heightmap, see [`WorldgenData`]
+    #[default]
+    Default,
+    /// Flat layers of blocks, listed from the bottom up and starting at y = 0
+    Superflat { layers: Vec<FlatLayer> },
+    /// Nothing at all
+    Void,
+    /// Noise heightmap with much higher hills and deeper valleys
+    Amplified,
+    /// Heights (and surface blocks) read from images, the default terrain around them
+    Heightmap(ImageTerrainSettings),
+}
+
+impl GeneratorSettings {
+    /// Settings of each kind of generator, in the order they are shown in menus
+    pub fn presets() -> [GeneratorSettings; 5] {
+        [
+            GeneratorSettings::Default,
+            GeneratorSettings::superflat(),
+            GeneratorSettings::Void,
+            GeneratorSettings::Amplified,
+            GeneratorSettings::Heightmap(ImageTerrainSettings::default()),
+        ]
+    }
+
+    /// Superflat world with a layer of grass over dirt and stone
+    pub fn superflat() -> Self {
+        GeneratorSettings::Superflat {
+            layers: vec![
+                FlatLayer::new("stone", 1),
+                FlatLayer::new("dirt", 2),
+                FlatLayer::new("grass", 1),
+            ],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GeneratorSettings::Default => "Default",
+            GeneratorSettings::Superflat { .. } => "Superflat",
+            GeneratorSettings::Void => "Void",
+            GeneratorSettings::Amplified => "Amplified",
+            GeneratorSettings::Heightmap(_) => "Heightmap",
+        }
+    }
+
+    /// Create the generator, fails if it uses blocks missing from the registry or images that
+    /// can't be read
+    pub fn build(
+        &self,
+        seed: i32,
+        data: &WorldgenData,
+        registry: &Arc<BlockRegistry>,
+    ) -> io::Result<GameWorldGenerator> {
+        Ok(match self {
+            GeneratorSettings::Default => {
+                GameWorldGenerator::new(NoiseGenerator::new(seed, data, registry)?)
+            }
+            GeneratorSettings::Superflat { layers } => {
+                GameWorldGenerator::new(SuperflatGenerator::new(layers, registry)?)
+            }
+            GeneratorSettings::Void => GameWorldGenerator::new(VoidGenerator),
+            GeneratorSettings::Amplified => {
+                GameWorldGenerator::new(NoiseGenerator::amplified(seed, data, registry)?)
+            }
+            GeneratorSettings::Heightmap(settings) => {
+                let image = ImageTerrain::load(settings, &data.surface_colors, registry)?;
+                GameWorldGenerator::new(NoiseGenerator::with_image(seed, data, registry, image)?)
+            }
+        })
+    }
+}
+
+impl fmt::Display for GeneratorSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeneratorSettings::Superflat { layers } => {
+                write!(f, "{} ({})", self.name(), format_flat_layers(layers))
+            }
+            GeneratorSettings::Heightmap(settings) => {
+                write!(f, "{} ({})", self.name(), settings.heightmap)
+            }
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+/// Superflat layers written as `height*block` (or just `block` for a single block) separated by
+/// commas, from the bottom up, e.g. `stone,2*dirt,grass`
+pub fn parse_flat_layers(input: &str) -> io::Result<Vec<FlatLayer>> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|layer| !layer.is_empty())
+        .map(|layer| match layer.split_once('*') {
+            Some((height, block)) => height
+                .trim()
+                .parse()
+                .map(|height| FlatLayer::new(block.trim(), height))
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("\"{}\" is not a valid layer height", height.trim()),
+                    )
+                }),
+            None => Ok(FlatLayer::new(layer, 1)),
+        })
+        .collect()
+}
+
+pub fn format_flat_layers(layers: &[FlatLayer]) -> String {
+    layers
+        .iter()
+        .map(|layer| match layer.height {
+            1 => layer.block.clone(),
+            height => format!("{}*{}", height, layer.block),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::pipeline::generate_chunk;
+    use crate::voxel::chunk::{Chunk, CHUNK_HEIGHT, CHUNK_SIZE};
+    use bevy::math::IVec3;
+
+    fn generate(generator: &GameWorldGenerator, pos: IVec3) -> Chunk {
+        generate_chunk(generator.generator.as_ref(), pos)
+    }
+
+    fn same_blocks(a: &Chunk, b: &Chunk) -> bool {
+        (0..CHUNK_SIZE).all(|x| {
+            (0..CHUNK_HEIGHT).all(|y| {
+                (0..CHUNK_SIZE).all(|z| {
+                    let local = IVec3::new(x, y, z);
+                    a.voxel_at(&local) == b.voxel_at(&local)
+                })
+            })
+        })
+    }
+
+    #[test]
+    fn test_generators_are_deterministic() {
+        let registry = Arc::new(BlockRegistry::default());
+        let data = WorldgenData::default();
+
+        for settings in GeneratorSettings::presets() {
+            let first = settings.build(1234, &data, &registry).unwrap();
+            let second = settings.build(1234, &data, &registry).unwrap();
+
+            for pos in [IVec3::new(0, 0, 0), IVec3::new(-3, 2, 5)] {
+                assert!(
+                    same_blocks(&generate(&first, pos), &generate(&second, pos)),
+                    "{} generator is not deterministic",
+                    settings
+                );
+            }
+        }
+
+        let other_seed = GeneratorSettings::Default
+            .build(4321, &data, &registry)
+            .unwrap();
+        let default = GeneratorSettings::Default
+            .build(1234, &data, &registry)
+            .unwrap();
+        assert!(!same_blocks(
+            &generate(&default, IVec3::ZERO),
+            &generate(&other_seed, IVec3::ZERO)
+        ));
+    }
+
+    #[test]
+    fn test_superflat_layers() {
+        let registry = Arc::new(BlockRegistry::default());
+        let data = WorldgenData::default();
+        let layers = parse_flat_layers("stone, 2*dirt,grass").unwrap();
+        assert_eq!(
+            layers,
+            vec![
+                FlatLayer::new("stone", 1),
+                FlatLayer::new("dirt", 2),
+                FlatLayer::new("grass", 1),
+            ]
+        );
+        assert_eq!(format_flat_layers(&layers), "stone,2*dirt,grass");
+        assert!(parse_flat_layers("x*dirt").is_err());
+
+        let generator = GeneratorSettings::Superflat { layers }
+            .build(0, &data, &registry)
+            .unwrap();
+        let chunk = generate(&generator, IVec3::ZERO);
+        let name = |y| {
+            let block = chunk.voxel_at(&IVec3::new(3, y, 7));
+            registry.get(block.voxel_type).name.clone()
+        };
+        assert_eq!(
+            (0..5).map(name).collect::<Vec<_>>(),
+            ["stone", "dirt", "dirt", "grass", "air"]
+        );
+        assert!(same_blocks(
+            &generate(&generator, IVec3::new(0, -1, 0)),
+            &Chunk::new(IVec3::new(0, -1, 0))
+        ));
+
+        assert!(GeneratorSettings::Superflat {
+            layers: vec![FlatLayer::new("unknown", 1)]
+        }
+        .build(0, &data, &registry)
+        .is_err());
+    }
+}
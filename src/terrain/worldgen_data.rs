@@ -0,0 +1,123 @@
+//! Data driving the terrain generation, loaded from `assets/worldgen.ron`
+//!
+//! Like the block data file it is read by the server when a world is loaded, blocks being
+//! referenced by name and resolved with the block registry when the generator is created.
+
+use crate::terrain::biome::BiomeDefinition;
+use crate::terrain::caves::CaveSettings;
+use crate::terrain::image_terrain::SurfaceColor;
+use crate::terrain::ores::OreDefinition;
+use crate::voxel::block::BlockType;
+use crate::voxel::block_registry::BlockRegistry;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const WORLDGEN_FILE: &str = "assets/worldgen.ron";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldgenData {
+    pub biomes: Vec<BiomeDefinition>,
+    /// Columns whose surface is below this height are filled with water up to it
+    pub sea_level: i32,
+    pub caves: CaveSettings,
+    /// Placed in this order, later ores replacing only the stone left by the previous ones
+    pub ores: Vec<OreDefinition>,
+    /// Surface blocks picked by the color maps of heightmap worlds
+    pub surface_colors: Vec<SurfaceColor>,
+}
+
+impl Default for WorldgenData {
+    /// Data shipped with the game
+    fn default() -> Self {
+        Self::parse(include_str!("../../assets/worldgen.ron")).unwrap()
+    }
+}
+
+impl WorldgenData {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> io::Result<Self> {
+        let data: WorldgenData = ron::from_str(source).map_err(invalid_data)?;
+
+        if data.biomes.is_empty() {
+            return Err(invalid_data("at least one biome is needed"));
+        }
+
+        for (i, biome) in data.biomes.iter().enumerate() {
+            if data.biomes[..i]
+                .iter()
+                .any(|other| other.biome == biome.biome)
+            {
+                return Err(invalid_data(format!(
+                    "biome {} is defined twice",
+                    biome.biome
+                )));
+            }
+
+            if let Some(decoration) = biome
+                .decorations
+                .iter()
+                .find(|decoration| !decoration.is_valid())
+            {
+                return Err(invalid_data(format!(
+                    "{} decoration of biome {} is larger at minimum than at maximum",
+                    decoration.name(),
+                    biome.biome
+                )));
+            }
+        }
+
+        if let Some(ore) = data
+            .ores
+            .iter()
+            .find(|ore| ore.min_height > ore.max_height || ore.vein_size == 0)
+        {
+            return Err(invalid_data(format!(
+                "ore {} needs a vein size and a minimum height below its maximum height",
+                ore.block
+            )));
+        }
+
+        Ok(data)
+    }
+}
+
+fn invalid_data(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+pub(crate) fn find_block(registry: &BlockRegistry, name: &str) -> io::Result<BlockType> {
+    registry.block_type(name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("The terrain needs a block named {}", name),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_worldgen_data() {
+        let data = WorldgenData::default();
+        assert!(WorldgenData::parse(&ron::to_string(&data).unwrap()).is_ok());
+
+        let mut no_biomes = data.clone();
+        no_biomes.biomes.clear();
+        assert!(WorldgenData::parse(&ron::to_string(&no_biomes).unwrap()).is_err());
+
+        let mut duplicated = data.clone();
+        duplicated.biomes.push(data.biomes[0].clone());
+        assert!(WorldgenData::parse(&ron::to_string(&duplicated).unwrap()).is_err());
+
+        let mut upside_down_ore = data.clone();
+        upside_down_ore.ores[0].min_height = upside_down_ore.ores[0].max_height + 1;
+        assert!(WorldgenData::parse(&ron::to_string(&upside_down_ore).unwrap()).is_err());
+    }
+}
@@ -1,7 +1,14 @@
 pub mod block;
+pub mod block_entity;
+pub mod block_model;
+pub mod block_registry;
 pub mod chunk;
+pub mod chunk_tracker;
 pub mod direction;
+pub mod light;
 pub mod mesh_builder;
+pub mod migration;
+pub mod palette;
 pub mod quad;
 pub mod texture;
 pub mod world;
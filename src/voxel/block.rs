@@ -1,52 +1,80 @@
-use crate::voxel::direction::Direction;
+use crate::voxel::block_registry::BlockRegistry;
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub enum BlockType {
-    Void,
-    Grass,
-    Dirt,
-    Stone,
+/// Numeric id of a block in the [`BlockRegistry`](crate::voxel::block_registry::BlockRegistry)
+///
+/// Ids are assigned when the registry is loaded and stored in saved chunks, the world keeps the
+/// name of each id in its level file so they stay the same across versions of the block list.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BlockType(u16);
+
+impl BlockType {
+    /// Empty space, the only block with a fixed id
+    pub const AIR: BlockType = BlockType(0);
+
+    pub const fn id(&self) -> u16 {
+        self.0
+    }
+
+    pub const fn from_id(id: u16) -> Self {
+        Self(id)
+    }
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+/// Values of the properties of a block, such as the axis of a log or whether a door is open
+///
+/// Each property declared by the block definition takes a few bits, read and written through
+/// [`BlockDefinition::property`](crate::voxel::block_registry::BlockDefinition::property) and
+/// [`BlockDefinition::with_property`](crate::voxel::block_registry::BlockDefinition::with_property).
+/// The default state has every property set to its first value.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BlockState(pub(crate) u16);
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Block {
     pub(crate) voxel_type: BlockType,
+    pub(crate) state: BlockState,
 }
 
-impl Default for Block {
-    fn default() -> Self {
+impl Block {
+    pub fn new(voxel_type: BlockType) -> Self {
         Self {
-            voxel_type: BlockType::Void,
+            voxel_type,
+            state: BlockState::default(),
         }
     }
-}
 
-impl Block {
+    pub fn with_state(voxel_type: BlockType, state: BlockState) -> Self {
+        Self { voxel_type, state }
+    }
+
     pub fn new_empty() -> Self {
-        Self {
-            voxel_type: BlockType::Void,
-        }
+        Self::new(BlockType::AIR)
     }
 
-    pub fn is_solid(&self) -> bool {
-        self.voxel_type != BlockType::Void
+    pub fn state(&self) -> BlockState {
+        self.state
     }
 
-    pub fn set_type(&mut self, voxel_type: BlockType) {
-        self.voxel_type = voxel_type;
+    pub fn is_air(&self) -> bool {
+        self.voxel_type == BlockType::AIR
     }
 
-    pub fn get_face(voxel_type: &BlockType, direction: &Direction) -> u16 {
-        match voxel_type {
-            BlockType::Void => 0,
-            BlockType::Grass => match direction {
-                Direction::Up => 23,
-                Direction::Down => 9,
-                _ => 10,
-            },
-            BlockType::Dirt => 9,
-            BlockType::Stone => 50,
-        }
+    /// Whether players collide with the block, as defined in the registry
+    pub fn is_solid(&self, registry: &BlockRegistry) -> bool {
+        !self.is_air() && registry.get(self.voxel_type).solid
+    }
+
+    /// Whether the block is a fluid, like water, as defined in the registry
+    pub fn is_fluid(&self, registry: &BlockRegistry) -> bool {
+        !self.is_air() && registry.get(self.voxel_type).fluid
+    }
+
+    /// Change the type of the block, its state going back to the default of the new type
+    pub fn set_type(&mut self, voxel_type: BlockType) {
+        self.voxel_type = voxel_type;
+        self.state = BlockState::default();
     }
 }
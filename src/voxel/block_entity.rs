@@ -0,0 +1,117 @@
+//! Per-instance data of blocks that don't fit in a [`Block`](crate::voxel::block::Block)
+//!
+//! Block entities are stored in their [`Chunk`](crate::voxel::chunk::Chunk), keyed by the local
+//! position of their block, and are saved and sent to clients with it. Once a chunk is loaded,
+//! the server keeps clients in sync with the `BlockEntity*` server messages.
+
+use crate::voxel::block::BlockType;
+use serde::{Deserialize, Deserializer, Serialize};
+
+pub const CHEST_SLOTS: usize = 27;
+pub const SIGN_LINES: usize = 4;
+
+/// Kind of block entity created along with a block, see
+/// [`BlockDefinition::block_entity`](crate::voxel::block_registry::BlockDefinition::block_entity)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockEntityKind {
+    Chest,
+    Sign,
+    Furnace,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub block: BlockType,
+    pub count: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BlockEntity {
+    Chest {
+        /// Never more than [`CHEST_SLOTS`], chests with more are rejected when read from disk or
+        /// the network
+        #[serde(deserialize_with = "deserialize_chest_slots")]
+        slots: Vec<Option<ItemStack>>,
+    },
+    Sign {
+        lines: [String; SIGN_LINES],
+    },
+    Furnace {
+        input: Option<ItemStack>,
+        fuel: Option<ItemStack>,
+        output: Option<ItemStack>,
+        /// Progress of the current smelt, from 0 to 1
+        progress: f32,
+        /// Seconds left before the current fuel runs out
+        burn_time: f32,
+    },
+}
+
+impl BlockEntity {
+    /// Empty block entity of a kind, as created when its block is placed
+    pub fn new(kind: BlockEntityKind) -> Self {
+        match kind {
+            BlockEntityKind::Chest => BlockEntity::Chest {
+                slots: vec![None; CHEST_SLOTS],
+            },
+            BlockEntityKind::Sign => BlockEntity::Sign {
+                lines: Default::default(),
+            },
+            BlockEntityKind::Furnace => BlockEntity::Furnace {
+                input: None,
+                fuel: None,
+                output: None,
+                progress: 0.0,
+                burn_time: 0.0,
+            },
+        }
+    }
+
+    pub fn kind(&self) -> BlockEntityKind {
+        match self {
+            BlockEntity::Chest { .. } => BlockEntityKind::Chest,
+            BlockEntity::Sign { .. } => BlockEntityKind::Sign,
+            BlockEntity::Furnace { .. } => BlockEntityKind::Furnace,
+        }
+    }
+}
+
+fn deserialize_chest_slots<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Option<ItemStack>>, D::Error> {
+    let slots = Vec::<Option<ItemStack>>::deserialize(deserializer)?;
+
+    if slots.len() > CHEST_SLOTS {
+        return Err(serde::de::Error::custom(format!(
+            "chest with {} slots, at most {} are allowed",
+            slots.len(),
+            CHEST_SLOTS
+        )));
+    }
+
+    Ok(slots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::config;
+
+    #[test]
+    fn test_rejects_oversized_chest() {
+        let chest = BlockEntity::new(BlockEntityKind::Chest);
+        let encoded = bincode::serde::encode_to_vec(&chest, config::standard()).unwrap();
+        let (decoded, _): (BlockEntity, _) =
+            bincode::serde::decode_from_slice(&encoded, config::standard()).unwrap();
+        assert_eq!(decoded, chest);
+
+        let oversized = BlockEntity::Chest {
+            slots: vec![None; CHEST_SLOTS + 1],
+        };
+        let encoded = bincode::serde::encode_to_vec(&oversized, config::standard()).unwrap();
+        assert!(
+            bincode::serde::decode_from_slice::<BlockEntity, _>(&encoded, config::standard())
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,462 @@
+//! Shapes of the blocks, from full cubes to slabs, stairs, fences and plants
+//!
+//! Each block definition picks a [`BlockModel`] in the data file, which is turned into boxes for
+//! the state of each block. The mesher draws the sides of the boxes that aren't hidden by the
+//! blocks around them, and players collide with the boxes and target them.
+
+use crate::voxel::block::{Block, BlockState};
+use crate::voxel::block_registry::{BlockDefinition, BlockRegistry, RenderCategory, HALF_PROPERTY};
+use crate::voxel::direction::Direction;
+use bevy::math::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+// Precompute corner offsets for each face direction relative to voxel center (0,0,0)
+// Order: Top-Left, Top-Right, Bottom-Right, Bottom-Left (relative to viewing the face)
+// Matches the typical quad vertex order for triangulation (0, 1, 2, 0, 2, 3)
+pub const FACE_CORNERS: [[Vec3; 4]; 6] = [
+    // Right (+X face)
+    [
+        Vec3::new(0.5, 0.5, -0.5),
+        Vec3::new(0.5, 0.5, 0.5),
+        Vec3::new(0.5, -0.5, 0.5),
+        Vec3::new(0.5, -0.5, -0.5),
+    ],
+    // Left (-X face)
+    [
+        Vec3::new(-0.5, 0.5, 0.5),
+        Vec3::new(-0.5, 0.5, -0.5),
+        Vec3::new(-0.5, -0.5, -0.5),
+        Vec3::new(-0.5, -0.5, 0.5),
+    ],
+    // Up (+Y face) - Top-Left from above is Back-Left
+    [
+        Vec3::new(-0.5, 0.5, 0.5),
+        Vec3::new(0.5, 0.5, 0.5),
+        Vec3::new(0.5, 0.5, -0.5),
+        Vec3::new(-0.5, 0.5, -0.5),
+    ],
+    // Down (-Y face) - Top-Left from below is Front-Left
+    [
+        Vec3::new(-0.5, -0.5, -0.5),
+        Vec3::new(0.5, -0.5, -0.5),
+        Vec3::new(0.5, -0.5, 0.5),
+        Vec3::new(-0.5, -0.5, 0.5),
+    ],
+    // Forward (+Z face)
+    [
+        Vec3::new(0.5, 0.5, 0.5),
+        Vec3::new(-0.5, 0.5, 0.5),
+        Vec3::new(-0.5, -0.5, 0.5),
+        Vec3::new(0.5, -0.5, 0.5),
+    ],
+    // Back (-Z face)
+    [
+        Vec3::new(-0.5, 0.5, -0.5),
+        Vec3::new(0.5, 0.5, -0.5),
+        Vec3::new(0.5, -0.5, -0.5),
+        Vec3::new(-0.5, -0.5, -0.5),
+    ],
+];
+
+// Normals for each face direction
+pub const FACE_NORMALS: [Vec3; 6] = [
+    Vec3::X,
+    Vec3::NEG_X,
+    Vec3::Y,
+    Vec3::NEG_Y,
+    Vec3::Z,
+    Vec3::NEG_Z,
+];
+
+/// Whole side of a block, as a rectangle of [`ModelBox::side_rect`]
+pub const FULL_SIDE: [Vec2; 2] = [Vec2::splat(-0.5), Vec2::splat(0.5)];
+
+/// Boxes are given in sixteenths of a block
+const UNITS: f32 = 16.0;
+
+/// Box of a model, from its corner with the lowest coordinates to the opposite one
+///
+/// Coordinates are in sixteenths of a block, from `(0, 0, 0)` at the -X -Y -Z corner of the block
+/// to `(16, 16, 16)` at the opposite corner.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModelBox {
+    pub from: [f32; 3],
+    pub to: [f32; 3],
+}
+
+impl ModelBox {
+    pub const FULL: ModelBox = ModelBox::new([0.0; 3], [UNITS; 3]);
+
+    pub const fn new(from: [f32; 3], to: [f32; 3]) -> Self {
+        Self { from, to }
+    }
+
+    /// Corner with the lowest coordinates, in blocks from the center of the block
+    pub fn min(&self) -> Vec3 {
+        Vec3::from_array(self.from) / UNITS - 0.5
+    }
+
+    /// Corner with the highest coordinates, in blocks from the center of the block
+    pub fn max(&self) -> Vec3 {
+        Vec3::from_array(self.to) / UNITS - 0.5
+    }
+
+    /// Whether the box is inside of the block, with its corners in order
+    pub fn is_valid(&self) -> bool {
+        (0..3).all(|axis| 0.0 <= self.from[axis] && self.from[axis] <= self.to[axis])
+            && self.to.iter().all(|to| *to <= UNITS)
+    }
+
+    /// Whether `point`, in blocks from the center of the block, is inside of the box
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(self.min()).all() && point.cmple(self.max()).all()
+    }
+
+    /// Corners of the side of the box towards a direction, in the order of [`FACE_CORNERS`]
+    pub fn face_corners(&self, direction_index: usize) -> [Vec3; 4] {
+        let (min, max) = (self.min(), self.max());
+
+        FACE_CORNERS[direction_index].map(|corner| Vec3::select(corner.cmpgt(Vec3::ZERO), max, min))
+    }
+
+    /// Position of the side of the box towards a direction, along the axis of that direction
+    fn side_position(&self, direction_index: usize) -> f32 {
+        let normal = FACE_NORMALS[direction_index];
+        let side = if normal.element_sum() > 0.0 {
+            self.max()
+        } else {
+            self.min()
+        };
+
+        (side * normal.abs()).element_sum()
+    }
+
+    /// Whether the side of the box towards a direction is on the side of the block
+    pub fn touches_side(&self, direction_index: usize) -> bool {
+        self.side_position(direction_index) * FACE_NORMALS[direction_index].element_sum() == 0.5
+    }
+
+    /// Rectangle taken by the side of the box towards a direction, along the two other axes in
+    /// the order X, Y, Z
+    pub fn side_rect(&self, direction_index: usize) -> [Vec2; 2] {
+        let flat = |corner: Vec3| match FACE_NORMALS[direction_index].abs() {
+            Vec3::X => Vec2::new(corner.y, corner.z),
+            Vec3::Y => Vec2::new(corner.x, corner.z),
+            _ => Vec2::new(corner.x, corner.y),
+        };
+
+        [flat(self.min()), flat(self.max())]
+    }
+
+    /// Whether the side of the box towards a direction is covered by the other boxes of the same
+    /// model pressed against it
+    pub fn is_side_hidden_by(&self, direction_index: usize, boxes: &[ModelBox]) -> bool {
+        let position = self.side_position(direction_index);
+        let covering: Vec<[Vec2; 2]> = boxes
+            .iter()
+            .filter(|other| *other != self && other.side_position(direction_index ^ 1) == position)
+            .map(|other| other.side_rect(direction_index ^ 1))
+            .collect();
+
+        !covering.is_empty() && rect_covered(self.side_rect(direction_index), &covering)
+    }
+
+    /// Box turned around the vertical axis of the block by quarter turns, from +Z towards -X
+    fn rotate_y(self, quarter_turns: u8) -> Self {
+        (0..quarter_turns % 4).fold(self, |model_box, _| {
+            let [from_x, from_y, from_z] = model_box.from;
+            let [to_x, to_y, to_z] = model_box.to;
+            ModelBox::new([UNITS - to_z, from_y, from_x], [UNITS - from_z, to_y, to_x])
+        })
+    }
+
+    /// Box turned upside down, the top of the block going to its bottom
+    fn flip_y(self) -> Self {
+        let mut flipped = self;
+        flipped.from[1] = UNITS - self.to[1];
+        flipped.to[1] = UNITS - self.from[1];
+        flipped
+    }
+}
+
+/// Shape of a block, in the data file
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum BlockModel {
+    /// A full block
+    #[default]
+    Cube,
+    /// Bottom half of a block, or its top half when the `half` property is `top`
+    Slab,
+    /// A slab with a step on its back half, turned by the `facing` property and upside down when
+    /// the `half` property is `top`
+    Stairs,
+    /// A post in the middle of the block, with bars going to the fences and full opaque blocks
+    /// next to it
+    Fence,
+    /// Two planes crossing along the diagonals of the block, for plants
+    Cross,
+    /// Boxes given in the data file, turned by the `facing` property
+    Elements(Vec<ModelBox>),
+}
+
+impl BlockModel {
+    pub fn is_cube(&self) -> bool {
+        *self == BlockModel::Cube
+    }
+
+    /// Boxes of a block of this model in `state`, `connects` telling whether a fence connects to
+    /// the block next to it in a direction
+    ///
+    /// Plants have a single box around their planes, that players can aim at.
+    pub fn boxes(
+        &self,
+        definition: &BlockDefinition,
+        state: BlockState,
+        connects: impl Fn(Direction) -> bool,
+    ) -> Vec<ModelBox> {
+        let top_half = definition.property(state, HALF_PROPERTY) == Some("top");
+        let turns = definition.facing_turns(state);
+
+        match self {
+            BlockModel::Cube => vec![ModelBox::FULL],
+            BlockModel::Slab => {
+                let slab = ModelBox::new([0.0; 3], [16.0, 8.0, 16.0]);
+                vec![if top_half { slab.flip_y() } else { slab }]
+            }
+            BlockModel::Stairs => [
+                ModelBox::new([0.0; 3], [16.0, 8.0, 16.0]),
+                ModelBox::new([0.0, 8.0, 0.0], [16.0, 16.0, 8.0]),
+            ]
+            .into_iter()
+            .map(|model_box| {
+                let model_box = model_box.rotate_y(turns);
+                if top_half {
+                    model_box.flip_y()
+                } else {
+                    model_box
+                }
+            })
+            .collect(),
+            BlockModel::Fence => {
+                let mut boxes = vec![ModelBox::new([6.0, 0.0, 6.0], [10.0, 16.0, 10.0])];
+                // Bar towards +Z, turned to each side the fence connects to
+                let bar = ModelBox::new([7.0, 6.0, 10.0], [9.0, 15.0, 16.0]);
+                for turns in 0..4 {
+                    if connects(Direction::Forward.rotate_y(turns)) {
+                        boxes.push(bar.rotate_y(turns));
+                    }
+                }
+                boxes
+            }
+            BlockModel::Cross => vec![ModelBox::new([2.0, 0.0, 2.0], [14.0, 13.0, 14.0])],
+            BlockModel::Elements(boxes) => boxes
+                .iter()
+                .map(|model_box| model_box.rotate_y(turns))
+                .collect(),
+        }
+    }
+}
+
+/// Whether a fence connects to `neighbor`, another fence or a full opaque block
+pub fn fence_connects_to(registry: &BlockRegistry, neighbor: Block) -> bool {
+    let definition = registry.get(neighbor.voxel_type);
+
+    definition.model == BlockModel::Fence
+        || (definition.model.is_cube()
+            && definition.solid
+            && definition.render == RenderCategory::Opaque)
+}
+
+/// Boxes of `block`, `neighbor` giving the blocks next to it for the fences
+pub fn block_boxes(
+    registry: &BlockRegistry,
+    block: Block,
+    neighbor: impl Fn(Direction) -> Option<Block>,
+) -> Vec<ModelBox> {
+    let definition = registry.get(block.voxel_type);
+
+    definition
+        .model
+        .boxes(definition, block.state, |direction| {
+            neighbor(direction).is_some_and(|neighbor| fence_connects_to(registry, neighbor))
+        })
+}
+
+/// Whether the side of `block` towards a direction covers all of `rect`, a rectangle of
+/// [`ModelBox::side_rect`]
+///
+/// Fences are taken without their bars, and plants never cover anything.
+pub fn covers_side(
+    registry: &BlockRegistry,
+    block: Block,
+    direction_index: usize,
+    rect: [Vec2; 2],
+) -> bool {
+    match registry.get(block.voxel_type).model {
+        BlockModel::Cube => true,
+        BlockModel::Cross => false,
+        _ => {
+            let sides: Vec<[Vec2; 2]> = block_boxes(registry, block, |_| None)
+                .iter()
+                .filter(|model_box| model_box.touches_side(direction_index))
+                .map(|model_box| model_box.side_rect(direction_index))
+                .collect();
+
+            !sides.is_empty() && rect_covered(rect, &sides)
+        }
+    }
+}
+
+/// Whether `rect` is entirely inside of the union of `covering`, rectangles being given by their
+/// lowest and highest corners
+///
+/// The rectangle is cut along the edges of the covering ones, so that each piece is either fully
+/// covered or not at all and only its center has to be checked.
+fn rect_covered(rect: [Vec2; 2], covering: &[[Vec2; 2]]) -> bool {
+    let cuts = |axis: usize| {
+        let mut cuts: Vec<f32> = covering
+            .iter()
+            .flat_map(|other| [other[0][axis], other[1][axis]])
+            .filter(|cut| rect[0][axis] < *cut && *cut < rect[1][axis])
+            .chain([rect[0][axis], rect[1][axis]])
+            .collect();
+        cuts.sort_by(f32::total_cmp);
+        cuts.dedup();
+        cuts
+    };
+    let (columns, rows) = (cuts(0), cuts(1));
+
+    columns.windows(2).all(|column| {
+        rows.windows(2).all(|row| {
+            let center = Vec2::new(column[0] + column[1], row[0] + row[1]) / 2.0;
+            covering
+                .iter()
+                .any(|other| center.cmpge(other[0]).all() && center.cmple(other[1]).all())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(name: &str) -> Block {
+        Block::new(BlockRegistry::default().block_type(name).unwrap())
+    }
+
+    #[test]
+    fn test_slab_and_stairs_boxes() {
+        let registry = BlockRegistry::default();
+        let slab = block("stone_slab");
+        let definition = registry.get(slab.voxel_type);
+
+        let bottom = block_boxes(&registry, slab, |_| None);
+        assert_eq!(bottom, [ModelBox::new([0.0; 3], [16.0, 8.0, 16.0])]);
+        assert!(bottom[0].contains(Vec3::new(0.2, -0.3, 0.4)));
+        assert!(!bottom[0].contains(Vec3::new(0.0, 0.2, 0.0)));
+
+        let top = Block::with_state(
+            slab.voxel_type,
+            definition
+                .with_property(slab.state, HALF_PROPERTY, "top")
+                .unwrap(),
+        );
+        assert_eq!(
+            block_boxes(&registry, top, |_| None),
+            [ModelBox::new([0.0, 8.0, 0.0], [16.0; 3])]
+        );
+
+        // Facing east, the step is on the -X half
+        let stairs = block("stone_stairs");
+        let definition = registry.get(stairs.voxel_type);
+        let state = definition
+            .with_property(stairs.state, "facing", "east")
+            .unwrap();
+        let boxes = block_boxes(
+            &registry,
+            Block::with_state(stairs.voxel_type, state),
+            |_| None,
+        );
+        assert_eq!(boxes[1], ModelBox::new([0.0, 8.0, 0.0], [8.0, 16.0, 16.0]));
+    }
+
+    #[test]
+    fn test_fence_connections() {
+        let registry = BlockRegistry::default();
+        let fence = block("oak_fence");
+        let (stone, glass) = (block("stone"), block("glass"));
+
+        assert_eq!(block_boxes(&registry, fence, |_| None).len(), 1);
+
+        let boxes = block_boxes(&registry, fence, |direction| match direction {
+            Direction::Left => Some(fence),
+            Direction::Forward => Some(stone),
+            Direction::Right => Some(glass),
+            _ => None,
+        });
+        assert_eq!(boxes.len(), 3);
+        assert!(boxes.iter().any(|model_box| model_box.touches_side(1)));
+        assert!(boxes.iter().any(|model_box| model_box.touches_side(4)));
+        assert!(!boxes.iter().any(|model_box| model_box.touches_side(0)));
+    }
+
+    #[test]
+    fn test_covers_side() {
+        let registry = BlockRegistry::default();
+        let [stone, slab, stairs, fence, grass] = [
+            "stone",
+            "stone_slab",
+            "stone_stairs",
+            "oak_fence",
+            "tall_grass",
+        ]
+        .map(block);
+        let (right, up, down, forward, back) = (0, 2, 3, 4, 5);
+
+        assert!(covers_side(&registry, stone, up, FULL_SIDE));
+        // A bottom slab covers the bottom of the block, and half of its sides
+        assert!(covers_side(&registry, slab, down, FULL_SIDE));
+        assert!(!covers_side(&registry, slab, up, FULL_SIDE));
+        assert!(!covers_side(&registry, slab, right, FULL_SIDE));
+        let lower_half = [Vec2::splat(-0.5), Vec2::new(0.0, 0.5)];
+        assert!(covers_side(&registry, slab, right, lower_half));
+
+        // Both boxes of the stairs make up its back side
+        assert!(covers_side(&registry, stairs, back, FULL_SIDE));
+        assert!(!covers_side(&registry, stairs, forward, FULL_SIDE));
+
+        assert!(!covers_side(&registry, fence, down, FULL_SIDE));
+        assert!(!covers_side(&registry, grass, down, FULL_SIDE));
+    }
+
+    #[test]
+    fn test_hidden_sides_inside_model() {
+        let registry = BlockRegistry::default();
+        let boxes = block_boxes(&registry, block("stone_stairs"), |_| None);
+
+        // The bottom of the step lies on the slab, whose top is only half covered
+        assert!(boxes[1].is_side_hidden_by(3, &boxes));
+        assert!(!boxes[0].is_side_hidden_by(2, &boxes));
+    }
+
+    #[test]
+    fn test_rect_covered() {
+        let rect = [Vec2::ZERO, Vec2::splat(4.0)];
+
+        assert!(rect_covered(rect, &[[Vec2::splat(-1.0), Vec2::splat(5.0)]]));
+        assert!(rect_covered(
+            rect,
+            &[
+                [Vec2::ZERO, Vec2::new(2.0, 4.0)],
+                [Vec2::new(2.0, 0.0), Vec2::splat(4.0)],
+            ]
+        ));
+        assert!(!rect_covered(
+            rect,
+            &[
+                [Vec2::ZERO, Vec2::new(2.0, 4.0)],
+                [Vec2::new(2.0, 0.0), Vec2::new(4.0, 3.0)],
+            ]
+        ));
+    }
+}
@@ -0,0 +1,800 @@
+//! Blocks of the game, loaded from a data file instead of being hardcoded
+//!
+//! The server loads `assets/blocks.ron` with the world and sends the resulting registry to every
+//! client when it connects, so both sides agree on the numeric id of each block.
+
+use crate::voxel::block::{Block, BlockState, BlockType};
+use crate::voxel::block_entity::BlockEntityKind;
+use crate::voxel::block_model::{BlockModel, ModelBox};
+use crate::voxel::direction::Direction;
+use bevy::log::warn;
+use bevy::math::{IVec3, Vec3};
+use bevy::prelude::Resource;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+pub const BLOCKS_FILE: &str = "assets/blocks.ron";
+pub const AIR_BLOCK_NAME: &str = "air";
+
+/// Property turning the block so the top and bottom of its textures are on the given axis,
+/// with the values `x`, `y` and `z`
+pub const AXIS_PROPERTY: &str = "axis";
+/// Property turning the block so its front texture faces `south` (+Z), `west` (-X), `north`
+/// (-Z) or `east` (+X)
+pub const FACING_PROPERTY: &str = "facing";
+const FACING_VALUES: [&str; 4] = ["south", "west", "north", "east"];
+/// Property putting slabs and stairs in the `bottom` or `top` half of the block
+pub const HALF_PROPERTY: &str = "half";
+/// Property of fluid blocks, from `0` for a full source block to `7` for the thinnest flow
+pub const LEVEL_PROPERTY: &str = "level";
+pub const MAX_FLUID_LEVEL: u8 = 7;
+
+/// Registry used by the meshing, the terrain generation and the physics
+///
+/// Inserted when a world is loaded or when the server sends its own registry, and shared with
+/// the generation and meshing tasks.
+#[derive(Resource, Clone)]
+pub struct GameBlockRegistry {
+    pub registry: Arc<BlockRegistry>,
+}
+
+impl GameBlockRegistry {
+    pub fn new(registry: BlockRegistry) -> Self {
+        Self {
+            registry: Arc::new(registry),
+        }
+    }
+}
+
+/// Spritesheet index of each face of a block
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BlockTextures {
+    All(u16),
+    Column {
+        top: u16,
+        bottom: u16,
+        side: u16,
+    },
+    Faces {
+        left: u16,
+        right: u16,
+        bottom: u16,
+        top: u16,
+        back: u16,
+        front: u16,
+    },
+}
+
+impl BlockTextures {
+    pub fn face(&self, direction: &Direction) -> u16 {
+        match self {
+            BlockTextures::All(texture) => *texture,
+            BlockTextures::Column { top, bottom, side } => match direction {
+                Direction::Up => *top,
+                Direction::Down => *bottom,
+                _ => *side,
+            },
+            BlockTextures::Faces {
+                left,
+                right,
+                bottom,
+                top,
+                back,
+                front,
+            } => match direction {
+                Direction::Left => *left,
+                Direction::Right => *right,
+                Direction::Down => *bottom,
+                Direction::Up => *top,
+                Direction::Back => *back,
+                Direction::Forward => *front,
+            },
+        }
+    }
+}
+
+/// How a block is drawn, and whether the blocks behind it can be seen
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenderCategory {
+    /// Hides the faces of the blocks next to it and stops light
+    #[default]
+    Opaque,
+    /// Pixels are either fully opaque or fully transparent, like leaves or glass
+    Cutout,
+    /// Blended with what is behind it, like water or ice
+    ///
+    /// Translucent blocks go in a separate mesh whose faces are sorted back to front.
+    Translucent,
+}
+
+/// A named property of a block, stored in its [`BlockState`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockProperty {
+    pub name: String,
+    /// Possible values, the first one being the default
+    pub values: Vec<String>,
+}
+
+impl BlockProperty {
+    /// Bits taken in the state to store the index of a value
+    fn bits(&self) -> u32 {
+        usize::BITS - self.values.len().saturating_sub(1).leading_zeros()
+    }
+}
+
+/// Mask of the `bits` bits of a state from `shift` on, computed on 32 bits so that a property
+/// may end on the last bit of the state
+fn state_mask(shift: u32, bits: u32) -> u32 {
+    ((1 << bits) - 1) << shift
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockDefinition {
+    pub name: String,
+    pub textures: BlockTextures,
+    /// Players collide with solid blocks
+    pub solid: bool,
+    /// Faces of the blocks next to a block that isn't opaque are not culled, and light goes
+    /// through it
+    #[serde(default)]
+    pub render: RenderCategory,
+    /// Shape of the block, a full cube unless given
+    #[serde(default)]
+    pub model: BlockModel,
+    #[serde(default)]
+    pub hardness: f32,
+    /// Light level emitted by the block, from 0 to 15
+    #[serde(default)]
+    pub light_emission: u8,
+    /// Fluids are never solid, have a `level` property and are drawn in a translucent pass
+    #[serde(default)]
+    pub fluid: bool,
+    #[serde(default)]
+    pub properties: Vec<BlockProperty>,
+    /// Block entity created along with the block, for data that doesn't fit in its state
+    #[serde(default)]
+    pub block_entity: Option<BlockEntityKind>,
+}
+
+impl BlockDefinition {
+    pub fn air() -> Self {
+        Self {
+            name: AIR_BLOCK_NAME.to_string(),
+            textures: BlockTextures::All(0),
+            solid: false,
+            // Never drawn, but seen through
+            render: RenderCategory::Translucent,
+            model: BlockModel::Cube,
+            hardness: 0.0,
+            light_emission: 0,
+            fluid: false,
+            properties: Vec::new(),
+            block_entity: None,
+        }
+    }
+
+    /// Stands in for a block the world knows but the data file doesn't define anymore, so its
+    /// id isn't given to another block
+    fn missing(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            textures: BlockTextures::All(0),
+            solid: true,
+            render: RenderCategory::Opaque,
+            model: BlockModel::Cube,
+            hardness: 0.0,
+            light_emission: 0,
+            fluid: false,
+            properties: Vec::new(),
+            block_entity: None,
+        }
+    }
+
+    /// Whether the blocks behind this one can be seen, and light goes through it
+    ///
+    /// Blocks that aren't full cubes always are, as they don't fill their cell.
+    pub fn is_transparent(&self) -> bool {
+        self.render != RenderCategory::Opaque || !self.model.is_cube()
+    }
+
+    /// The property called `name`, with the position and size of its bits in the state
+    fn property_bits(&self, name: &str) -> Option<(&BlockProperty, u32, u32)> {
+        let mut shift = 0;
+
+        for property in &self.properties {
+            if property.name == name {
+                return Some((property, shift, property.bits()));
+            }
+            shift += property.bits();
+        }
+
+        None
+    }
+
+    /// Value of a property in `state`, `None` if the block has no such property
+    pub fn property(&self, state: BlockState, name: &str) -> Option<&str> {
+        let (property, shift, bits) = self.property_bits(name)?;
+        let index = (state.0 as u32 & state_mask(shift, bits)) >> shift;
+
+        property.values.get(index as usize).map(String::as_str)
+    }
+
+    /// `state` with a property changed, `None` if the block has no such property or value
+    pub fn with_property(&self, state: BlockState, name: &str, value: &str) -> Option<BlockState> {
+        let (property, shift, bits) = self.property_bits(name)?;
+        let index = property.values.iter().position(|v| v == value)? as u32;
+        let mask = state_mask(shift, bits);
+
+        Some(BlockState(
+            ((state.0 as u32 & !mask) | (index << shift)) as u16,
+        ))
+    }
+
+    /// Whether every bit of `state` holds a value of one of the properties
+    pub fn is_valid_state(&self, state: BlockState) -> bool {
+        let mut shift = 0;
+
+        for property in &self.properties {
+            let index = (state.0 as u32 & state_mask(shift, property.bits())) >> shift;
+            if index as usize >= property.values.len() {
+                return false;
+            }
+            shift += property.bits();
+        }
+
+        state.0 as u32 >> shift == 0
+    }
+
+    /// State of a block placed against a face with the normal `face_normal`, by a player
+    /// looking towards `look_direction`
+    ///
+    /// The axis follows the normal of the face, and the front faces the player. Slabs and stairs
+    /// placed under a block go in its top half.
+    pub fn placement_state(&self, face_normal: IVec3, look_direction: Vec3) -> BlockState {
+        let state = BlockState::default();
+
+        let half = if face_normal.y < 0 { "top" } else { "bottom" };
+        let state = self
+            .with_property(state, HALF_PROPERTY, half)
+            .unwrap_or(state);
+
+        let axis = if face_normal.x != 0 {
+            "x"
+        } else if face_normal.z != 0 {
+            "z"
+        } else {
+            "y"
+        };
+        let state = self
+            .with_property(state, AXIS_PROPERTY, axis)
+            .unwrap_or(state);
+
+        let facing = if look_direction.x.abs() > look_direction.z.abs() {
+            if look_direction.x > 0.0 {
+                "west"
+            } else {
+                "east"
+            }
+        } else if look_direction.z > 0.0 {
+            "north"
+        } else {
+            "south"
+        };
+        self.with_property(state, FACING_PROPERTY, facing)
+            .unwrap_or(state)
+    }
+
+    /// Side of the block, as drawn in its textures, that ends up on `direction` once the block
+    /// is turned by the orientation properties of `state`
+    pub fn local_direction(&self, state: BlockState, direction: Direction) -> Direction {
+        let direction = match self.property(state, AXIS_PROPERTY) {
+            Some("x") => match direction {
+                Direction::Left => Direction::Down,
+                Direction::Right => Direction::Up,
+                Direction::Down => Direction::Left,
+                Direction::Up => Direction::Right,
+                _ => direction,
+            },
+            Some("z") => match direction {
+                Direction::Back => Direction::Down,
+                Direction::Forward => Direction::Up,
+                Direction::Down => Direction::Back,
+                Direction::Up => Direction::Forward,
+                _ => direction,
+            },
+            _ => direction,
+        };
+
+        direction.rotate_y((4 - self.facing_turns(state)) % 4)
+    }
+
+    /// Quarter turns from the front facing south (+Z) to the `facing` property of `state`, going
+    /// as [`Direction::rotate_y`]
+    pub fn facing_turns(&self, state: BlockState) -> u8 {
+        self.property(state, FACING_PROPERTY)
+            .and_then(|facing| FACING_VALUES.iter().position(|value| *value == facing))
+            .unwrap_or(0) as u8
+    }
+
+    /// Level of a fluid in `state`, `None` for blocks that aren't fluids
+    pub fn fluid_level(&self, state: BlockState) -> Option<u8> {
+        if !self.fluid {
+            return None;
+        }
+
+        self.property(state, LEVEL_PROPERTY)?.parse().ok()
+    }
+
+    /// Spritesheet index of the face of a block in `state` seen from `direction`
+    pub fn face_texture(&self, state: BlockState, direction: Direction) -> u16 {
+        self.textures.face(&self.local_direction(state, direction))
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockRegistry {
+    /// Definitions, indexed by block id
+    blocks: Vec<BlockDefinition>,
+    ids: HashMap<String, BlockType>,
+}
+
+impl Default for BlockRegistry {
+    /// The blocks shipped with the game, with ids in the order of the data file
+    fn default() -> Self {
+        Self::parse(include_str!("../../assets/blocks.ron"), &[]).unwrap()
+    }
+}
+
+impl BlockRegistry {
+    /// Read the block data file, keeping the ids of the blocks in `known_names`
+    ///
+    /// `known_names` holds the name of each id already used by a world, index 0 being air.
+    pub fn load(path: &Path, known_names: &[String]) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?, known_names)
+    }
+
+    pub fn parse(source: &str, known_names: &[String]) -> io::Result<Self> {
+        let definitions: Vec<BlockDefinition> = ron::from_str(source).map_err(invalid_data)?;
+
+        Self::assign_ids(definitions, known_names)
+    }
+
+    fn assign_ids(definitions: Vec<BlockDefinition>, known_names: &[String]) -> io::Result<Self> {
+        if known_names
+            .first()
+            .is_some_and(|name| name != AIR_BLOCK_NAME)
+        {
+            return Err(invalid_data("block id 0 must be air"));
+        }
+
+        let mut slots: Vec<Option<BlockDefinition>> = vec![None; known_names.len().max(1)];
+        slots[0] = Some(BlockDefinition::air());
+
+        for definition in definitions {
+            if definition.name == AIR_BLOCK_NAME {
+                return Err(invalid_data("air is built in and can't be defined"));
+            }
+
+            let id = match known_names.iter().position(|name| *name == definition.name) {
+                Some(id) => id,
+                None => {
+                    slots.push(None);
+                    slots.len() - 1
+                }
+            };
+
+            if slots[id].is_some() {
+                return Err(invalid_data(format!(
+                    "block {} is defined twice",
+                    definition.name
+                )));
+            }
+            slots[id] = Some(definition);
+        }
+
+        let blocks = slots
+            .into_iter()
+            .enumerate()
+            .map(|(id, slot)| {
+                slot.unwrap_or_else(|| {
+                    warn!(
+                        "Block {} of the world is not defined anymore",
+                        known_names[id]
+                    );
+                    BlockDefinition::missing(&known_names[id])
+                })
+            })
+            .collect();
+
+        Self::from_definitions(blocks)
+    }
+
+    /// Build a registry from definitions already ordered by id, as sent by the server
+    pub fn from_definitions(blocks: Vec<BlockDefinition>) -> io::Result<Self> {
+        if blocks.len() > u16::MAX as usize + 1 {
+            return Err(invalid_data(format!("{} blocks defined", blocks.len())));
+        }
+        if blocks
+            .first()
+            .is_none_or(|block| block.name != AIR_BLOCK_NAME)
+        {
+            return Err(invalid_data("block id 0 must be air"));
+        }
+
+        let mut ids = HashMap::with_capacity(blocks.len());
+
+        for (id, block) in blocks.iter().enumerate() {
+            let state_bits: u32 = block.properties.iter().map(BlockProperty::bits).sum();
+            if state_bits > u16::BITS
+                || block
+                    .properties
+                    .iter()
+                    .any(|property| property.values.is_empty())
+            {
+                return Err(invalid_data(format!(
+                    "properties of block {} don't fit in its state",
+                    block.name
+                )));
+            }
+            if block.fluid
+                && (block.solid
+                    || block.render != RenderCategory::Translucent
+                    || !block.model.is_cube()
+                    || !is_level_property(block))
+            {
+                return Err(invalid_data(format!(
+                    "fluid {} must not be solid, must be a translucent cube and needs a level property from 0 to {}",
+                    block.name, MAX_FLUID_LEVEL
+                )));
+            }
+            if let BlockModel::Elements(boxes) = &block.model {
+                if boxes.is_empty() || !boxes.iter().all(ModelBox::is_valid) {
+                    return Err(invalid_data(format!(
+                        "boxes of block {} must be inside of it, from 0 to 16",
+                        block.name
+                    )));
+                }
+            }
+            if block.light_emission > 15 {
+                return Err(invalid_data(format!(
+                    "block {} emits light level {}",
+                    block.name, block.light_emission
+                )));
+            }
+            if ids
+                .insert(block.name.clone(), BlockType::from_id(id as u16))
+                .is_some()
+            {
+                return Err(invalid_data(format!(
+                    "block {} is defined twice",
+                    block.name
+                )));
+            }
+        }
+
+        Ok(Self { blocks, ids })
+    }
+
+    /// Definition of a block, unknown ids are treated as air
+    pub fn get(&self, block_type: BlockType) -> &BlockDefinition {
+        self.blocks
+            .get(block_type.id() as usize)
+            .unwrap_or(&self.blocks[0])
+    }
+
+    /// Whether the type of `block` is registered and its state only holds values of its properties
+    pub fn is_valid(&self, block: Block) -> bool {
+        self.blocks
+            .get(block.voxel_type.id() as usize)
+            .is_some_and(|definition| definition.is_valid_state(block.state))
+    }
+
+    pub fn block_type(&self, name: &str) -> Option<BlockType> {
+        self.ids.get(name).copied()
+    }
+
+    /// Every definition, indexed by block id
+    pub fn definitions(&self) -> &[BlockDefinition] {
+        &self.blocks
+    }
+
+    /// Name of every block, indexed by id, to be stored with the world
+    pub fn names(&self) -> Vec<String> {
+        self.blocks.iter().map(|block| block.name.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+/// Whether the level property of a fluid has every level in order
+fn is_level_property(block: &BlockDefinition) -> bool {
+    block
+        .properties
+        .iter()
+        .find(|property| property.name == LEVEL_PROPERTY)
+        .is_some_and(|property| {
+            property.values.len() == MAX_FLUID_LEVEL as usize + 1
+                && property
+                    .values
+                    .iter()
+                    .enumerate()
+                    .all(|(level, value)| *value == level.to_string())
+        })
+}
+
+fn invalid_data(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_registry() {
+        let registry = BlockRegistry::default();
+
+        assert_eq!(registry.block_type(AIR_BLOCK_NAME), Some(BlockType::AIR));
+        assert!(!registry.get(BlockType::AIR).solid);
+
+        let grass = registry.get(registry.block_type("grass").unwrap());
+        assert_eq!(grass.textures.face(&Direction::Up), 23);
+        assert_eq!(grass.textures.face(&Direction::Down), 9);
+        assert_eq!(grass.textures.face(&Direction::Left), 10);
+        assert!(!grass.is_transparent());
+
+        let render = |name| registry.get(registry.block_type(name).unwrap()).render;
+        assert_eq!(render("leaves"), RenderCategory::Cutout);
+        assert_eq!(render("water"), RenderCategory::Translucent);
+        assert!(registry.get(BlockType::AIR).is_transparent());
+    }
+
+    #[test]
+    fn test_known_ids_are_kept() {
+        let source = r#"[
+            (name: "stone", textures: All(50), solid: true),
+            (name: "glass", textures: All(49), solid: true, render: Cutout),
+            (name: "dirt", textures: All(9), solid: true),
+        ]"#;
+        let known_names: Vec<String> = ["air", "grass", "dirt", "stone"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+
+        let registry = BlockRegistry::parse(source, &known_names).unwrap();
+
+        assert_eq!(registry.block_type("dirt"), Some(BlockType::from_id(2)));
+        assert_eq!(registry.block_type("stone"), Some(BlockType::from_id(3)));
+        // New blocks go after the known ones
+        assert_eq!(registry.block_type("glass"), Some(BlockType::from_id(4)));
+        // Removed blocks keep their id
+        assert_eq!(registry.block_type("grass"), Some(BlockType::from_id(1)));
+        assert_eq!(registry.names()[..4], known_names[..]);
+    }
+
+    #[test]
+    fn test_block_properties() {
+        let registry = BlockRegistry::parse(
+            r#"[
+                (
+                    name: "door",
+                    textures: Faces(left: 1, right: 2, bottom: 3, top: 4, back: 5, front: 6),
+                    solid: true,
+                    properties: [
+                        (name: "facing", values: ["south", "west", "north", "east"]),
+                        (name: "open", values: ["false", "true"]),
+                    ],
+                ),
+            ]"#,
+            &[],
+        )
+        .unwrap();
+        let door = registry.get(registry.block_type("door").unwrap());
+
+        let state = BlockState::default();
+        assert_eq!(door.property(state, "facing"), Some("south"));
+        assert_eq!(door.property(state, "open"), Some("false"));
+        assert_eq!(door.property(state, "color"), None);
+
+        let state = door.with_property(state, "open", "true").unwrap();
+        let state = door.with_property(state, "facing", "west").unwrap();
+        assert_eq!(door.property(state, "facing"), Some("west"));
+        assert_eq!(door.property(state, "open"), Some("true"));
+        assert!(door.is_valid_state(state));
+        assert!(door.with_property(state, "open", "maybe").is_none());
+        assert!(!door.is_valid_state(BlockState(1 << 3)));
+
+        // Facing west, the front is on the -X side
+        assert_eq!(door.face_texture(state, Direction::Left), 6);
+        assert_eq!(door.face_texture(state, Direction::Forward), 2);
+        assert_eq!(door.face_texture(state, Direction::Up), 4);
+    }
+
+    #[test]
+    fn test_properties_filling_the_state() {
+        let property = |name: &str, count: usize| BlockProperty {
+            name: name.to_string(),
+            values: (0..count).map(|value| value.to_string()).collect(),
+        };
+        let block = |properties| BlockDefinition {
+            name: "sign".to_string(),
+            textures: BlockTextures::All(1),
+            solid: true,
+            properties,
+            ..BlockDefinition::air()
+        };
+
+        // One property taking all 16 bits
+        let wide = block(vec![property("text", 1 << 16)]);
+        let state = wide
+            .with_property(BlockState::default(), "text", "65535")
+            .unwrap();
+        assert_eq!(state, BlockState(u16::MAX));
+        assert_eq!(wide.property(state, "text"), Some("65535"));
+        assert!(wide.is_valid_state(state));
+
+        // A property with a single value, so no bits, after the 16 bits of the others
+        let full = block(vec![
+            property("low", 256),
+            property("high", 256),
+            property("fixed", 1),
+        ]);
+        let state = full
+            .with_property(BlockState::default(), "high", "255")
+            .unwrap();
+        assert_eq!(state, BlockState(0xff00));
+        assert_eq!(full.property(state, "fixed"), Some("0"));
+        assert_eq!(full.with_property(state, "fixed", "0"), Some(state));
+        assert!(full.is_valid_state(state));
+
+        assert!(BlockRegistry::from_definitions(vec![BlockDefinition::air(), full]).is_ok());
+        assert!(BlockRegistry::from_definitions(vec![
+            BlockDefinition::air(),
+            block(vec![property("low", 256), property("high", 512)]),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_log_axis() {
+        let registry = BlockRegistry::default();
+        let log = registry.get(registry.block_type("log").unwrap());
+        let top = log.textures.face(&Direction::Up);
+        let side = log.textures.face(&Direction::Left);
+
+        let upright = BlockState::default();
+        assert_eq!(log.face_texture(upright, Direction::Up), top);
+        assert_eq!(log.face_texture(upright, Direction::Left), side);
+
+        let along_x = log.with_property(upright, AXIS_PROPERTY, "x").unwrap();
+        assert_eq!(log.face_texture(along_x, Direction::Up), side);
+        assert_eq!(log.face_texture(along_x, Direction::Left), top);
+        assert_eq!(log.face_texture(along_x, Direction::Forward), side);
+    }
+
+    #[test]
+    fn test_invalid_registries() {
+        assert!(
+            BlockRegistry::parse(r#"[(name: "air", textures: All(0), solid: false)]"#, &[])
+                .is_err()
+        );
+        assert!(BlockRegistry::parse(
+            r#"[
+                (name: "dirt", textures: All(9), solid: true),
+                (name: "dirt", textures: All(9), solid: true),
+            ]"#,
+            &[]
+        )
+        .is_err());
+        assert!(BlockRegistry::parse("[]", &["dirt".to_string()]).is_err());
+        assert!(BlockRegistry::parse(
+            r#"[(name: "dirt", textures: All(9), solid: true, properties: [(name: "a", values: [])])]"#,
+            &[]
+        )
+        .is_err());
+        assert!(BlockRegistry::from_definitions(Vec::new()).is_err());
+        // Fluids need their levels and can't be walked on
+        assert!(BlockRegistry::parse(
+            r#"[(name: "water", textures: All(5), solid: false, fluid: true)]"#,
+            &[]
+        )
+        .is_err());
+        assert!(BlockRegistry::parse(
+            r#"[(
+                name: "water",
+                textures: All(5),
+                solid: true,
+                render: Translucent,
+                fluid: true,
+                properties: [(name: "level", values: ["0", "1", "2", "3", "4", "5", "6", "7"])],
+            )]"#,
+            &[]
+        )
+        .is_err());
+        // Fluids are drawn in the translucent mesh
+        assert!(BlockRegistry::parse(
+            r#"[(
+                name: "water",
+                textures: All(5),
+                solid: false,
+                fluid: true,
+                properties: [(name: "level", values: ["0", "1", "2", "3", "4", "5", "6", "7"])],
+            )]"#,
+            &[]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_block_models() {
+        let registry = BlockRegistry::parse(
+            r#"[
+                (name: "stone", textures: All(50), solid: true),
+                (name: "slab", textures: All(50), solid: true, model: Slab),
+                (
+                    name: "plate",
+                    textures: All(50),
+                    solid: false,
+                    model: Elements([(from: (1, 0, 1), to: (15, 1.5, 15))]),
+                ),
+            ]"#,
+            &[],
+        )
+        .unwrap();
+        let model = |name| &registry.get(registry.block_type(name).unwrap()).model;
+
+        assert_eq!(*model("stone"), BlockModel::Cube);
+        assert_eq!(*model("slab"), BlockModel::Slab);
+        assert_eq!(
+            *model("plate"),
+            BlockModel::Elements(vec![ModelBox::new([1.0, 0.0, 1.0], [15.0, 1.5, 15.0])])
+        );
+        // Light goes through the blocks that don't fill their cell
+        assert!(!registry
+            .get(registry.block_type("stone").unwrap())
+            .is_transparent());
+        assert!(registry
+            .get(registry.block_type("slab").unwrap())
+            .is_transparent());
+
+        // Boxes must be inside of the block
+        assert!(BlockRegistry::parse(
+            r#"[(name: "plate", textures: All(50), solid: true, model: Elements([(from: (0, 0, 0), to: (17, 1, 16))]))]"#,
+            &[]
+        )
+        .is_err());
+        assert!(BlockRegistry::parse(
+            r#"[(name: "plate", textures: All(50), solid: true, model: Elements([]))]"#,
+            &[]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_fluid_level() {
+        let registry = BlockRegistry::default();
+        let water = registry.get(registry.block_type("water").unwrap());
+
+        assert_eq!(water.fluid_level(BlockState::default()), Some(0));
+        let state = water
+            .with_property(BlockState::default(), LEVEL_PROPERTY, "5")
+            .unwrap();
+        assert_eq!(water.fluid_level(state), Some(5));
+        assert_eq!(
+            registry
+                .get(registry.block_type("stone").unwrap())
+                .fluid_level(state),
+            None
+        );
+    }
+}
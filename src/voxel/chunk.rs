@@ -1,11 +1,20 @@
 use crate::meshing::check_loading_world_ended;
+use crate::storage::level::LevelData;
 use crate::terrain::chunk_generation::TerrainGenSet;
-use crate::terrain::chunk_generation::{process_chunk_generation, queue_chunk_generation};
+use crate::terrain::chunk_generation::{
+    process_chunk_loading, queue_chunk_generation, run_generation_stages, ServerChunkPipeline,
+};
 use crate::terrain::meshing::{
-    check_server_loading_world_ended, clear_dirty_chunks, prepare_chunks, process_mesh_tasks,
-    queue_mesh_tasks, ChunkMeshingSet,
+    check_server_loading_world_ended, clear_dirty_sections, prepare_chunks, process_mesh_tasks,
+    queue_mesh_tasks, sort_translucent_faces, ChunkMeshingSet,
 };
-use crate::voxel::block::{Block, BlockType};
+use crate::voxel::block::Block;
+use crate::voxel::block_entity::BlockEntity;
+use crate::voxel::block_registry::{BlockRegistry, GameBlockRegistry};
+use crate::voxel::chunk_tracker::{evict_chunks_system, ChunkEvictionSettings, ChunkTracker};
+use crate::voxel::light::{propagate, ChunkLight, Light, LightChannel, LightUpdates, SectionLight};
+use crate::voxel::migration::migrate_chunk;
+use crate::voxel::palette::PalettedStorage;
 use crate::voxel::world::World;
 use crate::{ClientState, ServerState};
 use bevy::math::IVec3;
@@ -14,7 +23,9 @@ use bincode::config;
 use lazy_static::*;
 use lz4::block::{compress, decompress, CompressionMode};
 use serde::{Deserialize, Serialize};
-use serde_big_array::BigArray;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io;
 use std::sync::{RwLock, Weak};
 
 lazy_static! {
@@ -28,51 +39,220 @@ lazy_static! {
 pub const CHUNK_SIZE: i32 = 16;
 pub const CHUNK_HEIGHT: i32 = 256;
 
+/// Starts every serialized chunk, followed by the format version
+pub const CHUNK_MAGIC: [u8; 4] = *b"VXCH";
+/// Bump when the serialized layout of [`Chunk`] changes, and add a migration for the old one
+pub const CHUNK_FORMAT_VERSION: u16 = 1;
+
 pub type CompressedChunk = Vec<u8>;
-pub type ChunkData = [Block; (CHUNK_SIZE * CHUNK_SIZE * CHUNK_HEIGHT) as usize];
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Height of a section, the part of a chunk that is stored and meshed on its own
+pub const SECTION_SIZE: i32 = 16;
+pub const SECTION_COUNT: usize = (CHUNK_HEIGHT / SECTION_SIZE) as usize;
+/// Number of blocks in a section
+pub const SECTION_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * SECTION_SIZE) as usize;
+
+/// A 16 block high slice of a chunk
+///
+/// Sections that only hold air are not stored at all, see [`Chunk::section`].
+#[derive(Clone, Debug)]
+pub struct ChunkSection {
+    voxels: PalettedStorage,
+    /// Number of blocks that are not air
+    block_count: u16,
+}
+
+impl ChunkSection {
+    fn new() -> Self {
+        Self {
+            voxels: PalettedStorage::new(SECTION_VOLUME, Block::new_empty()),
+            block_count: 0,
+        }
+    }
+
+    /// Index of a voxel in the section, `coordinate.y` being relative to the bottom of the section
+    pub fn get_index(coordinate: &IVec3) -> usize {
+        (coordinate.y * CHUNK_SIZE * CHUNK_SIZE + coordinate.z * CHUNK_SIZE + coordinate.x) as usize
+    }
+
+    pub fn get(&self, coordinate: &IVec3) -> Block {
+        self.voxels.get(Self::get_index(coordinate))
+    }
+
+    /// Set a voxel, returning the one it replaced
+    pub fn set(&mut self, coordinate: &IVec3, block: Block) -> Block {
+        let previous = self.voxels.set(Self::get_index(coordinate), block);
+
+        if previous.is_air() && !block.is_air() {
+            self.block_count += 1;
+        } else if !previous.is_air() && block.is_air() {
+            self.block_count -= 1;
+        }
+
+        previous
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.block_count == 0
+    }
+
+    pub fn voxels(&self) -> &PalettedStorage {
+        &self.voxels
+    }
+}
+
+impl Serialize for ChunkSection {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.voxels.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChunkSection {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let voxels = PalettedStorage::deserialize(deserializer)?;
+
+        if voxels.len() != SECTION_VOLUME {
+            return Err(serde::de::Error::custom(format!(
+                "chunk section holds {} blocks",
+                voxels.len()
+            )));
+        }
+
+        Ok(Self {
+            block_count: voxels.iter().filter(|block| !block.is_air()).count() as u16,
+            voxels,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Chunk {
-    #[serde(with = "BigArray")]
-    pub voxels: ChunkData,
+    /// Sections from the bottom of the chunk up, `None` when the section is all air
+    sections: [Option<ChunkSection>; SECTION_COUNT],
+    /// Light of each section, including the ones that are all air
+    light: [SectionLight; SECTION_COUNT],
+    /// Extra data of some blocks, by local position
+    block_entities: HashMap<IVec3, BlockEntity>,
     pub pos: IVec3,
 
+    /// Loaded chunks around this one: left (-X), right (+X), back (-Z), front (+Z), below (-Y)
+    /// and above (+Y), so `i ^ 1` is the opposite side of `i`
     #[serde(skip)]
-    pub neighbors: [Weak<RwLock<Chunk>>; 4],
+    pub neighbors: [Weak<RwLock<Chunk>>; 6],
 }
 
-impl Default for Chunk {
-    fn default() -> Chunk {
-        Chunk {
-            voxels: [Block::new_empty(); (CHUNK_SIZE * CHUNK_SIZE * CHUNK_HEIGHT) as usize],
-            pos: IVec3::default(),
-            neighbors: [Weak::new(), Weak::new(), Weak::new(), Weak::new()],
+impl Chunk {
+    pub fn new(pos: IVec3) -> Self {
+        Self {
+            pos,
+            ..Default::default()
         }
     }
-}
 
-impl Chunk {
-    pub fn from_compressed(bytes: &CompressedChunk) -> Self {
-        let decompressed = decompress(bytes, None).unwrap();
+    pub fn from_compressed(bytes: &CompressedChunk, registry: &BlockRegistry) -> Self {
+        Self::try_from_compressed(bytes, registry).unwrap()
+    }
 
-        bincode::serde::decode_from_slice(&decompressed, config::standard())
-            .unwrap()
-            .0
+    /// Decode a chunk written by [`Chunk::compress`], upgrading it if it was written by an
+    /// older version with the blocks of `registry`
+    pub fn try_from_compressed(bytes: &[u8], registry: &BlockRegistry) -> io::Result<Self> {
+        let decompressed = decompress(bytes, None)?;
+
+        // Chunks without the header were written before the format was versioned
+        let Some(header) = decompressed.strip_prefix(&CHUNK_MAGIC) else {
+            return migrate_chunk(0, &decompressed, registry);
+        };
+
+        if header.len() < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated chunk header",
+            ));
+        }
+        let version = u16::from_le_bytes([header[0], header[1]]);
+        let body = &header[2..];
+
+        match version.cmp(&CHUNK_FORMAT_VERSION) {
+            Ordering::Equal => bincode::serde::decode_from_slice(body, config::standard())
+                .map(|(chunk, _)| chunk)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Ordering::Less => migrate_chunk(version, body, registry),
+            Ordering::Greater => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "chunk format version {} is newer than the supported version {}",
+                    version, CHUNK_FORMAT_VERSION
+                ),
+            )),
+        }
     }
 
     pub fn compress(&self) -> CompressedChunk {
-        let data = bincode::serde::encode_to_vec(self, config::standard()).unwrap();
+        let mut data = CHUNK_MAGIC.to_vec();
+        data.extend_from_slice(&CHUNK_FORMAT_VERSION.to_le_bytes());
+        bincode::serde::encode_into_std_write(self, &mut data, config::standard()).unwrap();
 
         compress(&data, Some(CompressionMode::HIGHCOMPRESSION(12)), true).unwrap()
     }
 
+    /// Approximate memory used by the chunk, in bytes
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + self
+                .sections
+                .iter()
+                .flatten()
+                .map(|section| section.voxels.heap_size())
+                .sum::<usize>()
+            + self
+                .light
+                .iter()
+                .map(SectionLight::heap_size)
+                .sum::<usize>()
+            + self.block_entities.capacity() * size_of::<(IVec3, BlockEntity)>()
+    }
+
+    pub fn block_entity(&self, local_coordinate: &IVec3) -> Option<&BlockEntity> {
+        self.block_entities.get(local_coordinate)
+    }
+
+    pub fn block_entity_mut(&mut self, local_coordinate: &IVec3) -> Option<&mut BlockEntity> {
+        self.block_entities.get_mut(local_coordinate)
+    }
+
+    /// Block entities of the chunk, by local position
+    pub fn block_entities(&self) -> impl Iterator<Item = (&IVec3, &BlockEntity)> {
+        self.block_entities.iter()
+    }
+
+    /// Attach a block entity to the block at a local position, returning the one it replaced
+    pub fn set_block_entity(
+        &mut self,
+        local_coordinate: IVec3,
+        block_entity: BlockEntity,
+    ) -> Option<BlockEntity> {
+        if !Self::is_in_chunk(&local_coordinate) {
+            return None;
+        }
+
+        self.block_entities.insert(local_coordinate, block_entity)
+    }
+
+    pub fn remove_block_entity(&mut self, local_coordinate: &IVec3) -> Option<BlockEntity> {
+        self.block_entities.remove(local_coordinate)
+    }
+
     pub fn set_neighbor(&mut self, index: usize, chunk: Weak<RwLock<Chunk>>) {
         self.neighbors[index] = chunk;
     }
 
-    pub fn get_index(coordinate: &IVec3) -> usize {
-        (coordinate.z * CHUNK_SIZE * CHUNK_HEIGHT + coordinate.y * CHUNK_SIZE + coordinate.x)
-            as usize
+    /// Index of the section holding the voxels at height `y`
+    pub fn section_index(y: i32) -> usize {
+        (y / SECTION_SIZE) as usize
+    }
+
+    pub fn section(&self, section_index: usize) -> Option<&ChunkSection> {
+        self.sections[section_index].as_ref()
     }
 
     pub fn is_in_chunk(coordinate: &IVec3) -> bool {
@@ -84,71 +264,314 @@ impl Chunk {
             && coordinate.z < CHUNK_SIZE
     }
 
+    /// Voxel at a coordinate inside of this chunk
+    pub fn voxel_at(&self, coordinate: &IVec3) -> Block {
+        match self.section(Self::section_index(coordinate.y)) {
+            Some(section) => section.get(&(coordinate.with_y(coordinate.y % SECTION_SIZE))),
+            None => Block::new_empty(),
+        }
+    }
+
+    /// Set a voxel inside of this chunk without marking anything to be remeshed, returning the
+    /// one it replaced
+    pub fn set_voxel(&mut self, coordinate: &IVec3, block: Block) -> Block {
+        let section_slot = &mut self.sections[Self::section_index(coordinate.y)];
+        let section_coordinate = coordinate.with_y(coordinate.y % SECTION_SIZE);
+
+        let Some(section) = section_slot else {
+            if !block.is_air() {
+                section_slot
+                    .insert(ChunkSection::new())
+                    .set(&section_coordinate, block);
+            }
+            return Block::new_empty();
+        };
+
+        let previous = section.set(&section_coordinate, block);
+        if section.is_empty() {
+            *section_slot = None;
+        }
+
+        previous
+    }
+
+    /// Light of a voxel inside of this chunk
+    pub fn light_at(&self, coordinate: &IVec3) -> Light {
+        self.light[Self::section_index(coordinate.y)].get(ChunkSection::get_index(
+            &coordinate.with_y(coordinate.y % SECTION_SIZE),
+        ))
+    }
+
+    /// Set the light of a voxel inside of this chunk, without spreading it
+    pub fn set_light(&mut self, coordinate: &IVec3, light: Light) {
+        self.light[Self::section_index(coordinate.y)].set(
+            ChunkSection::get_index(&coordinate.with_y(coordinate.y % SECTION_SIZE)),
+            light,
+        );
+    }
+
+    /// Light the chunk from scratch on its own, the sky shining down the columns for which
+    /// `sky_above(x, z)` is true
+    ///
+    /// Light coming from the chunks around is added once they are next to each other in the
+    /// world, see
+    /// [`World::spread_light_between`](crate::voxel::world::World::spread_light_between).
+    pub fn compute_light(
+        &mut self,
+        registry: &BlockRegistry,
+        sky_above: impl Fn(i32, i32) -> bool,
+    ) {
+        // Lowest voxel of each column the sky shines on straight down
+        let mut floors = [[CHUNK_HEIGHT; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                if sky_above(x, z) {
+                    let ground = (0..CHUNK_HEIGHT)
+                        .rev()
+                        .find(|y| !self.voxel_at(&IVec3::new(x, *y, z)).is_air());
+                    floors[x as usize][z as usize] = ground.map_or(0, |y| y + 1);
+                }
+            }
+        }
+
+        // Sections above every floor are lit as a whole
+        let highest_floor = floors.iter().flatten().copied().max().unwrap_or(0);
+        let sky_sections_bottom = (highest_floor + SECTION_SIZE - 1) / SECTION_SIZE * SECTION_SIZE;
+        for (section_index, light) in self.light.iter_mut().enumerate() {
+            let section_bottom = section_index as i32 * SECTION_SIZE;
+            *light = SectionLight::Uniform(if section_bottom >= sky_sections_bottom {
+                Light::SKY
+            } else {
+                Light::DARK
+            });
+        }
+
+        let mut updates = LightUpdates::default();
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let floor = floors[x as usize][z as usize];
+                for y in floor..sky_sections_bottom {
+                    self.set_light(&IVec3::new(x, y, z), Light::SKY);
+                }
+
+                // The sky spreads sideways into the columns around whose floor is higher
+                let neighbor_floor = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                    .iter()
+                    .filter_map(|(dx, dz)| {
+                        floors
+                            .get((x + dx) as usize)
+                            .and_then(|column| column.get((z + dz) as usize))
+                    })
+                    .copied()
+                    .max()
+                    .unwrap_or(0);
+                for y in floor..neighbor_floor.max(floor + 1).min(CHUNK_HEIGHT) {
+                    updates.spread(IVec3::new(x, y, z));
+                }
+            }
+        }
+
+        for section_index in 0..SECTION_COUNT {
+            let Some(section) = self.section(section_index) else {
+                continue;
+            };
+            let emits_light = |block: &Block| registry.get(block.voxel_type).light_emission > 0;
+            if !section.voxels().palette().iter().any(emits_light) {
+                continue;
+            }
+
+            for index in 0..SECTION_VOLUME {
+                let coordinate = IVec3::new(
+                    index as i32 % CHUNK_SIZE,
+                    section_index as i32 * SECTION_SIZE + index as i32 / (CHUNK_SIZE * CHUNK_SIZE),
+                    index as i32 / CHUNK_SIZE % CHUNK_SIZE,
+                );
+                let block = self.voxel_at(&coordinate);
+                let emission = registry.get(block.voxel_type).light_emission;
+                if emission > 0 {
+                    let light = self.light_at(&coordinate);
+                    self.set_light(&coordinate, light.with(LightChannel::Block, emission));
+                    updates.spread(coordinate);
+                }
+            }
+        }
+
+        // Light reaching the borders is spread once the neighbors are loaded
+        propagate(&mut ChunkLight::new(self), updates, registry);
+
+        for light in &mut self.light {
+            light.compact();
+        }
+    }
+
     pub fn get_voxel(&self, coordinate: IVec3) -> Option<Block> {
         if Self::is_in_chunk(&coordinate) {
-            Some(self.voxels[Self::get_index(&coordinate)])
+            Some(self.voxel_at(&coordinate))
+        } else if coordinate.y < 0 {
+            // Below
+            self.neighbors[4].upgrade().map(|chunk| {
+                chunk
+                    .read()
+                    .unwrap()
+                    .voxel_at(&(coordinate + IVec3::new(0, CHUNK_HEIGHT, 0)))
+            })
+        } else if coordinate.y >= CHUNK_HEIGHT {
+            // Above
+            self.neighbors[5].upgrade().map(|chunk| {
+                chunk
+                    .read()
+                    .unwrap()
+                    .voxel_at(&(coordinate - IVec3::new(0, CHUNK_HEIGHT, 0)))
+            })
         } else if coordinate.x < 0 {
             // Left
             self.neighbors[0].upgrade().map(|chunk| {
-                chunk.read().unwrap().voxels
-                    [Self::get_index(&(coordinate + IVec3::new(CHUNK_SIZE, 0, 0)))]
+                chunk
+                    .read()
+                    .unwrap()
+                    .voxel_at(&(coordinate + IVec3::new(CHUNK_SIZE, 0, 0)))
             })
         } else if coordinate.x >= CHUNK_SIZE {
             // Right
             self.neighbors[1].upgrade().map(|chunk| {
-                chunk.read().unwrap().voxels
-                    [Self::get_index(&(coordinate - IVec3::new(CHUNK_SIZE, 0, 0)))]
+                chunk
+                    .read()
+                    .unwrap()
+                    .voxel_at(&(coordinate - IVec3::new(CHUNK_SIZE, 0, 0)))
             })
         } else if coordinate.z < 0 {
             // Back
             self.neighbors[2].upgrade().map(|chunk| {
-                chunk.read().unwrap().voxels
-                    [Self::get_index(&(coordinate + IVec3::new(0, 0, CHUNK_SIZE)))]
+                chunk
+                    .read()
+                    .unwrap()
+                    .voxel_at(&(coordinate + IVec3::new(0, 0, CHUNK_SIZE)))
             })
         } else if coordinate.z >= CHUNK_SIZE {
             // Front
             self.neighbors[3].upgrade().map(|chunk| {
-                chunk.read().unwrap().voxels
-                    [Self::get_index(&(coordinate - IVec3::new(0, 0, CHUNK_SIZE)))]
+                chunk
+                    .read()
+                    .unwrap()
+                    .voxel_at(&(coordinate - IVec3::new(0, 0, CHUNK_SIZE)))
             })
         } else {
             None
         }
     }
 
-    pub fn edit_voxel(&mut self, world: &World, local_coordinate: IVec3, new_type: BlockType) {
-        if Self::is_in_chunk(&local_coordinate)
-            && self.voxels[Self::get_index(&local_coordinate)].voxel_type != new_type
-        {
-            self.voxels[Self::get_index(&local_coordinate)].voxel_type = new_type;
-            self.update_chunk(world);
-            self.update_surrounding_voxels(world, local_coordinate);
+    /// Set a voxel and relight around it, marking the sections to remesh
+    ///
+    /// Returns the light updates reaching past the borders of the chunk, in world positions, for
+    /// [`World::edit_voxel`] to carry on in the chunks around.
+    pub fn edit_voxel(
+        &mut self,
+        world: &World,
+        local_coordinate: IVec3,
+        block: Block,
+        registry: &BlockRegistry,
+    ) -> LightUpdates {
+        if !Self::is_in_chunk(&local_coordinate) {
+            return LightUpdates::default();
+        }
+
+        let previous = self.set_voxel(&local_coordinate, block);
+
+        if previous.voxel_type != block.voxel_type {
+            // The block entity belonged to the replaced block
+            self.block_entities.remove(&local_coordinate);
         }
+
+        if previous == block {
+            return LightUpdates::default();
+        }
+
+        self.update_section(world, Self::section_index(local_coordinate.y));
+        self.update_surrounding_voxels(world, local_coordinate);
+
+        let mut volume = ChunkLight::new(self);
+        let mut updates = LightUpdates::default();
+        updates.voxel_changed(&mut volume, local_coordinate, registry);
+        let mut outside = propagate(&mut volume, updates, registry);
+
+        for coordinate in std::mem::take(&mut volume.changed) {
+            self.update_section(world, Self::section_index(coordinate.y));
+            self.update_surrounding_voxels(world, coordinate);
+        }
+
+        outside.translate(World::chunk_local_to_world(&self.pos, &IVec3::ZERO));
+        outside
     }
 
-    pub fn update_chunk(&mut self, world: &World) {
-        let dirty_chunks = &world.dirty_chunks;
-        dirty_chunks.write().unwrap().insert(self.pos);
+    /// Mark a section of this chunk to be remeshed
+    pub fn update_section(&self, world: &World, section_index: usize) {
+        world
+            .dirty_sections
+            .write()
+            .unwrap()
+            .insert(World::section_coord(&self.pos, section_index));
     }
 
+    /// Mark the sections next to a voxel to be remeshed, when the voxel is on their border
     pub fn update_surrounding_voxels(&mut self, world: &World, local_coordinate: IVec3) {
+        let section_index = Self::section_index(local_coordinate.y);
+
         if local_coordinate.x == 0 {
-            self.update_neighbor(world, 0);
+            self.update_neighbor(world, 0, section_index);
         } else if local_coordinate.x == CHUNK_SIZE - 1 {
-            self.update_neighbor(world, 1);
+            self.update_neighbor(world, 1, section_index);
         }
 
         if local_coordinate.z == 0 {
-            self.update_neighbor(world, 2);
+            self.update_neighbor(world, 2, section_index);
         } else if local_coordinate.z == CHUNK_SIZE - 1 {
-            self.update_neighbor(world, 3);
+            self.update_neighbor(world, 3, section_index);
+        }
+
+        // On a vertical edge the chunk diagonal to it shades its corner too
+        let x_border = match local_coordinate.x {
+            0 => Some(-1),
+            x if x == CHUNK_SIZE - 1 => Some(1),
+            _ => None,
+        };
+        let z_border = match local_coordinate.z {
+            0 => Some(-1),
+            z if z == CHUNK_SIZE - 1 => Some(1),
+            _ => None,
+        };
+        if let (Some(x), Some(z)) = (x_border, z_border) {
+            world
+                .dirty_sections
+                .write()
+                .unwrap()
+                .insert(World::section_coord(&self.pos, section_index) + IVec3::new(x, 0, z));
+        }
+
+        // Section coordinates keep counting across chunks, so this also reaches the chunks
+        // above and below
+        let section_coord = World::section_coord(&self.pos, section_index);
+        let section_y = local_coordinate.y % SECTION_SIZE;
+        if section_y == 0 {
+            world
+                .dirty_sections
+                .write()
+                .unwrap()
+                .insert(section_coord - IVec3::Y);
+        } else if section_y == SECTION_SIZE - 1 {
+            world
+                .dirty_sections
+                .write()
+                .unwrap()
+                .insert(section_coord + IVec3::Y);
         }
     }
 
-    pub fn update_neighbor(&mut self, world: &World, index: usize) {
+    pub fn update_neighbor(&mut self, world: &World, index: usize, section_index: usize) {
         if let Some(neighbor) = self.neighbors[index].upgrade() {
-            let mut neighbor = neighbor.write().unwrap();
-            neighbor.update_chunk(world);
+            neighbor
+                .read()
+                .unwrap()
+                .update_section(world, section_index);
         }
     }
 
@@ -188,6 +611,19 @@ impl Chunk {
 #[derive(Component)]
 pub struct ChunkEntity(pub IVec3);
 
+/// Entity holding the mesh of a chunk section, child of its [`ChunkEntity`]
+#[derive(Component)]
+pub struct ChunkSectionEntity(pub IVec3);
+
+/// Child of a [`ChunkSectionEntity`] holding the mesh of its translucent blocks and fluids, drawn
+/// in the translucent pass
+#[derive(Component)]
+pub struct SectionTranslucentEntity;
+
+/// Section entities of a chunk, spawned when a section first gets something to mesh
+#[derive(Component, Default)]
+pub struct ChunkSections(pub [Option<Entity>; SECTION_COUNT]);
+
 #[derive(Component)]
 pub struct ServerChunkEntity(pub IVec3);
 
@@ -200,14 +636,20 @@ impl Plugin for ClientChunkPlugin {
         )
         .add_systems(
             Update,
-            (prepare_chunks, queue_mesh_tasks, process_mesh_tasks)
+            (
+                prepare_chunks,
+                // The blocks of a remote server are only known once it sent its registry
+                queue_mesh_tasks.run_if(resource_exists::<GameBlockRegistry>),
+                process_mesh_tasks,
+                sort_translucent_faces,
+            )
                 .chain()
                 .in_set(ChunkMeshingSet)
                 .run_if(in_state(ClientState::LoadingWorld).or(in_state(ClientState::Playing))),
         )
         .add_systems(
             Last,
-            clear_dirty_chunks
+            clear_dirty_sections
                 .run_if(in_state(ClientState::LoadingWorld).or(in_state(ClientState::Playing))),
         );
     }
@@ -216,16 +658,154 @@ impl Plugin for ClientChunkPlugin {
 pub struct ServerChunkPlugin;
 impl Plugin for ServerChunkPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Last,
-            check_server_loading_world_ended.run_if(in_state(ServerState::LoadingWorld)),
-        )
-        .add_systems(
-            Update,
-            (queue_chunk_generation, process_chunk_generation)
-                .chain()
-                .in_set(TerrainGenSet)
-                .run_if(in_state(ServerState::LoadingWorld).or(in_state(ServerState::Running))),
+        app.init_resource::<ChunkTracker>()
+            .init_resource::<ChunkEvictionSettings>()
+            .init_resource::<ServerChunkPipeline>()
+            // Nothing to generate when the world failed to load, the server is going back to the
+            // menu
+            .add_systems(
+                Last,
+                check_server_loading_world_ended
+                    .run_if(in_state(ServerState::LoadingWorld).and(resource_exists::<LevelData>)),
+            )
+            .add_systems(
+                Update,
+                (
+                    queue_chunk_generation,
+                    process_chunk_loading,
+                    run_generation_stages,
+                )
+                    .chain()
+                    .in_set(TerrainGenSet)
+                    .run_if(
+                        in_state(ServerState::LoadingWorld)
+                            .or(in_state(ServerState::Running))
+                            .and(resource_exists::<LevelData>),
+                    ),
+            )
+            .add_systems(
+                Update,
+                evict_chunks_system
+                    .after(TerrainGenSet)
+                    .run_if(in_state(ServerState::Running)),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::block::{BlockState, BlockType};
+    use crate::voxel::block_entity::BlockEntity;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_air_sections_are_not_stored() {
+        let stone = BlockRegistry::default().block_type("stone").unwrap();
+        let mut chunk = Chunk::default();
+        let coordinate = IVec3::new(3, 40, 5);
+        assert!(chunk.section(Chunk::section_index(coordinate.y)).is_none());
+
+        chunk.set_voxel(&coordinate, Block::new(stone));
+        assert!(chunk.section(2).is_some());
+        assert_eq!(chunk.voxel_at(&coordinate).voxel_type, stone);
+        assert!((0..SECTION_COUNT)
+            .filter(|section| *section != 2)
+            .all(|section| chunk.section(section).is_none()));
+
+        chunk.set_voxel(&coordinate, Block::new_empty());
+        assert!(chunk.section(2).is_none());
+        assert_eq!(chunk.voxel_at(&coordinate).voxel_type, BlockType::AIR);
+    }
+
+    #[test]
+    fn test_edit_marks_only_touched_sections_dirty() {
+        let registry = BlockRegistry::default();
+        let stone = registry.block_type("stone").unwrap();
+        let world = World::new();
+        // Dark, so that the edits don't change the light of the sections around them
+        let mut chunk = Chunk::default();
+        chunk.compute_light(&registry, |_, _| false);
+        world.set_chunk(IVec3::ZERO, chunk, &registry);
+        world.dirty_sections.write().unwrap().clear();
+
+        // Inside of a section
+        world.edit_voxel(&IVec3::new(5, 37, 5), Block::new(stone), &registry);
+        assert_eq!(
+            *world.dirty_sections.read().unwrap(),
+            HashSet::from([IVec3::new(0, 2, 0)])
+        );
+        world.dirty_sections.write().unwrap().clear();
+
+        // On the border with the section below
+        world.edit_voxel(&IVec3::new(5, 32, 5), Block::new(stone), &registry);
+        assert_eq!(
+            *world.dirty_sections.read().unwrap(),
+            HashSet::from([IVec3::new(0, 2, 0), IVec3::new(0, 1, 0)])
         );
+        world.dirty_sections.write().unwrap().clear();
+
+        // On the vertical edge with the chunk diagonal to it
+        world.edit_voxel(&IVec3::new(0, 37, 0), Block::new(stone), &registry);
+        assert_eq!(
+            *world.dirty_sections.read().unwrap(),
+            HashSet::from([IVec3::new(0, 2, 0), IVec3::new(-1, 2, -1)])
+        );
+    }
+
+    #[test]
+    fn test_block_state_roundtrip() {
+        let registry = BlockRegistry::default();
+        let log_type = registry.block_type("log").unwrap();
+        let log = registry.get(log_type);
+        let state = log
+            .with_property(BlockState::default(), "axis", "z")
+            .unwrap();
+
+        let mut chunk = Chunk::default();
+        chunk.set_voxel(&IVec3::new(1, 2, 3), Block::with_state(log_type, state));
+        chunk.set_voxel(&IVec3::new(1, 3, 3), Block::new(log_type));
+
+        let chunk = Chunk::try_from_compressed(&chunk.compress(), &registry).unwrap();
+        let block = chunk.voxel_at(&IVec3::new(1, 2, 3));
+        assert_eq!(block.voxel_type, log_type);
+        assert_eq!(log.property(block.state(), "axis"), Some("z"));
+        assert_eq!(
+            log.property(chunk.voxel_at(&IVec3::new(1, 3, 3)).state(), "axis"),
+            Some("y")
+        );
+    }
+
+    #[test]
+    fn test_block_entities_follow_their_block() {
+        let registry = BlockRegistry::default();
+        let stone = registry.block_type("stone").unwrap();
+        let dirt = registry.block_type("dirt").unwrap();
+        let world = World::new();
+        world.set_chunk(IVec3::ZERO, Chunk::default(), &registry);
+
+        let pos = IVec3::new(4, 20, 6);
+        let sign = BlockEntity::Sign {
+            lines: [
+                "Hello".to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+            ],
+        };
+        world.edit_voxel(&pos, Block::new(stone), &registry);
+        assert!(world.set_block_entity(&pos, sign.clone()).is_none());
+
+        // Saved and sent with the chunk
+        let chunk = world.get_chunk(IVec3::ZERO).unwrap();
+        let decoded =
+            Chunk::try_from_compressed(&chunk.read().unwrap().compress(), &registry).unwrap();
+        assert_eq!(decoded.block_entity(&pos), Some(&sign));
+
+        // Kept when only the state changes, dropped with the block
+        world.edit_voxel(&pos, Block::new(stone), &registry);
+        assert_eq!(world.get_block_entity(&pos), Some(sign));
+        world.edit_voxel(&pos, Block::new(dirt), &registry);
+        assert_eq!(world.get_block_entity(&pos), None);
     }
 }
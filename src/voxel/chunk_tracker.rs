@@ -0,0 +1,245 @@
+use crate::storage::WorldStorage;
+use crate::voxel::world::GameWorld;
+use bevy::math::IVec3;
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+pub const DEFAULT_CHUNK_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+pub const EVICTION_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Resource)]
+pub struct ChunkEvictionSettings {
+    /// Memory, in bytes, the server can use for chunks before it starts evicting them
+    pub memory_budget: usize,
+}
+
+impl Default for ChunkEvictionSettings {
+    fn default() -> Self {
+        Self {
+            memory_budget: DEFAULT_CHUNK_MEMORY_BUDGET,
+        }
+    }
+}
+
+/// Keeps track of which clients have which chunks loaded, and when each chunk was last needed
+#[derive(Resource, Default)]
+pub struct ChunkTracker {
+    watchers: HashMap<IVec3, HashSet<u64>>,
+    last_used: HashMap<IVec3, u64>,
+    /// [`ServerChunkEntity`](crate::voxel::chunk::ServerChunkEntity) of each chunk loaded or
+    /// being loaded
+    entities: HashMap<IVec3, Entity>,
+    clock: u64,
+}
+
+impl ChunkTracker {
+    pub fn watch(&mut self, chunk_coord: IVec3, client_id: u64) {
+        self.watchers
+            .entry(chunk_coord)
+            .or_default()
+            .insert(client_id);
+        self.touch(chunk_coord);
+    }
+
+    pub fn unwatch(&mut self, chunk_coord: IVec3, client_id: u64) {
+        if let Some(watchers) = self.watchers.get_mut(&chunk_coord) {
+            watchers.remove(&client_id);
+
+            if watchers.is_empty() {
+                self.watchers.remove(&chunk_coord);
+            }
+        }
+        self.touch(chunk_coord);
+    }
+
+    pub fn remove_client(&mut self, client_id: u64) {
+        let chunks: Vec<IVec3> = self
+            .watchers
+            .iter()
+            .filter(|(_, watchers)| watchers.contains(&client_id))
+            .map(|(chunk_coord, _)| *chunk_coord)
+            .collect();
+
+        for chunk_coord in chunks {
+            self.unwatch(chunk_coord, client_id);
+        }
+    }
+
+    /// Mark a chunk as used right now
+    pub fn touch(&mut self, chunk_coord: IVec3) {
+        self.clock += 1;
+        self.last_used.insert(chunk_coord, self.clock);
+    }
+
+    pub fn is_needed(&self, chunk_coord: &IVec3) -> bool {
+        self.watchers.contains_key(chunk_coord)
+    }
+
+    pub fn entity(&self, chunk_coord: &IVec3) -> Option<Entity> {
+        self.entities.get(chunk_coord).copied()
+    }
+
+    pub fn set_entity(&mut self, chunk_coord: IVec3, entity: Entity) {
+        self.entities.insert(chunk_coord, entity);
+    }
+
+    /// Forget an evicted chunk, returning its entity to despawn
+    pub fn forget(&mut self, chunk_coord: &IVec3) -> Option<Entity> {
+        self.watchers.remove(chunk_coord);
+        self.last_used.remove(chunk_coord);
+        self.entities.remove(chunk_coord)
+    }
+
+    /// Chunks no client needs, least recently used first
+    pub fn eviction_candidates(&self, loaded_chunks: impl Iterator<Item = IVec3>) -> Vec<IVec3> {
+        let mut candidates: Vec<(u64, IVec3)> = loaded_chunks
+            .filter(|chunk_coord| !self.is_needed(chunk_coord))
+            .map(|chunk_coord| {
+                (
+                    self.last_used
+                        .get(&chunk_coord)
+                        .copied()
+                        .unwrap_or_default(),
+                    chunk_coord,
+                )
+            })
+            .collect();
+
+        candidates.sort_by_key(|(last_used, chunk_coord)| {
+            (*last_used, chunk_coord.x, chunk_coord.y, chunk_coord.z)
+        });
+        candidates
+            .into_iter()
+            .map(|(_, chunk_coord)| chunk_coord)
+            .collect()
+    }
+}
+
+/// Evict chunks no client needs once the chunks use more memory than the budget
+///
+/// Chunks are written back to storage before being evicted, a chunk that fails to save stays
+/// loaded.
+pub fn evict_chunks_system(
+    mut commands: Commands,
+    game_world: Res<GameWorld>,
+    storage: Res<WorldStorage>,
+    settings: Res<ChunkEvictionSettings>,
+    mut tracker: ResMut<ChunkTracker>,
+    mut timer: Local<Timer>,
+    time: Res<Time>,
+) {
+    timer.set_duration(EVICTION_INTERVAL);
+    timer.set_mode(TimerMode::Repeating);
+
+    if !timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let world = game_world.world.read().unwrap();
+    let (loaded_chunks, mut memory_usage) = {
+        let chunk_data_map = world.chunk_data_map.read().unwrap();
+        let memory_usage: usize = chunk_data_map
+            .values()
+            .map(|chunk| chunk.read().unwrap().memory_usage())
+            .sum();
+
+        (
+            chunk_data_map.keys().copied().collect::<Vec<_>>(),
+            memory_usage,
+        )
+    };
+
+    if memory_usage <= settings.memory_budget {
+        return;
+    }
+
+    let pending_generating_chunks = world.pending_generating_chunks.read().unwrap().clone();
+    let mut evicted = 0;
+
+    for chunk_coord in tracker.eviction_candidates(loaded_chunks.into_iter()) {
+        if memory_usage <= settings.memory_budget {
+            break;
+        }
+
+        if pending_generating_chunks.contains_key(&chunk_coord) {
+            continue;
+        }
+
+        if let Err(err) = storage.save_chunk(&world, &chunk_coord) {
+            error!(
+                "Failed to save chunk {:?} before evicting it: {}",
+                chunk_coord, err
+            );
+            continue;
+        }
+
+        if let Some(chunk) = world.remove_chunk(&chunk_coord) {
+            memory_usage -= chunk.read().unwrap().memory_usage();
+            evicted += 1;
+        }
+        if let Some(entity) = tracker.forget(&chunk_coord) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    debug!(
+        "Evicted {} chunks, chunks now use {} bytes",
+        evicted, memory_usage
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eviction_candidates_least_recently_used_first() {
+        let mut tracker = ChunkTracker::default();
+        let a = IVec3::new(0, 0, 0);
+        let b = IVec3::new(1, 0, 0);
+        let c = IVec3::new(2, 0, 0);
+        let never_used = IVec3::new(3, 0, 0);
+
+        tracker.watch(a, 1);
+        tracker.watch(b, 1);
+        tracker.watch(c, 2);
+        tracker.unwatch(b, 1);
+        tracker.unwatch(a, 1);
+
+        assert_eq!(
+            tracker.eviction_candidates([a, b, c, never_used].into_iter()),
+            vec![never_used, b, a]
+        );
+    }
+
+    #[test]
+    fn test_remove_client_releases_its_chunks() {
+        let mut tracker = ChunkTracker::default();
+        let shared = IVec3::new(0, 0, 0);
+        let own = IVec3::new(1, 0, 0);
+
+        tracker.watch(shared, 1);
+        tracker.watch(shared, 2);
+        tracker.watch(own, 1);
+        tracker.remove_client(1);
+
+        assert!(tracker.is_needed(&shared));
+        assert!(!tracker.is_needed(&own));
+    }
+
+    #[test]
+    fn test_forget_returns_entity() {
+        let mut tracker = ChunkTracker::default();
+        let chunk_coord = IVec3::new(0, 0, 0);
+        let entity = Entity::from_raw(7);
+
+        tracker.set_entity(chunk_coord, entity);
+        tracker.watch(chunk_coord, 1);
+        assert_eq!(tracker.entity(&chunk_coord), Some(entity));
+
+        assert_eq!(tracker.forget(&chunk_coord), Some(entity));
+        assert_eq!(tracker.entity(&chunk_coord), None);
+        assert_eq!(tracker.forget(&chunk_coord), None);
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Direction {
     Left,
     Right,
@@ -32,4 +32,33 @@ impl Direction {
             _ => unreachable!(),
         }
     }
+
+    /// Index of the direction, the inverse of [`Direction::from_index`]
+    #[inline]
+    pub fn index(&self) -> usize {
+        match self {
+            Direction::Right => 0,
+            Direction::Left => 1,
+            Direction::Up => 2,
+            Direction::Down => 3,
+            Direction::Forward => 4,
+            Direction::Back => 5,
+        }
+    }
+
+    /// Turn a horizontal direction by quarter turns, going from front (+Z) to left (-X), back
+    /// (-Z) and right (+X). Up and down are unchanged.
+    pub fn rotate_y(self, quarter_turns: u8) -> Direction {
+        const HORIZONTAL: [Direction; 4] = [
+            Direction::Forward,
+            Direction::Left,
+            Direction::Back,
+            Direction::Right,
+        ];
+
+        match HORIZONTAL.iter().position(|direction| *direction == self) {
+            Some(index) => HORIZONTAL[(index + quarter_turns as usize) % 4],
+            None => self,
+        }
+    }
 }
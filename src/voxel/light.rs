@@ -0,0 +1,541 @@
+//! Sky light and block light of the voxels
+//!
+//! Every voxel has two light levels from 0 to [`MAX_LIGHT`]: sky light, coming down from the
+//! sky, and block light, emitted by blocks like lava. Light spreads through transparent blocks,
+//! losing a level at each step, except sky light going straight down through air which stays at
+//! its maximum. Levels are computed with a flood fill when a chunk is generated, updated around
+//! the voxels that change, and baked into the vertex colors of the chunk meshes.
+//!
+//! Light crossing chunk borders is handled in two steps: [`propagate`] runs on whatever
+//! [`LightVolume`] it is given, a single chunk or the whole [`World`], and hands back the updates
+//! that reached the edges of the volume so they can be carried on in a larger one.
+
+use crate::voxel::block::Block;
+use crate::voxel::block_registry::BlockRegistry;
+use crate::voxel::chunk::{Chunk, SECTION_VOLUME};
+use crate::voxel::world::World;
+use bevy::math::IVec3;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+
+pub const MAX_LIGHT: u8 = 15;
+
+/// Brightness of voxels without any light, so that caves are dark but not black
+const MIN_BRIGHTNESS: f32 = 0.04;
+/// Brightness lost at each light level
+const BRIGHTNESS_FALLOFF: f32 = 0.8;
+
+const OFFSETS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LightChannel {
+    Sky,
+    Block,
+}
+
+impl LightChannel {
+    pub const ALL: [Self; 2] = [Self::Sky, Self::Block];
+}
+
+/// Sky light and block light of a voxel, packed in a byte
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Light(u8);
+
+impl Light {
+    pub const DARK: Light = Light(0);
+    /// Open sky, without any block light
+    pub const SKY: Light = Light(MAX_LIGHT << 4);
+
+    pub fn new(sky: u8, block: u8) -> Self {
+        Self((sky.min(MAX_LIGHT) << 4) | block.min(MAX_LIGHT))
+    }
+
+    pub fn sky(self) -> u8 {
+        self.0 >> 4
+    }
+
+    pub fn block(self) -> u8 {
+        self.0 & MAX_LIGHT
+    }
+
+    pub fn get(self, channel: LightChannel) -> u8 {
+        match channel {
+            LightChannel::Sky => self.sky(),
+            LightChannel::Block => self.block(),
+        }
+    }
+
+    pub fn with(self, channel: LightChannel, level: u8) -> Self {
+        match channel {
+            LightChannel::Sky => Self::new(level, self.block()),
+            LightChannel::Block => Self::new(self.sky(), level),
+        }
+    }
+
+    /// Color multiplier of the faces lit by this light, from a dim minimum to 1
+    pub fn brightness(self) -> f32 {
+        let level = self.sky().max(self.block());
+        MIN_BRIGHTNESS
+            + (1.0 - MIN_BRIGHTNESS) * BRIGHTNESS_FALLOFF.powi((MAX_LIGHT - level) as i32)
+    }
+}
+
+/// Light of the voxels of a chunk section, indexed like the section
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "SerializedSectionLight")]
+pub enum SectionLight {
+    /// Every voxel has the same light, like the open sky or solid rock
+    Uniform(Light),
+    Mixed(Vec<Light>),
+}
+
+/// Layout checked before being trusted as a [`SectionLight`], as it comes from disk or the
+/// network
+#[derive(Deserialize)]
+enum SerializedSectionLight {
+    Uniform(Light),
+    Mixed(Vec<Light>),
+}
+
+impl TryFrom<SerializedSectionLight> for SectionLight {
+    type Error = String;
+
+    fn try_from(light: SerializedSectionLight) -> Result<Self, Self::Error> {
+        match light {
+            SerializedSectionLight::Uniform(light) => Ok(Self::Uniform(light)),
+            SerializedSectionLight::Mixed(light) if light.len() == SECTION_VOLUME => {
+                Ok(Self::Mixed(light))
+            }
+            SerializedSectionLight::Mixed(light) => {
+                Err(format!("chunk section holds {} light levels", light.len()))
+            }
+        }
+    }
+}
+
+impl Default for SectionLight {
+    fn default() -> Self {
+        Self::Uniform(Light::SKY)
+    }
+}
+
+impl SectionLight {
+    pub fn get(&self, index: usize) -> Light {
+        match self {
+            Self::Uniform(light) => *light,
+            Self::Mixed(lights) => lights[index],
+        }
+    }
+
+    pub fn set(&mut self, index: usize, light: Light) {
+        match self {
+            Self::Uniform(uniform) if *uniform == light => {}
+            Self::Uniform(uniform) => {
+                let mut lights = vec![*uniform; SECTION_VOLUME];
+                lights[index] = light;
+                *self = Self::Mixed(lights);
+            }
+            Self::Mixed(lights) => lights[index] = light,
+        }
+    }
+
+    /// Go back to a single light level if every voxel has the same
+    pub fn compact(&mut self) {
+        if let Self::Mixed(lights) = self {
+            if lights.iter().all(|light| *light == lights[0]) {
+                *self = Self::Uniform(lights[0]);
+            }
+        }
+    }
+
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Self::Uniform(_) => 0,
+            Self::Mixed(lights) => lights.capacity(),
+        }
+    }
+}
+
+/// Voxels light spreads through
+pub trait LightVolume {
+    /// Block and light of a voxel, `None` if it is outside of the volume
+    fn voxel(&mut self, pos: IVec3) -> Option<(Block, Light)>;
+
+    fn set_light(&mut self, pos: IVec3, light: Light);
+}
+
+/// Light changes waiting to be spread
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LightUpdates {
+    /// Voxels whose light was removed from a channel, with the level they had
+    removed: Vec<(IVec3, LightChannel, u8)>,
+    /// Voxels whose light spreads to their neighbors
+    added: Vec<(IVec3, LightChannel)>,
+}
+
+impl LightUpdates {
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty()
+    }
+
+    /// Spread the light of a voxel to its neighbors
+    pub fn spread(&mut self, pos: IVec3) {
+        self.added
+            .extend(LightChannel::ALL.map(|channel| (pos, channel)));
+    }
+
+    /// Relight a voxel whose block changed, its light and the light passing through it being
+    /// recomputed
+    pub fn voxel_changed(
+        &mut self,
+        volume: &mut impl LightVolume,
+        pos: IVec3,
+        registry: &BlockRegistry,
+    ) {
+        let Some((block, light)) = volume.voxel(pos) else {
+            return;
+        };
+
+        for channel in LightChannel::ALL {
+            let level = light.get(channel);
+            if level > 0 {
+                self.removed.push((pos, channel, level));
+            }
+        }
+
+        let emission = registry.get(block.voxel_type).light_emission;
+        volume.set_light(pos, Light::new(0, emission));
+        if emission > 0 {
+            self.added.push((pos, LightChannel::Block));
+        }
+
+        // The voxel gets its light back from its neighbors if it lets light through
+        for offset in OFFSETS {
+            self.spread(pos + offset);
+        }
+    }
+
+    /// Move the updates of a chunk to world positions
+    pub fn translate(&mut self, offset: IVec3) {
+        for (pos, _, _) in &mut self.removed {
+            *pos += offset;
+        }
+        for (pos, _) in &mut self.added {
+            *pos += offset;
+        }
+    }
+}
+
+/// Light level a voxel gets from a neighbor at `level`, `offset` going from the neighbor to the
+/// voxel
+fn spread_level(channel: LightChannel, level: u8, offset: IVec3, block: Block) -> u8 {
+    if channel == LightChannel::Sky
+        && level == MAX_LIGHT
+        && offset == IVec3::NEG_Y
+        && block.is_air()
+    {
+        MAX_LIGHT
+    } else {
+        level.saturating_sub(1)
+    }
+}
+
+/// Spread light updates through a volume, returning the ones that need voxels outside of it
+///
+/// Removed light is taken away from every voxel it lit, then the light around the darkened
+/// voxels spreads back into them.
+pub fn propagate(
+    volume: &mut impl LightVolume,
+    updates: LightUpdates,
+    registry: &BlockRegistry,
+) -> LightUpdates {
+    let mut outside = LightUpdates::default();
+    let mut removed = VecDeque::from(updates.removed);
+    let mut added = VecDeque::from(updates.added);
+
+    while let Some((pos, channel, level)) = removed.pop_front() {
+        let mut reaches_outside = false;
+
+        for offset in OFFSETS {
+            let neighbor_pos = pos + offset;
+            let Some((block, light)) = volume.voxel(neighbor_pos) else {
+                reaches_outside = true;
+                continue;
+            };
+
+            let neighbor_level = light.get(channel);
+            if neighbor_level == 0 {
+                continue;
+            }
+
+            if neighbor_level <= spread_level(channel, level, offset, block) {
+                volume.set_light(neighbor_pos, light.with(channel, 0));
+                removed.push_back((neighbor_pos, channel, neighbor_level));
+
+                // Blocks emitting light keep their own
+                let emission = registry.get(block.voxel_type).light_emission;
+                if channel == LightChannel::Block && emission > 0 {
+                    volume.set_light(neighbor_pos, light.with(channel, emission));
+                    added.push_back((neighbor_pos, channel));
+                }
+            } else {
+                // Lit by something else, which spreads back into the darkened voxels
+                added.push_back((neighbor_pos, channel));
+            }
+        }
+
+        if reaches_outside {
+            outside.removed.push((pos, channel, level));
+        }
+    }
+
+    while let Some((pos, channel)) = added.pop_front() {
+        let Some((_, light)) = volume.voxel(pos) else {
+            outside.added.push((pos, channel));
+            continue;
+        };
+
+        let level = light.get(channel);
+        if level <= 1 {
+            continue;
+        }
+
+        let mut reaches_outside = false;
+
+        for offset in OFFSETS {
+            let neighbor_pos = pos + offset;
+            let Some((block, neighbor_light)) = volume.voxel(neighbor_pos) else {
+                reaches_outside = true;
+                continue;
+            };
+
+            if !registry.get(block.voxel_type).is_transparent() {
+                continue;
+            }
+
+            let neighbor_level = spread_level(channel, level, offset, block);
+            if neighbor_level > neighbor_light.get(channel) {
+                volume.set_light(neighbor_pos, neighbor_light.with(channel, neighbor_level));
+                added.push_back((neighbor_pos, channel));
+            }
+        }
+
+        if reaches_outside {
+            outside.added.push((pos, channel));
+        }
+    }
+
+    outside
+}
+
+/// The voxels of a chunk, by local position
+pub struct ChunkLight<'a> {
+    pub chunk: &'a mut Chunk,
+    /// Voxels whose light changed
+    pub changed: Vec<IVec3>,
+}
+
+impl<'a> ChunkLight<'a> {
+    pub fn new(chunk: &'a mut Chunk) -> Self {
+        Self {
+            chunk,
+            changed: Vec::new(),
+        }
+    }
+}
+
+impl LightVolume for ChunkLight<'_> {
+    fn voxel(&mut self, pos: IVec3) -> Option<(Block, Light)> {
+        Chunk::is_in_chunk(&pos).then(|| (self.chunk.voxel_at(&pos), self.chunk.light_at(&pos)))
+    }
+
+    fn set_light(&mut self, pos: IVec3, light: Light) {
+        self.chunk.set_light(&pos, light);
+        self.changed.push(pos);
+    }
+}
+
+/// The voxels of the loaded chunks, by world position
+///
+/// Chunks are locked one at a time, and their sections are marked to be remeshed as their light
+/// changes.
+pub struct WorldLight<'a> {
+    world: &'a World,
+    /// Last chunk used, as updates mostly stay in the same chunk
+    chunk: Option<(IVec3, Arc<RwLock<Chunk>>)>,
+}
+
+impl<'a> WorldLight<'a> {
+    pub fn new(world: &'a World) -> Self {
+        Self { world, chunk: None }
+    }
+
+    fn chunk(&mut self, pos: IVec3) -> Option<(Arc<RwLock<Chunk>>, IVec3)> {
+        let chunk_coord = World::get_chunk_coord(&pos);
+        let local = pos - World::chunk_local_to_world(&chunk_coord, &IVec3::ZERO);
+
+        match &self.chunk {
+            Some((coord, chunk)) if *coord == chunk_coord => Some((Arc::clone(chunk), local)),
+            _ => {
+                let chunk = self.world.get_chunk(chunk_coord)?;
+                self.chunk = Some((chunk_coord, Arc::clone(&chunk)));
+                Some((chunk, local))
+            }
+        }
+    }
+}
+
+impl LightVolume for WorldLight<'_> {
+    fn voxel(&mut self, pos: IVec3) -> Option<(Block, Light)> {
+        let (chunk, local) = self.chunk(pos)?;
+        let chunk = chunk.read().unwrap();
+        Some((chunk.voxel_at(&local), chunk.light_at(&local)))
+    }
+
+    fn set_light(&mut self, pos: IVec3, light: Light) {
+        let Some((chunk, local)) = self.chunk(pos) else {
+            return;
+        };
+        let mut chunk = chunk.write().unwrap();
+
+        if chunk.light_at(&local) != light {
+            chunk.set_light(&local, light);
+            chunk.update_section(self.world, Chunk::section_index(local.y));
+            chunk.update_surrounding_voxels(self.world, local);
+            self.world.unsaved_chunks.write().unwrap().insert(chunk.pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::chunk::{CHUNK_HEIGHT, CHUNK_SIZE};
+
+    /// Stone floor at y = 10 with a closed room under it, lit by the sky from above
+    fn floor_chunk() -> Chunk {
+        let registry = BlockRegistry::default();
+        let stone = Block::new(registry.block_type("stone").unwrap());
+        let mut chunk = Chunk::new(IVec3::ZERO);
+
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for y in 0..=10 {
+                    chunk.set_voxel(&IVec3::new(x, y, z), stone);
+                }
+            }
+        }
+        for x in 2..=6 {
+            for z in 2..=6 {
+                for y in 5..=7 {
+                    chunk.set_voxel(&IVec3::new(x, y, z), Block::new_empty());
+                }
+            }
+        }
+
+        chunk.compute_light(&registry, |_, _| true);
+        chunk
+    }
+
+    #[test]
+    fn test_light_packing() {
+        let light = Light::new(12, 3);
+        assert_eq!((light.sky(), light.block()), (12, 3));
+        assert_eq!(light.with(LightChannel::Block, 15).block(), 15);
+        assert_eq!(light.with(LightChannel::Sky, 0).sky(), 0);
+        assert!(Light::SKY.brightness() > Light::new(0, 10).brightness());
+        assert!(Light::DARK.brightness() > 0.0);
+    }
+
+    #[test]
+    fn test_sky_light() {
+        let chunk = floor_chunk();
+
+        assert_eq!(chunk.light_at(&IVec3::new(3, 11, 3)), Light::SKY);
+        assert_eq!(
+            chunk.light_at(&IVec3::new(3, CHUNK_HEIGHT - 1, 3)),
+            Light::SKY
+        );
+        assert_eq!(chunk.light_at(&IVec3::new(3, 6, 3)), Light::DARK);
+        assert_eq!(chunk.light_at(&IVec3::new(3, 4, 3)), Light::DARK);
+    }
+
+    #[test]
+    fn test_light_updates() {
+        let registry = BlockRegistry::default();
+        let lava = Block::new(registry.block_type("lava").unwrap());
+        let stone = Block::new(registry.block_type("stone").unwrap());
+        let mut chunk = floor_chunk();
+
+        // Lava lights the room
+        chunk.set_voxel(&IVec3::new(2, 5, 2), lava);
+        let mut volume = ChunkLight::new(&mut chunk);
+        let mut updates = LightUpdates::default();
+        updates.voxel_changed(&mut volume, IVec3::new(2, 5, 2), &registry);
+        propagate(&mut volume, updates, &registry);
+        assert_eq!(chunk.light_at(&IVec3::new(2, 5, 2)).block(), 15);
+        assert_eq!(chunk.light_at(&IVec3::new(6, 7, 6)).block(), 15 - 10);
+        assert_eq!(chunk.light_at(&IVec3::new(6, 7, 6)).sky(), 0);
+
+        // Digging through the floor lets the sky in
+        for y in 8..=10 {
+            chunk.set_voxel(&IVec3::new(4, y, 4), Block::new_empty());
+            let mut volume = ChunkLight::new(&mut chunk);
+            let mut updates = LightUpdates::default();
+            updates.voxel_changed(&mut volume, IVec3::new(4, y, 4), &registry);
+            propagate(&mut volume, updates, &registry);
+        }
+        assert_eq!(chunk.light_at(&IVec3::new(4, 5, 4)).sky(), 15);
+        assert_eq!(chunk.light_at(&IVec3::new(6, 5, 4)).sky(), 13);
+
+        // Closing it and removing the lava darkens the room again
+        for (pos, block) in [
+            (IVec3::new(4, 10, 4), stone),
+            (IVec3::new(2, 5, 2), Block::new_empty()),
+        ] {
+            chunk.set_voxel(&pos, block);
+            let mut volume = ChunkLight::new(&mut chunk);
+            let mut updates = LightUpdates::default();
+            updates.voxel_changed(&mut volume, pos, &registry);
+            propagate(&mut volume, updates, &registry);
+        }
+        for pos in [
+            IVec3::new(4, 5, 4),
+            IVec3::new(2, 5, 2),
+            IVec3::new(6, 7, 6),
+        ] {
+            assert_eq!(chunk.light_at(&pos), Light::DARK);
+        }
+        assert_eq!(chunk.light_at(&IVec3::new(4, 11, 4)), Light::SKY);
+    }
+
+    #[test]
+    fn test_light_leaving_the_chunk() {
+        let registry = BlockRegistry::default();
+        let lava = Block::new(registry.block_type("lava").unwrap());
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.compute_light(&registry, |_, _| false);
+
+        chunk.set_voxel(&IVec3::new(1, 5, 5), lava);
+        let mut volume = ChunkLight::new(&mut chunk);
+        let mut updates = LightUpdates::default();
+        updates.voxel_changed(&mut volume, IVec3::new(1, 5, 5), &registry);
+        let outside = propagate(&mut volume, updates, &registry);
+
+        assert!(outside.removed.is_empty());
+        assert!(outside
+            .added
+            .iter()
+            .all(|(_, channel)| *channel == LightChannel::Block));
+        assert!(outside.added.iter().any(|(pos, _)| pos.x == 0));
+    }
+}
@@ -1,95 +1,268 @@
 // src/voxel/mesh_builder.rs
 
-use crate::chunk::{CHUNK_HEIGHT, CHUNK_SIZE};
-use crate::voxel::block::Block; // Make sure BlockType is imported
-use crate::voxel::chunk::{Chunk, ChunkData}; // Make sure ChunkData is imported
+use crate::chunk::{CHUNK_HEIGHT, CHUNK_SIZE, SECTION_SIZE};
+use crate::voxel::block::Block;
+use crate::voxel::block_model::{
+    block_boxes, covers_side, BlockModel, FACE_CORNERS, FACE_NORMALS, FULL_SIDE,
+};
+use crate::voxel::block_registry::{BlockRegistry, RenderCategory, MAX_FLUID_LEVEL};
+use crate::voxel::chunk::{Chunk, ChunkSection};
 use crate::voxel::direction::Direction;
-use crate::voxel::texture::convert_face_id_to_uv; // Keep this
+use crate::voxel::light::Light;
+use crate::voxel::texture::tile_origin;
 use bevy::asset::RenderAssetUsages;
 use bevy::math::IVec3;
 use bevy::prelude::*;
-use bevy::render::mesh::Indices;
 use bevy::render::mesh::Mesh;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
 use bevy::render::render_resource::PrimitiveTopology;
-use std::sync::RwLockReadGuard;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
 use std::time::Instant;
 
-// Precompute corner offsets for each face direction relative to voxel center (0,0,0)
-// Order: Top-Left, Top-Right, Bottom-Right, Bottom-Left (relative to viewing the face)
-// Matches the typical quad vertex order for triangulation (0, 1, 2, 0, 2, 3)
-const FACE_CORNERS: [[Vec3; 4]; 6] = [
-    // Right (+X face)
-    [
-        Vec3::new(0.5, 0.5, -0.5),
-        Vec3::new(0.5, 0.5, 0.5),
-        Vec3::new(0.5, -0.5, 0.5),
-        Vec3::new(0.5, -0.5, -0.5),
-    ],
-    // Left (-X face)
-    [
-        Vec3::new(-0.5, 0.5, 0.5),
-        Vec3::new(-0.5, 0.5, -0.5),
-        Vec3::new(-0.5, -0.5, -0.5),
-        Vec3::new(-0.5, -0.5, 0.5),
-    ],
-    // Up (+Y face) - Top-Left from above is Back-Left
-    [
-        Vec3::new(-0.5, 0.5, 0.5),
-        Vec3::new(0.5, 0.5, 0.5),
-        Vec3::new(0.5, 0.5, -0.5),
-        Vec3::new(-0.5, 0.5, -0.5),
-    ],
-    // Down (-Y face) - Top-Left from below is Front-Left
-    [
-        Vec3::new(-0.5, -0.5, -0.5),
-        Vec3::new(0.5, -0.5, -0.5),
-        Vec3::new(0.5, -0.5, 0.5),
-        Vec3::new(-0.5, -0.5, 0.5),
-    ],
-    // Forward (+Z face)
-    [
-        Vec3::new(0.5, 0.5, 0.5),
-        Vec3::new(-0.5, 0.5, 0.5),
-        Vec3::new(-0.5, -0.5, 0.5),
-        Vec3::new(0.5, -0.5, 0.5),
-    ],
-    // Back (-Z face)
-    [
-        Vec3::new(-0.5, 0.5, -0.5),
-        Vec3::new(0.5, 0.5, -0.5),
-        Vec3::new(0.5, -0.5, -0.5),
-        Vec3::new(-0.5, -0.5, -0.5),
-    ],
-];
-
-// Normals for each face direction
-const FACE_NORMALS: [Vec3; 6] = [
-    Vec3::X,
-    Vec3::NEG_X,
-    Vec3::Y,
-    Vec3::NEG_Y,
-    Vec3::Z,
-    Vec3::NEG_Z,
-];
+/// Brightness of a vertex for each ambient occlusion value, from fully to not occluded
+const AO_BRIGHTNESS: [f32; 4] = [0.45, 0.6, 0.8, 1.0];
+
+/// Vertices of a mesh being built
+#[derive(Default)]
+struct MeshData {
+    vertices: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    /// Position in blocks across the quad, the texture repeating once per block
+    uvs: Vec<Vec2>,
+    /// Top left corner of the tile of the quad in the atlas
+    tiles: Vec<Vec2>,
+    /// Light baked into each vertex, multiplying the texture
+    colors: Vec<[f32; 4]>,
+    indices: Vec<u32>,
+}
+
+impl MeshData {
+    fn with_capacity(quads: usize) -> Self {
+        Self {
+            vertices: Vec::with_capacity(quads * 4),
+            normals: Vec::with_capacity(quads * 4),
+            uvs: Vec::with_capacity(quads * 4),
+            tiles: Vec::with_capacity(quads * 4),
+            colors: Vec::with_capacity(quads * 4),
+            indices: Vec::with_capacity(quads * 6),
+        }
+    }
+
+    /// Add a quad `size` blocks wide and high with the texture `face_id` repeated over it, lit by
+    /// `brightness` and each corner darkened by its ambient occlusion value
+    fn add_quad(
+        &mut self,
+        corners: [Vec3; 4],
+        normal: Vec3,
+        face_id: u16,
+        size: Vec2,
+        brightness: f32,
+        ao: [u8; 4],
+    ) {
+        self.add_quad_with_uvs(
+            corners,
+            normal,
+            face_id,
+            [Vec2::ZERO, size.with_y(0.0), size, size.with_x(0.0)],
+            brightness,
+            ao,
+        );
+    }
+
+    /// Add a quad showing the part of the texture `face_id` at `uvs`, in blocks from its top left
+    /// corner
+    fn add_quad_with_uvs(
+        &mut self,
+        corners: [Vec3; 4],
+        normal: Vec3,
+        face_id: u16,
+        uvs: [Vec2; 4],
+        brightness: f32,
+        ao: [u8; 4],
+    ) {
+        let first_index = self.vertices.len() as u32;
+
+        self.vertices.extend(corners);
+        self.normals.extend([normal; 4]);
+        self.uvs.extend(uvs);
+        self.tiles.extend([tile_origin(face_id); 4]);
+        self.colors.extend(ao.map(|ao| {
+            let color = brightness * AO_BRIGHTNESS[ao as usize];
+            [color, color, color, 1.0]
+        }));
+        self.indices
+            .extend(quad_indices(ao).map(|i| first_index + i));
+    }
+
+    fn build(self) -> Mesh {
+        build_mesh(
+            self.vertices,
+            self.normals,
+            self.uvs,
+            self.tiles,
+            self.colors,
+            self.indices,
+        )
+    }
+}
+
+/// Two triangles of a quad, split along the diagonal whose corners are the least occluded
+///
+/// The colors are interpolated over each triangle, so splitting along the other diagonal would
+/// stretch the shadow of a single occluded corner over half of the quad.
+fn quad_indices(ao: [u8; 4]) -> [u32; 6] {
+    if ao[0] + ao[2] >= ao[1] + ao[3] {
+        [0, 1, 2, 0, 2, 3]
+    } else {
+        [1, 2, 3, 1, 3, 0]
+    }
+}
+
+/// Ambient occlusion of a face corner, from 0 when it is in a corner to 3 when nothing is
+/// around it
+///
+/// `side1` and `side2` are the blocks next to the corner along the face, and `corner` the one
+/// diagonal to it.
+fn vertex_ao(side1: bool, side2: bool, corner: bool) -> u8 {
+    if side1 && side2 {
+        0
+    } else {
+        3 - (side1 as u8 + side2 as u8 + corner as u8)
+    }
+}
+
+/// Ambient occlusion of the corners of a face, in the order of [`FACE_CORNERS`]
+///
+/// Each corner is occluded by the opaque blocks touching it in the layer in front of the face.
+/// Blocks across a vertical and a horizontal chunk border at once never occlude.
+fn face_ao(
+    voxel_pos: IVec3,
+    direction_index: usize,
+    chunk: &Chunk,
+    neighbor_guards: &NeighborGuards,
+    registry: &BlockRegistry,
+) -> [u8; 4] {
+    let normal = FACE_NORMALS[direction_index].as_ivec3();
+    let occludes = |offset: IVec3| {
+        get_cell(voxel_pos + normal + offset, chunk, neighbor_guards).is_some_and(|(chunk, pos)| {
+            !registry
+                .get(chunk.voxel_at(&pos).voxel_type)
+                .is_transparent()
+        })
+    };
+
+    FACE_CORNERS[direction_index].map(|corner| {
+        // Directions of the corner along the face, the component along the normal removed
+        let along_face = (corner * 2.0).as_ivec3() * (IVec3::ONE - normal.abs());
+        let [side1, side2] = match normal.abs() {
+            IVec3::X => [along_face.with_z(0), along_face.with_y(0)],
+            IVec3::Y => [along_face.with_z(0), along_face.with_x(0)],
+            _ => [along_face.with_y(0), along_face.with_x(0)],
+        };
+
+        vertex_ao(occludes(side1), occludes(side2), occludes(along_face))
+    })
+}
+
+/// Meshes of a section, translucent blocks being drawn in a separate pass
+pub struct SectionMeshes {
+    /// Opaque and cutout blocks, drawn with an alpha mask
+    pub opaque: Mesh,
+    /// Translucent blocks and fluids, blended with what is behind them
+    ///
+    /// One quad per face, so they can be sorted with [`sort_quads_back_to_front`].
+    pub translucent: Mesh,
+}
+
+/// Height of the surface of a fluid at `level` in its block, when no fluid is above it
+pub fn fluid_height(level: u8) -> f32 {
+    (MAX_FLUID_LEVEL + 1 - level) as f32 / (MAX_FLUID_LEVEL + 2) as f32
+}
 
 // Holds optional read guards for neighbor chunks
+#[derive(Default)]
 struct NeighborGuards<'a> {
     left: Option<RwLockReadGuard<'a, Chunk>>,
     right: Option<RwLockReadGuard<'a, Chunk>>,
     back: Option<RwLockReadGuard<'a, Chunk>>,
     forward: Option<RwLockReadGuard<'a, Chunk>>,
+    below: Option<RwLockReadGuard<'a, Chunk>>,
+    above: Option<RwLockReadGuard<'a, Chunk>>,
+    // Chunks diagonal to this one, for the ambient occlusion of the corners
+    left_back: Option<RwLockReadGuard<'a, Chunk>>,
+    left_forward: Option<RwLockReadGuard<'a, Chunk>>,
+    right_back: Option<RwLockReadGuard<'a, Chunk>>,
+    right_forward: Option<RwLockReadGuard<'a, Chunk>>,
+}
+
+/// How the faces of the opaque blocks are turned into quads
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MeshingStrategy {
+    /// One quad per visible face
+    PerFace,
+    /// Neighboring faces that look the same merged into larger quads
+    #[default]
+    Greedy,
+}
+
+/// Visible face of a block, and what it takes for faces to be merged
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Face {
+    face_id: u16,
+    /// Light of the cell the face looks into
+    light: Light,
+    ao: [u8; 4],
 }
 
-pub fn create_chunk_mesh(chunk: &Chunk) -> Mesh {
+/// Build the meshes of one section of a chunk, positioned relative to the bottom of the section
+pub fn create_section_mesh(
+    chunk: &Chunk,
+    section_index: usize,
+    registry: &BlockRegistry,
+) -> SectionMeshes {
+    create_section_mesh_with(chunk, section_index, registry, MeshingStrategy::default())
+}
+
+/// [`create_section_mesh`] with a chosen way of meshing the opaque blocks
+pub fn create_section_mesh_with(
+    chunk: &Chunk,
+    section_index: usize,
+    registry: &BlockRegistry,
+    strategy: MeshingStrategy,
+) -> SectionMeshes {
     // --- Start Timing ---
     let start_time = Instant::now();
 
+    let Some(section) = chunk.section(section_index) else {
+        // All air, nothing to draw
+        return SectionMeshes {
+            opaque: MeshData::default().build(),
+            translucent: MeshData::default().build(),
+        };
+    };
+    let section_base_y = section_index as i32 * SECTION_SIZE;
+
     // --- Neighbor Arc Acquisition ---
     // Get the Arcs first. They need to live until neighbor_guards goes out of scope.
     let neighbor_left_arc_opt = chunk.neighbors[0].upgrade();
     let neighbor_right_arc_opt = chunk.neighbors[1].upgrade();
     let neighbor_back_arc_opt = chunk.neighbors[2].upgrade();
     let neighbor_forward_arc_opt = chunk.neighbors[3].upgrade();
+    let neighbor_below_arc_opt = chunk.neighbors[4].upgrade();
+    let neighbor_above_arc_opt = chunk.neighbors[5].upgrade();
+    // The diagonal chunks are reached through the links of the chunks beside this one
+    let diagonal_arc = |side: &Option<Arc<RwLock<Chunk>>>, index: usize| {
+        side.as_ref()
+            .and_then(|arc| arc.read().ok()?.neighbors[index].upgrade())
+    };
+    let neighbor_left_back_arc_opt =
+        diagonal_arc(&neighbor_left_arc_opt, 2).or_else(|| diagonal_arc(&neighbor_back_arc_opt, 0));
+    let neighbor_left_forward_arc_opt = diagonal_arc(&neighbor_left_arc_opt, 3)
+        .or_else(|| diagonal_arc(&neighbor_forward_arc_opt, 0));
+    let neighbor_right_back_arc_opt = diagonal_arc(&neighbor_right_arc_opt, 2)
+        .or_else(|| diagonal_arc(&neighbor_back_arc_opt, 1));
+    let neighbor_right_forward_arc_opt = diagonal_arc(&neighbor_right_arc_opt, 3)
+        .or_else(|| diagonal_arc(&neighbor_forward_arc_opt, 1));
 
     // --- Neighbor Lock Acquisition ---
     // Now create the guards, borrowing from the Arcs above.
@@ -107,6 +280,24 @@ pub fn create_chunk_mesh(chunk: &Chunk) -> Mesh {
         forward: neighbor_forward_arc_opt
             .as_ref()
             .and_then(|arc| arc.read().ok()),
+        below: neighbor_below_arc_opt
+            .as_ref()
+            .and_then(|arc| arc.read().ok()),
+        above: neighbor_above_arc_opt
+            .as_ref()
+            .and_then(|arc| arc.read().ok()),
+        left_back: neighbor_left_back_arc_opt
+            .as_ref()
+            .and_then(|arc| arc.read().ok()),
+        left_forward: neighbor_left_forward_arc_opt
+            .as_ref()
+            .and_then(|arc| arc.read().ok()),
+        right_back: neighbor_right_back_arc_opt
+            .as_ref()
+            .and_then(|arc| arc.read().ok()),
+        right_forward: neighbor_right_forward_arc_opt
+            .as_ref()
+            .and_then(|arc| arc.read().ok()),
     };
 
     // --- Mesh Data Initialization ---
@@ -114,108 +305,438 @@ pub fn create_chunk_mesh(chunk: &Chunk) -> Mesh {
     // A rough estimate (e.g., 1/4th of voxels have 3 exposed faces) might be okay.
     // Let's estimate based on potential surface area + some internal faces.
     let estimated_quads = (CHUNK_SIZE * CHUNK_SIZE * 3)
-        + (CHUNK_SIZE * CHUNK_HEIGHT * 3)
-        + (CHUNK_SIZE * CHUNK_HEIGHT * 3); // Rough estimate
-    let estimated_vertices = estimated_quads * 4;
-    let estimated_indices = estimated_quads * 6;
-
-    let mut vertices = Vec::<Vec3>::with_capacity(estimated_vertices as usize);
-    let mut normals = Vec::<Vec3>::with_capacity(estimated_vertices as usize);
-    let mut uvs = Vec::<Vec2>::with_capacity(estimated_vertices as usize);
-    let mut indices = Vec::<u32>::with_capacity(estimated_indices as usize);
-    let mut current_vertex_index: u32 = 0;
-
-    let chunk_voxels = &chunk.voxels; // Borrow voxel data locally
+        + (CHUNK_SIZE * SECTION_SIZE * 3)
+        + (CHUNK_SIZE * SECTION_SIZE * 3); // Rough estimate
+    let mut opaque = MeshData::with_capacity(estimated_quads as usize);
+    let mut translucent = MeshData::default();
 
     // --- Main Meshing Loop ---
-    for y in 0..CHUNK_HEIGHT {
+    for y in 0..SECTION_SIZE {
         for z in 0..CHUNK_SIZE {
             for x in 0..CHUNK_SIZE {
-                let voxel_pos_local = IVec3::new(x, y, z);
-                let voxel_index = Chunk::get_index(&voxel_pos_local);
-                let current_voxel = chunk_voxels[voxel_index];
+                let voxel_pos_section = IVec3::new(x, y, z);
+                let voxel_pos_local = IVec3::new(x, section_base_y + y, z);
+                let current_voxel = section.get(&voxel_pos_section);
 
-                if !current_voxel.is_solid() {
+                if current_voxel.is_air() {
                     continue; // Skip air blocks
                 }
 
-                let current_voxel_type = current_voxel.voxel_type;
-                let current_voxel_world_pos = voxel_pos_local.as_vec3(); // For positioning quads
+                let current_definition = registry.get(current_voxel.voxel_type);
+                let current_voxel_world_pos = voxel_pos_section.as_vec3(); // For positioning quads
+
+                if !current_definition.model.is_cube() {
+                    add_model_faces(
+                        match current_definition.render {
+                            RenderCategory::Translucent => &mut translucent,
+                            _ => &mut opaque,
+                        },
+                        voxel_pos_local,
+                        current_voxel,
+                        current_voxel_world_pos,
+                        chunk,
+                        &neighbor_guards,
+                        registry,
+                    );
+                    continue;
+                }
+
+                let Some(level) = current_definition.fluid_level(current_voxel.state) else {
+                    let mesh = match current_definition.render {
+                        RenderCategory::Translucent => &mut translucent,
+                        _ if strategy == MeshingStrategy::PerFace => &mut opaque,
+                        // Merged by the greedy pass after this loop
+                        _ => continue,
+                    };
+                    for direction_index in 0..6 {
+                        let Some(face) = visible_face(
+                            voxel_pos_local,
+                            current_voxel,
+                            direction_index,
+                            chunk,
+                            &neighbor_guards,
+                            registry,
+                        ) else {
+                            continue;
+                        };
+
+                        mesh.add_quad(
+                            FACE_CORNERS[direction_index]
+                                .map(|corner| current_voxel_world_pos + corner),
+                            FACE_NORMALS[direction_index],
+                            face.face_id,
+                            Vec2::ONE,
+                            face.light.brightness(),
+                            face.ao,
+                        );
+                    }
+                    continue;
+                };
+
+                // Fluids are lowered by their level, unless the same fluid is above them
+                let above =
+                    get_voxel_neighbor_optimized(voxel_pos_local, 2, chunk, &neighbor_guards);
+                let fluid_top =
+                    if above.is_some_and(|above| above.voxel_type == current_voxel.voxel_type) {
+                        0.5
+                    } else {
+                        fluid_height(level) - 0.5
+                    };
 
                 // --- Neighbor Check and Quad Generation ---
                 // Iterate through 6 directions (Right, Left, Up, Down, Forward, Back)
                 for direction_index in 0..6 {
-                    let neighbor_voxel = get_voxel_neighbor_optimized(
+                    let neighbor_cell = get_neighbor_cell(
                         voxel_pos_local,
                         direction_index,
-                        chunk_voxels,     // Pass current chunk's data
+                        chunk,            // Pass current chunk's data
                         &neighbor_guards, // Pass neighbor guards
                     );
+                    let neighbor_voxel = neighbor_cell.map(|(chunk, pos)| chunk.voxel_at(&pos));
 
-                    if should_add_face(neighbor_voxel) {
-                        // Add face directly to mesh data vectors
-                        let face_normal = FACE_NORMALS[direction_index];
-                        let texture_coords = convert_face_id_to_uv(Block::get_face(
-                            &current_voxel_type,
-                            &Direction::from_index(direction_index),
-                        ));
-
-                        for (i, texture_coord) in texture_coords.iter().enumerate() {
-                            // Calculate vertex position relative to chunk origin
-                            vertices
-                                .push(current_voxel_world_pos + FACE_CORNERS[direction_index][i]);
-                            normals.push(face_normal);
-                            uvs.push(*texture_coord); // Use UVs from texture atlas lookup
-                        }
-
-                        // Add indices for the two triangles forming the quad
-                        indices.push(current_vertex_index); // Triangle 1: Vertex 0
-                        indices.push(current_vertex_index + 1); // Triangle 1: Vertex 1
-                        indices.push(current_vertex_index + 2); // Triangle 1: Vertex 2
+                    if !should_add_face(
+                        registry,
+                        current_voxel,
+                        direction_index,
+                        FULL_SIDE,
+                        neighbor_voxel,
+                    ) {
+                        continue;
+                    }
 
-                        indices.push(current_vertex_index); // Triangle 2: Vertex 0
-                        indices.push(current_vertex_index + 2); // Triangle 2: Vertex 2
-                        indices.push(current_vertex_index + 3); // Triangle 2: Vertex 3
+                    let corners = FACE_CORNERS[direction_index].map(|corner| {
+                        if corner.y > 0.0 {
+                            current_voxel_world_pos + corner.with_y(fluid_top)
+                        } else {
+                            current_voxel_world_pos + corner
+                        }
+                    });
+                    // Faces are lit by the cell they look into, unloaded chunks counting as open sky
+                    let light = neighbor_cell
+                        .map_or(Light::SKY, |(neighbor_chunk, neighbor_pos)| {
+                            neighbor_chunk.light_at(&neighbor_pos)
+                        });
 
-                        current_vertex_index += 4;
-                    }
+                    translucent.add_quad(
+                        corners,
+                        FACE_NORMALS[direction_index],
+                        current_definition.face_texture(
+                            current_voxel.state,
+                            Direction::from_index(direction_index),
+                        ),
+                        Vec2::ONE,
+                        light.brightness(),
+                        [3; 4],
+                    );
                 }
             }
         }
     }
 
-    // --- Final Mesh Construction ---
-    let mut chunk_mesh = Mesh::new(
-        PrimitiveTopology::TriangleList,
-        RenderAssetUsages::default(),
-    );
+    if strategy == MeshingStrategy::Greedy {
+        add_greedy_faces(
+            &mut opaque,
+            chunk,
+            section,
+            section_base_y,
+            &neighbor_guards,
+            registry,
+        );
+    }
 
-    chunk_mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
-    chunk_mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-    chunk_mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    chunk_mesh.insert_indices(Indices::U32(indices));
+    // --- Final Mesh Construction ---
+    let meshes = SectionMeshes {
+        opaque: opaque.build(),
+        translucent: translucent.build(),
+    };
 
     // --- End Timing & Log ---
     let elapsed = start_time.elapsed(); // <-- Calculate elapsed time
                                         // Log using Bevy's debug macro. Includes chunk position for context.
     debug!(
-        "Mesh generation for chunk {:?} took {:?}",
-        chunk.pos, elapsed
+        "Mesh generation for section {} of chunk {:?} took {:?}",
+        section_index, chunk.pos, elapsed
     ); // <-- Log the duration
 
-    chunk_mesh
+    meshes
+}
+
+fn build_mesh(
+    vertices: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    tiles: Vec<Vec2>,
+    colors: Vec<[f32; 4]>,
+    indices: Vec<u32>,
+) -> Mesh {
+    let mut mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    );
+
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_1, tiles);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.insert_indices(Indices::U32(indices));
+
+    mesh
+}
+
+/// Face of a block that isn't a fluid towards a direction, `None` if a neighbor hides it
+fn visible_face(
+    voxel_pos: IVec3,
+    block: Block,
+    direction_index: usize,
+    chunk: &Chunk,
+    neighbor_guards: &NeighborGuards,
+    registry: &BlockRegistry,
+) -> Option<Face> {
+    let neighbor_cell = get_neighbor_cell(voxel_pos, direction_index, chunk, neighbor_guards);
+    if !should_add_face(
+        registry,
+        block,
+        direction_index,
+        FULL_SIDE,
+        neighbor_cell.map(|(chunk, pos)| chunk.voxel_at(&pos)),
+    ) {
+        return None;
+    }
+
+    Some(Face {
+        face_id: registry
+            .get(block.voxel_type)
+            .face_texture(block.state, Direction::from_index(direction_index)),
+        // Unloaded chunks count as open sky
+        light: neighbor_cell.map_or(Light::SKY, |(chunk, pos)| chunk.light_at(&pos)),
+        ao: face_ao(voxel_pos, direction_index, chunk, neighbor_guards, registry),
+    })
+}
+
+/// Add the faces of a block that isn't a cube, positioned at `section_pos`
+///
+/// The sides of its boxes against the sides of the block are hidden like the faces of cubes,
+/// and lit by the cell they look into. Sides inside of the block are hidden by the other boxes
+/// of the model pressed against them, and lit by the cell of the block. Plants are two planes
+/// seen from both sides.
+fn add_model_faces(
+    mesh: &mut MeshData,
+    voxel_pos: IVec3,
+    block: Block,
+    section_pos: Vec3,
+    chunk: &Chunk,
+    neighbor_guards: &NeighborGuards,
+    registry: &BlockRegistry,
+) {
+    let definition = registry.get(block.voxel_type);
+    let own_brightness = chunk.light_at(&voxel_pos).brightness();
+
+    if definition.model == BlockModel::Cross {
+        let face_id = definition.face_texture(block.state, Direction::Forward);
+        for [start, end] in [
+            [Vec3::new(-0.5, 0.0, -0.5), Vec3::new(0.5, 0.0, 0.5)],
+            [Vec3::new(-0.5, 0.0, 0.5), Vec3::new(0.5, 0.0, -0.5)],
+        ] {
+            let corners = [
+                start.with_y(0.5),
+                end.with_y(0.5),
+                end.with_y(-0.5),
+                start.with_y(-0.5),
+            ]
+            .map(|corner| section_pos + corner);
+            let normal = (corners[1] - corners[0])
+                .cross(corners[3] - corners[0])
+                .normalize();
+            let [top_left, top_right, bottom_right, bottom_left] = corners;
+
+            for (corners, normal) in [
+                (corners, normal),
+                ([top_right, top_left, bottom_left, bottom_right], -normal),
+            ] {
+                mesh.add_quad(corners, normal, face_id, Vec2::ONE, own_brightness, [3; 4]);
+            }
+        }
+        return;
+    }
+
+    let boxes = block_boxes(registry, block, |direction| {
+        get_voxel_neighbor_optimized(voxel_pos, direction.index(), chunk, neighbor_guards)
+    });
+    for model_box in &boxes {
+        for direction_index in 0..6 {
+            let brightness = if model_box.touches_side(direction_index) {
+                let neighbor_cell =
+                    get_neighbor_cell(voxel_pos, direction_index, chunk, neighbor_guards);
+                if !should_add_face(
+                    registry,
+                    block,
+                    direction_index,
+                    model_box.side_rect(direction_index),
+                    neighbor_cell.map(|(chunk, pos)| chunk.voxel_at(&pos)),
+                ) {
+                    continue;
+                }
+                // Unloaded chunks count as open sky
+                neighbor_cell
+                    .map_or(Light::SKY, |(chunk, pos)| chunk.light_at(&pos))
+                    .brightness()
+            } else if model_box.is_side_hidden_by(direction_index, &boxes) {
+                continue;
+            } else {
+                own_brightness
+            };
+
+            // The part of the texture the side would take on a full face of the block
+            let face = FACE_CORNERS[direction_index];
+            let (right, down) = (face[1] - face[0], face[3] - face[0]);
+            let corners = model_box.face_corners(direction_index);
+            let uvs = corners.map(|corner| {
+                Vec2::new((corner - face[0]).dot(right), (corner - face[0]).dot(down))
+            });
+
+            mesh.add_quad_with_uvs(
+                corners.map(|corner| section_pos + corner),
+                FACE_NORMALS[direction_index],
+                definition.face_texture(block.state, Direction::from_index(direction_index)),
+                uvs,
+                brightness,
+                [3; 4],
+            );
+        }
+    }
+}
+
+/// Add the visible faces of the opaque and cutout blocks of a section, merged into as few quads
+/// as possible
+///
+/// Each layer of the section is swept once per direction, and faces with the same texture, light
+/// and ambient occlusion are grown into rectangles, first along the rows of the texture and then
+/// down its columns. Faces whose corners are not equally occluded are kept on their own, as
+/// interpolating their shading over a larger quad would not match.
+fn add_greedy_faces(
+    mesh: &mut MeshData,
+    chunk: &Chunk,
+    section: &ChunkSection,
+    section_base_y: i32,
+    neighbor_guards: &NeighborGuards,
+    registry: &BlockRegistry,
+) {
+    let size = IVec3::new(CHUNK_SIZE, SECTION_SIZE, CHUNK_SIZE);
+
+    for direction_index in 0..6 {
+        let corners = FACE_CORNERS[direction_index];
+        let normal_axis = FACE_NORMALS[direction_index].abs().as_ivec3();
+        // Directions the texture goes right and down on the faces
+        let right = (corners[1] - corners[0]).as_ivec3();
+        let down = (corners[3] - corners[0]).as_ivec3();
+        let width = (right.abs() * size).element_sum();
+        let height = (down.abs() * size).element_sum();
+        // Block of the first layer at the top left corner of the faces
+        let origin = (size - IVec3::ONE) * (right + down).min(IVec3::ZERO).abs();
+
+        let mut faces = vec![None; (width * height) as usize];
+        for layer in 0..(normal_axis * size).element_sum() {
+            let layer_origin = origin + normal_axis * layer;
+            let section_pos = |column: i32, row: i32| layer_origin + right * column + down * row;
+
+            for row in 0..height {
+                for column in 0..width {
+                    let pos = section_pos(column, row);
+                    let block = section.get(&pos);
+
+                    let definition = registry.get(block.voxel_type);
+                    faces[(row * width + column) as usize] = if block.is_air()
+                        || definition.render == RenderCategory::Translucent
+                        || !definition.model.is_cube()
+                    {
+                        None
+                    } else {
+                        visible_face(
+                            pos.with_y(pos.y + section_base_y),
+                            block,
+                            direction_index,
+                            chunk,
+                            neighbor_guards,
+                            registry,
+                        )
+                    };
+                }
+            }
+
+            for row in 0..height {
+                let mut column = 0;
+                while column < width {
+                    let Some(face) = faces[(row * width + column) as usize] else {
+                        column += 1;
+                        continue;
+                    };
+
+                    let same_face = |column: i32, row: i32| {
+                        faces[(row * width + column) as usize] == Some(face)
+                    };
+                    let (mut quad_width, mut quad_height) = (1, 1);
+                    if face.ao.iter().all(|ao| *ao == face.ao[0]) {
+                        while column + quad_width < width && same_face(column + quad_width, row) {
+                            quad_width += 1;
+                        }
+                        while row + quad_height < height
+                            && (column..column + quad_width)
+                                .all(|column| same_face(column, row + quad_height))
+                        {
+                            quad_height += 1;
+                        }
+                    }
+
+                    for merged_row in row..row + quad_height {
+                        let start = (merged_row * width + column) as usize;
+                        faces[start..start + quad_width as usize].fill(None);
+                    }
+
+                    let top_left = section_pos(column, row).as_vec3();
+                    let to_right = (right * (quad_width - 1)).as_vec3();
+                    let to_bottom = (down * (quad_height - 1)).as_vec3();
+                    mesh.add_quad(
+                        [
+                            top_left + corners[0],
+                            top_left + to_right + corners[1],
+                            top_left + to_right + to_bottom + corners[2],
+                            top_left + to_bottom + corners[3],
+                        ],
+                        FACE_NORMALS[direction_index],
+                        face.face_id,
+                        Vec2::new(quad_width as f32, quad_height as f32),
+                        face.light.brightness(),
+                        face.ao,
+                    );
+
+                    column += quad_width;
+                }
+            }
+        }
+    }
 }
 
 // Optimized neighbor lookup using pre-acquired locks (guards)
 #[inline]
 fn get_voxel_neighbor_optimized<'a>(
+    voxel_pos: IVec3,
+    direction_index: usize,
+    current_chunk: &'a Chunk,
+    neighbor_guards: &'a NeighborGuards<'a>,
+) -> Option<Block> {
+    get_neighbor_cell(voxel_pos, direction_index, current_chunk, neighbor_guards)
+        .map(|(chunk, pos)| chunk.voxel_at(&pos))
+}
+
+/// Chunk and local position of the cell next to a voxel, `None` if its chunk is not loaded
+#[inline]
+fn get_neighbor_cell<'a>(
     voxel_pos: IVec3,                        // Local position in the current chunk
     direction_index: usize, // 0..5 corresponding to Right, Left, Up, Down, Forward, Back
-    current_chunk_voxels: &'a ChunkData, // Voxel data of the chunk being meshed
+    current_chunk: &'a Chunk, // The chunk being meshed
     neighbor_guards: &'a NeighborGuards<'a>, // Locked neighbor data
-) -> Option<&'a Block> {
-    // Return Option<&Block> to match should_add_face
-
+) -> Option<(&'a Chunk, IVec3)> {
     match direction_index {
         // --- X Axis ---
         0 => {
@@ -224,11 +745,11 @@ fn get_voxel_neighbor_optimized<'a>(
                 // Check Right Neighbor Chunk
                 neighbor_guards.right.as_ref().map(|guard| {
                     let neighbor_local_pos = IVec3::new(0, voxel_pos.y, voxel_pos.z);
-                    &guard.voxels[Chunk::get_index(&neighbor_local_pos)]
+                    (&**guard, neighbor_local_pos)
                 })
             } else {
                 // Within current chunk
-                Some(&current_chunk_voxels[Chunk::get_index(&(voxel_pos + IVec3::X))])
+                Some((current_chunk, voxel_pos + IVec3::X))
             }
         }
         1 => {
@@ -237,32 +758,38 @@ fn get_voxel_neighbor_optimized<'a>(
                 // Check Left Neighbor Chunk
                 neighbor_guards.left.as_ref().map(|guard| {
                     let neighbor_local_pos = IVec3::new(CHUNK_SIZE - 1, voxel_pos.y, voxel_pos.z);
-                    &guard.voxels[Chunk::get_index(&neighbor_local_pos)]
+                    (&**guard, neighbor_local_pos)
                 })
             } else {
                 // Within current chunk
-                Some(&current_chunk_voxels[Chunk::get_index(&(voxel_pos - IVec3::X))])
+                Some((current_chunk, voxel_pos - IVec3::X))
             }
         }
         // --- Y Axis ---
         2 => {
             // Up (+Y)
             if voxel_pos.y + 1 >= CHUNK_HEIGHT {
-                None
-            }
-            // Above world
-            else {
-                Some(&current_chunk_voxels[Chunk::get_index(&(voxel_pos + IVec3::Y))])
+                // Check Above Neighbor Chunk
+                neighbor_guards.above.as_ref().map(|guard| {
+                    let neighbor_local_pos = IVec3::new(voxel_pos.x, 0, voxel_pos.z);
+                    (&**guard, neighbor_local_pos)
+                })
+            } else {
+                // Within current chunk
+                Some((current_chunk, voxel_pos + IVec3::Y))
             }
         }
         3 => {
             // Down (-Y)
             if voxel_pos.y - 1 < 0 {
-                None
-            }
-            // Below world
-            else {
-                Some(&current_chunk_voxels[Chunk::get_index(&(voxel_pos - IVec3::Y))])
+                // Check Below Neighbor Chunk
+                neighbor_guards.below.as_ref().map(|guard| {
+                    let neighbor_local_pos = IVec3::new(voxel_pos.x, CHUNK_HEIGHT - 1, voxel_pos.z);
+                    (&**guard, neighbor_local_pos)
+                })
+            } else {
+                // Within current chunk
+                Some((current_chunk, voxel_pos - IVec3::Y))
             }
         }
         // --- Z Axis ---
@@ -272,11 +799,11 @@ fn get_voxel_neighbor_optimized<'a>(
                 // Check Forward Neighbor Chunk
                 neighbor_guards.forward.as_ref().map(|guard| {
                     let neighbor_local_pos = IVec3::new(voxel_pos.x, voxel_pos.y, 0);
-                    &guard.voxels[Chunk::get_index(&neighbor_local_pos)]
+                    (&**guard, neighbor_local_pos)
                 })
             } else {
                 // Within current chunk
-                Some(&current_chunk_voxels[Chunk::get_index(&(voxel_pos + IVec3::Z))])
+                Some((current_chunk, voxel_pos + IVec3::Z))
             }
         }
         5 => {
@@ -285,21 +812,360 @@ fn get_voxel_neighbor_optimized<'a>(
                 // Check Back Neighbor Chunk
                 neighbor_guards.back.as_ref().map(|guard| {
                     let neighbor_local_pos = IVec3::new(voxel_pos.x, voxel_pos.y, CHUNK_SIZE - 1);
-                    &guard.voxels[Chunk::get_index(&neighbor_local_pos)]
+                    (&**guard, neighbor_local_pos)
                 })
             } else {
                 // Within current chunk
-                Some(&current_chunk_voxels[Chunk::get_index(&(voxel_pos - IVec3::Z))])
+                Some((current_chunk, voxel_pos - IVec3::Z))
             }
         }
         _ => unreachable!(), // Should be 0..5
     }
 }
 
+/// Chunk and local position of any voxel next to the chunk, `None` if it is outside of the
+/// chunk, the six beside it and the four diagonal to it horizontally
+fn get_cell<'a>(
+    pos: IVec3,
+    current_chunk: &'a Chunk,
+    neighbor_guards: &'a NeighborGuards<'a>,
+) -> Option<(&'a Chunk, IVec3)> {
+    let size = IVec3::new(CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE);
+    // -1 below the chunk, 1 above it and 0 inside of it, along each axis
+    let outside = pos.div_euclid(size);
+    let guard = match outside.to_array() {
+        [0, 0, 0] => return Some((current_chunk, pos)),
+        [-1, 0, 0] => &neighbor_guards.left,
+        [1, 0, 0] => &neighbor_guards.right,
+        [0, -1, 0] => &neighbor_guards.below,
+        [0, 1, 0] => &neighbor_guards.above,
+        [0, 0, -1] => &neighbor_guards.back,
+        [0, 0, 1] => &neighbor_guards.forward,
+        [-1, 0, -1] => &neighbor_guards.left_back,
+        [-1, 0, 1] => &neighbor_guards.left_forward,
+        [1, 0, -1] => &neighbor_guards.right_back,
+        [1, 0, 1] => &neighbor_guards.right_forward,
+        _ => return None,
+    };
+
+    guard.as_ref().map(|guard| (&**guard, pos.rem_euclid(size)))
+}
+
+/// Whether the part `rect` of the side of `block` towards `neighbor_voxel` can be seen, `rect`
+/// being a rectangle of [`ModelBox::side_rect`](crate::voxel::block_model::ModelBox::side_rect)
+///
+/// Opaque blocks hide the faces against them, as long as their model covers them. Faces between
+/// two blocks of the same translucent type, like water or ice, are hidden too so only the surface
+/// of the volume is drawn, while cutout blocks keep them as they show through the holes of the
+/// texture.
 #[inline]
-fn should_add_face(neighbor_voxel: Option<&Block>) -> bool {
+fn should_add_face(
+    registry: &BlockRegistry,
+    block: Block,
+    direction_index: usize,
+    rect: [Vec2; 2],
+    neighbor_voxel: Option<Block>,
+) -> bool {
     match neighbor_voxel {
-        Some(voxel) => !voxel.is_solid(), // Add face if neighbor is not solid (e.g., air)
+        Some(voxel) => {
+            let hides = match registry.get(voxel.voxel_type).render {
+                RenderCategory::Opaque => true,
+                RenderCategory::Cutout => false,
+                RenderCategory::Translucent => voxel.voxel_type == block.voxel_type,
+            };
+            // The neighbor's side facing this one
+            !(hides && covers_side(registry, voxel, direction_index ^ 1, rect))
+        }
         None => true, // Add face if neighbor is outside the loaded chunk or world bounds
     }
 }
+
+/// Reorder the quads of a mesh built by [`create_section_mesh`] so that the ones furthest from
+/// `viewer`, in the coordinates of the mesh, are drawn first
+///
+/// Translucent faces are blended over what is already drawn, so drawing them back to front is
+/// what lets the faces behind show through the ones in front.
+pub fn sort_quads_back_to_front(mesh: &mut Mesh, viewer: Vec3) {
+    let (Some(VertexAttributeValues::Float32x3(positions)), Some(Indices::U32(indices))) =
+        (mesh.attribute(Mesh::ATTRIBUTE_POSITION), mesh.indices())
+    else {
+        return;
+    };
+
+    // Each quad has four vertices of its own and six indices, its two triangles
+    let distance = |quad: &[u32]| {
+        let first = *quad.iter().min().unwrap() as usize;
+        let center = positions[first..first + 4]
+            .iter()
+            .map(|position| Vec3::from_array(*position))
+            .sum::<Vec3>()
+            / 4.0;
+        center.distance_squared(viewer)
+    };
+    let mut quads: Vec<(f32, &[u32])> = indices
+        .chunks_exact(6)
+        .map(|quad| (distance(quad), quad))
+        .collect();
+    quads.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    let sorted = quads
+        .into_iter()
+        .flat_map(|(_, quad)| quad)
+        .copied()
+        .collect();
+    mesh.insert_indices(Indices::U32(sorted));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::RwLock;
+
+    const UP: usize = 2;
+
+    fn stone() -> Block {
+        Block::new(BlockRegistry::default().block_type("stone").unwrap())
+    }
+
+    #[test]
+    fn test_vertex_ao() {
+        assert_eq!(vertex_ao(false, false, false), 3);
+        assert_eq!(vertex_ao(true, false, false), 2);
+        assert_eq!(vertex_ao(false, false, true), 2);
+        assert_eq!(vertex_ao(false, true, true), 1);
+        // Both sides hide the corner block
+        assert_eq!(vertex_ao(true, true, false), 0);
+        assert_eq!(vertex_ao(true, true, true), 0);
+    }
+
+    #[test]
+    fn test_face_ao() {
+        let registry = BlockRegistry::default();
+        let guards = NeighborGuards::default();
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        let pos = IVec3::new(5, 5, 5);
+        chunk.set_voxel(&pos, stone());
+
+        assert_eq!(face_ao(pos, UP, &chunk, &guards, &registry), [3; 4]);
+
+        // A block on the +X side of the layer above darkens the two corners touching it
+        chunk.set_voxel(&IVec3::new(6, 6, 5), stone());
+        assert_eq!(face_ao(pos, UP, &chunk, &guards, &registry), [3, 2, 2, 3]);
+
+        // One diagonal to the -X +Z corner
+        chunk.set_voxel(&IVec3::new(4, 6, 6), stone());
+        assert_eq!(face_ao(pos, UP, &chunk, &guards, &registry), [2, 2, 2, 3]);
+
+        // With the block on the -Z side, the +X -Z corner is enclosed
+        chunk.set_voxel(&IVec3::new(5, 6, 4), stone());
+        assert_eq!(face_ao(pos, UP, &chunk, &guards, &registry), [2, 2, 0, 2]);
+
+        // Blocks below the face, or that can be seen through, don't occlude it
+        chunk.set_voxel(&IVec3::new(5, 5, 4), stone());
+        chunk.set_voxel(
+            &IVec3::new(4, 6, 4),
+            Block::new(registry.block_type("water").unwrap()),
+        );
+        assert_eq!(face_ao(pos, UP, &chunk, &guards, &registry), [2, 2, 0, 2]);
+    }
+
+    #[test]
+    fn test_face_ao_across_chunk_border() {
+        let registry = BlockRegistry::default();
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        let pos = IVec3::new(CHUNK_SIZE - 1, 5, 0);
+        chunk.set_voxel(&pos, stone());
+
+        let mut right = Chunk::new(IVec3::X);
+        right.set_voxel(&IVec3::new(0, 6, 0), stone());
+        let right = RwLock::new(right);
+
+        // Unloaded neighbors don't occlude
+        let guards = NeighborGuards::default();
+        assert_eq!(face_ao(pos, UP, &chunk, &guards, &registry), [3; 4]);
+
+        let guards = NeighborGuards {
+            right: Some(right.read().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(face_ao(pos, UP, &chunk, &guards, &registry), [3, 2, 2, 3]);
+
+        // A block in the +X -Z chunk darkens the corner touching it
+        let mut right_back = Chunk::new(IVec3::new(1, 0, -1));
+        right_back.set_voxel(&IVec3::new(0, 6, CHUNK_SIZE - 1), stone());
+        let right_back = RwLock::new(right_back);
+        let guards = NeighborGuards {
+            right: Some(right.read().unwrap()),
+            right_back: Some(right_back.read().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(face_ao(pos, UP, &chunk, &guards, &registry), [3, 2, 1, 3]);
+    }
+
+    /// A layer of blocks at the bottom of a chunk, `block_at(x, z)` picking each of them
+    fn layer_chunk(block_at: impl Fn(i32, i32) -> Block) -> Chunk {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                chunk.set_voxel(&IVec3::new(x, 0, z), block_at(x, z));
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn test_greedy_merges_flat_layer() {
+        let registry = BlockRegistry::default();
+        let chunk = layer_chunk(|_, _| stone());
+
+        let per_face = create_section_mesh_with(&chunk, 0, &registry, MeshingStrategy::PerFace);
+        let greedy = create_section_mesh_with(&chunk, 0, &registry, MeshingStrategy::Greedy);
+
+        // The top and bottom, and one row of faces on each side
+        let faces = (2 * CHUNK_SIZE * CHUNK_SIZE + 4 * CHUNK_SIZE) as usize;
+        assert_eq!(per_face.opaque.count_vertices(), faces * 4);
+        // One quad for each side of the layer
+        assert_eq!(greedy.opaque.count_vertices(), 6 * 4);
+
+        // The texture repeats once per block
+        let Some(VertexAttributeValues::Float32x2(uvs)) =
+            greedy.opaque.attribute(Mesh::ATTRIBUTE_UV_0)
+        else {
+            panic!("the mesh has no UVs");
+        };
+        let size = CHUNK_SIZE as f32;
+        assert!(uvs.contains(&[size, size]));
+        assert!(uvs.contains(&[size, 1.0]));
+    }
+
+    #[test]
+    fn test_greedy_keeps_different_faces_apart() {
+        let registry = BlockRegistry::default();
+        let dirt = Block::new(registry.block_type("dirt").unwrap());
+        // Dirt on the -X half of the layer
+        let mut chunk = layer_chunk(|x, _| if x < CHUNK_SIZE / 2 { dirt } else { stone() });
+
+        let greedy = create_section_mesh_with(&chunk, 0, &registry, MeshingStrategy::Greedy);
+        // The top, bottom, +Z and -Z sides split in two
+        assert_eq!(greedy.opaque.count_vertices(), 10 * 4);
+
+        // A block on top occludes the corners of the faces around it, which are left unmerged
+        chunk.set_voxel(&IVec3::new(12, 1, 8), stone());
+        let greedy = create_section_mesh_with(&chunk, 0, &registry, MeshingStrategy::Greedy);
+        let per_face = create_section_mesh_with(&chunk, 0, &registry, MeshingStrategy::PerFace);
+        assert!(greedy.opaque.count_vertices() > 10 * 4);
+        assert!(greedy.opaque.count_vertices() < per_face.opaque.count_vertices());
+    }
+
+    #[test]
+    fn test_render_categories() {
+        let registry = BlockRegistry::default();
+        let [ice, glass] =
+            ["ice", "glass"].map(|name| Block::new(registry.block_type(name).unwrap()));
+        let mut chunk = Chunk::new(IVec3::ZERO);
+
+        // Ice goes in the translucent mesh, and shows the top of the stone under it
+        chunk.set_voxel(&IVec3::new(5, 4, 5), stone());
+        chunk.set_voxel(&IVec3::new(5, 5, 5), ice);
+        let meshes = create_section_mesh(&chunk, 0, &registry);
+        assert_eq!(meshes.opaque.count_vertices(), 6 * 4);
+        assert_eq!(meshes.translucent.count_vertices(), 5 * 4);
+
+        // Faces between two blocks of ice are hidden
+        chunk.set_voxel(&IVec3::new(6, 5, 5), ice);
+        let meshes = create_section_mesh(&chunk, 0, &registry);
+        assert_eq!(meshes.translucent.count_vertices(), 9 * 4);
+
+        // Glass is drawn with the opaque blocks, keeping the faces between two of them
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.set_voxel(&IVec3::new(5, 5, 5), glass);
+        chunk.set_voxel(&IVec3::new(6, 5, 5), glass);
+        let meshes = create_section_mesh_with(&chunk, 0, &registry, MeshingStrategy::PerFace);
+        assert_eq!(meshes.opaque.count_vertices(), 12 * 4);
+        assert_eq!(meshes.translucent.count_vertices(), 0);
+        // Greedy meshing merges the four sides around both blocks
+        let meshes = create_section_mesh_with(&chunk, 0, &registry, MeshingStrategy::Greedy);
+        assert_eq!(meshes.opaque.count_vertices(), 8 * 4);
+    }
+
+    #[test]
+    fn test_block_model_faces() {
+        let registry = BlockRegistry::default();
+        let [slab, grass] =
+            ["stone_slab", "tall_grass"].map(|name| Block::new(registry.block_type(name).unwrap()));
+        let quads = |chunk: &Chunk| {
+            create_section_mesh_with(chunk, 0, &registry, MeshingStrategy::PerFace)
+                .opaque
+                .count_vertices()
+                / 4
+        };
+
+        // A slab on stone hides the top of the stone, and the stone hides the bottom of the slab
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.set_voxel(&IVec3::new(5, 4, 5), stone());
+        chunk.set_voxel(&IVec3::new(5, 5, 5), slab);
+        assert_eq!(quads(&chunk), 5 + 5);
+
+        // The side of a stone next to the slab is only half covered and stays, the slab side
+        // against the stone is hidden
+        chunk.set_voxel(&IVec3::new(6, 5, 5), stone());
+        assert_eq!(quads(&chunk), 5 + 4 + 6);
+
+        // The top of the slab is drawn half a block up, with the texture of a full face
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            create_section_mesh(&chunk, 0, &registry)
+                .opaque
+                .attribute(Mesh::ATTRIBUTE_POSITION)
+                .cloned()
+        else {
+            panic!("the mesh has no positions");
+        };
+        assert!(positions.contains(&[4.5, 5.0, 5.5]));
+
+        // Plants are two planes seen from both sides, and don't hide the block under them
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.set_voxel(&IVec3::new(5, 4, 5), stone());
+        chunk.set_voxel(&IVec3::new(5, 5, 5), grass);
+        assert_eq!(quads(&chunk), 6 + 4);
+    }
+
+    #[test]
+    fn test_sort_quads_back_to_front() {
+        let registry = BlockRegistry::default();
+        let ice = Block::new(registry.block_type("ice").unwrap());
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.set_voxel(&IVec3::new(2, 5, 5), ice);
+        chunk.set_voxel(&IVec3::new(10, 5, 5), ice);
+        let mut mesh = create_section_mesh(&chunk, 0, &registry).translucent;
+
+        let quad_x = |mesh: &Mesh, quad: usize| {
+            let Some(VertexAttributeValues::Float32x3(positions)) =
+                mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+            else {
+                panic!("the mesh has no positions");
+            };
+            let Some(Indices::U32(indices)) = mesh.indices() else {
+                panic!("the mesh has no indices");
+            };
+            positions[indices[quad * 6] as usize][0]
+        };
+
+        // The far side of the far block first, the near side of the near block last
+        sort_quads_back_to_front(&mut mesh, Vec3::new(-20.0, 5.0, 5.0));
+        assert_eq!(quad_x(&mesh, 0), 10.5);
+        assert_eq!(quad_x(&mesh, 11), 1.5);
+
+        sort_quads_back_to_front(&mut mesh, Vec3::new(30.0, 5.0, 5.0));
+        assert_eq!(quad_x(&mesh, 0), 1.5);
+        assert_eq!(quad_x(&mesh, 11), 10.5);
+        assert_eq!(mesh.indices().unwrap().len(), 12 * 6);
+    }
+
+    #[test]
+    fn test_quad_split_follows_occlusion() {
+        assert_eq!(quad_indices([3; 4]), [0, 1, 2, 0, 2, 3]);
+        // A single occluded corner is split off on its own triangle
+        assert_eq!(quad_indices([0, 3, 3, 3]), [1, 2, 3, 1, 3, 0]);
+        assert_eq!(quad_indices([3, 0, 3, 3]), [0, 1, 2, 0, 2, 3]);
+    }
+}
@@ -0,0 +1,181 @@
+//! Upgrades chunks serialized by older versions of the game to the current [`Chunk`] format
+//!
+//! Each old format keeps a frozen copy of its types here, so they can still be decoded after
+//! the live types changed.
+
+use crate::voxel::block::Block;
+use crate::voxel::block_registry::BlockRegistry;
+use crate::voxel::chunk::Chunk;
+use bevy::math::IVec3;
+use bincode::config;
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+use std::io;
+
+/// Chunk dimensions of version 0, which stored every block of a column
+const LEGACY_CHUNK_SIZE: i32 = 16;
+const LEGACY_CHUNK_HEIGHT: i32 = 256;
+const LEGACY_CHUNK_VOLUME: usize =
+    (LEGACY_CHUNK_SIZE * LEGACY_CHUNK_SIZE * LEGACY_CHUNK_HEIGHT) as usize;
+
+/// Name of each block id used before blocks were defined in a data file
+const LEGACY_BLOCK_NAMES: [&str; 4] = ["air", "grass", "dirt", "stone"];
+
+/// Version 0: chunks written before the format was versioned
+///
+/// There is no header, and block types are encoded by their enum variant index.
+mod v0 {
+    use super::*;
+
+    #[derive(Copy, Clone, Serialize, Deserialize)]
+    pub enum BlockType {
+        Void,
+        Grass,
+        Dirt,
+        Stone,
+    }
+
+    #[derive(Copy, Clone, Serialize, Deserialize)]
+    pub struct Block {
+        pub voxel_type: BlockType,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct Chunk {
+        #[serde(with = "BigArray")]
+        pub voxels: [Block; LEGACY_CHUNK_VOLUME],
+        pub pos: IVec3,
+    }
+}
+
+/// Index of a voxel in the flat block layout of version 0
+fn legacy_index(coordinate: &IVec3) -> usize {
+    (coordinate.z * LEGACY_CHUNK_SIZE * LEGACY_CHUNK_HEIGHT
+        + coordinate.y * LEGACY_CHUNK_SIZE
+        + coordinate.x) as usize
+}
+
+/// Fill a new chunk with the blocks of a flat legacy layout
+fn chunk_from_legacy(
+    pos: IVec3,
+    mut block_at: impl FnMut(usize) -> io::Result<Block>,
+) -> io::Result<Chunk> {
+    let mut chunk = Chunk::new(pos);
+
+    for x in 0..LEGACY_CHUNK_SIZE {
+        for y in 0..LEGACY_CHUNK_HEIGHT {
+            for z in 0..LEGACY_CHUNK_SIZE {
+                let coordinate = IVec3::new(x, y, z);
+                chunk.set_voxel(&coordinate, block_at(legacy_index(&coordinate))?);
+            }
+        }
+    }
+
+    Ok(chunk)
+}
+
+fn invalid_data(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+/// Block of the current registry for a block id of version 0
+fn legacy_block(registry: &BlockRegistry, id: u16) -> io::Result<Block> {
+    LEGACY_BLOCK_NAMES
+        .get(id as usize)
+        .and_then(|name| registry.block_type(name))
+        .map(Block::new)
+        .ok_or_else(|| invalid_data(format!("unknown block id {}", id)))
+}
+
+fn migrate_v0(data: &[u8], registry: &BlockRegistry) -> io::Result<Chunk> {
+    let legacy: Box<v0::Chunk> = Box::new(
+        bincode::serde::decode_from_slice(data, config::standard())
+            .map_err(invalid_data)?
+            .0,
+    );
+
+    chunk_from_legacy(legacy.pos, |index| {
+        // Variants are in the order of the legacy ids
+        legacy_block(registry, legacy.voxels[index].voxel_type as u16)
+    })
+}
+
+/// Decode a chunk body written with an older format `version`
+///
+/// None of the older formats stored light, so it is computed again, guessing that the chunks
+/// from y = 0 up are under the open sky.
+pub fn migrate_chunk(version: u16, data: &[u8], registry: &BlockRegistry) -> io::Result<Chunk> {
+    let mut chunk = match version {
+        0 => migrate_v0(data, registry),
+        _ => Err(invalid_data(format!(
+            "no migration from chunk format version {}",
+            version
+        ))),
+    }?;
+
+    let sky_above = chunk.pos.y >= 0;
+    chunk.compute_light(registry, |_, _| sky_above);
+
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::voxel::block::BlockType;
+    use crate::voxel::block_registry::BlockRegistry;
+    use crate::voxel::chunk::{Chunk, CHUNK_SIZE};
+    use crate::voxel::light::{Light, MAX_LIGHT};
+    use bevy::math::IVec3;
+
+    fn assert_fixture_chunk(chunk: &Chunk) {
+        let registry = BlockRegistry::default();
+        let [grass, dirt, stone] =
+            ["grass", "dirt", "stone"].map(|name| registry.block_type(name).unwrap());
+
+        assert_eq!(chunk.pos, IVec3::new(2, 0, -3));
+
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for y in 0..10 {
+                    assert_eq!(chunk.voxel_at(&IVec3::new(x, y, z)).voxel_type, stone);
+                }
+                assert_eq!(chunk.voxel_at(&IVec3::new(x, 10, z)).voxel_type, dirt);
+            }
+        }
+
+        assert_eq!(chunk.voxel_at(&IVec3::new(1, 11, 1)).voxel_type, grass);
+        assert_eq!(
+            chunk.voxel_at(&IVec3::new(2, 11, 1)).voxel_type,
+            BlockType::AIR
+        );
+    }
+
+    #[test]
+    fn test_migrate_v0_fixture() {
+        let chunk = Chunk::try_from_compressed(
+            include_bytes!("../../tests/fixtures/chunk_v0.bin"),
+            &BlockRegistry::default(),
+        )
+        .unwrap();
+
+        assert_fixture_chunk(&chunk);
+        // Light is computed for the migrated chunk
+        assert_eq!(chunk.light_at(&IVec3::new(2, 11, 1)).sky(), MAX_LIGHT);
+        assert_eq!(chunk.light_at(&IVec3::new(1, 5, 1)), Light::DARK);
+    }
+
+    #[test]
+    fn test_v1_fixture() {
+        let chunk = Chunk::try_from_compressed(
+            include_bytes!("../../tests/fixtures/chunk_v1.bin"),
+            &BlockRegistry::default(),
+        )
+        .unwrap();
+
+        assert_fixture_chunk(&chunk);
+
+        let recompressed =
+            Chunk::try_from_compressed(&chunk.compress(), &BlockRegistry::default()).unwrap();
+        assert_fixture_chunk(&recompressed);
+    }
+}
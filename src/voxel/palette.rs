@@ -0,0 +1,396 @@
+//! Palette compressed block storage
+//!
+//! Instead of a full [`Block`] per position, a [`PalettedStorage`] keeps the distinct blocks it
+//! holds in a palette, and a bit-packed palette index per position, like Minecraft's chunk
+//! section palettes. A chunk that is all air needs no index data at all, and one made of 4 kinds
+//! of blocks only needs 2 bits per block.
+
+use crate::voxel::block::Block;
+use crate::voxel::chunk::SECTION_VOLUME;
+use serde::{Deserialize, Serialize};
+
+/// Largest index size, enough for a palette holding a different block at every position of a
+/// chunk
+pub const MAX_BITS_PER_BLOCK: u32 = 16;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(
+    try_from = "SerializedPalettedStorage",
+    into = "SerializedPalettedStorage"
+)]
+pub struct PalettedStorage {
+    len: usize,
+    palette: Vec<Block>,
+    /// Size of an index in `data`, 0 when the palette only holds one block
+    bits_per_block: u32,
+    /// Palette indices, packed without spanning across words
+    data: Vec<u64>,
+}
+
+/// Layout checked before being trusted as a [`PalettedStorage`], as it comes from disk or the
+/// network, and written without the blocks no position uses anymore
+#[derive(Serialize, Deserialize)]
+struct SerializedPalettedStorage {
+    len: usize,
+    palette: Vec<Block>,
+    bits_per_block: u32,
+    data: Vec<u64>,
+}
+
+impl PalettedStorage {
+    /// Storage of `len` blocks, all set to `block`
+    pub fn new(len: usize, block: Block) -> Self {
+        Self {
+            len,
+            palette: vec![block],
+            bits_per_block: 0,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn palette(&self) -> &[Block] {
+        &self.palette
+    }
+
+    pub fn bits_per_block(&self) -> u32 {
+        self.bits_per_block
+    }
+
+    /// Memory allocated for the palette and the indices, in bytes
+    pub fn heap_size(&self) -> usize {
+        self.palette.capacity() * size_of::<Block>() + self.data.capacity() * size_of::<u64>()
+    }
+
+    pub fn get(&self, index: usize) -> Block {
+        assert!(index < self.len, "block index {} out of bounds", index);
+
+        self.palette[self.palette_index(index)]
+    }
+
+    /// Set the block at `index`, returning the one it replaced
+    ///
+    /// The indices only get wider when the palette outgrows them, and the blocks no position
+    /// uses anymore can't be dropped first.
+    pub fn set(&mut self, index: usize, block: Block) -> Block {
+        assert!(index < self.len, "block index {} out of bounds", index);
+
+        let palette_index = match self.palette.iter().position(|entry| *entry == block) {
+            Some(palette_index) => palette_index,
+            None => {
+                if self.palette.len() == 1 << self.bits_per_block {
+                    self.compact();
+                }
+                self.palette.push(block);
+
+                if self.palette.len() > 1 << self.bits_per_block {
+                    self.resize(bits_needed(self.palette.len()));
+                }
+                self.palette.len() - 1
+            }
+        };
+
+        let previous = self.palette_index(index);
+        self.write_palette_index(index, palette_index);
+
+        self.palette[previous]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Block> + '_ {
+        (0..self.len).map(|index| self.get(index))
+    }
+
+    fn blocks_per_word(bits_per_block: u32) -> usize {
+        (u64::BITS / bits_per_block) as usize
+    }
+
+    fn word_count(len: usize, bits_per_block: u32) -> usize {
+        if bits_per_block == 0 {
+            0
+        } else {
+            len.div_ceil(Self::blocks_per_word(bits_per_block))
+        }
+    }
+
+    fn palette_index(&self, index: usize) -> usize {
+        if self.bits_per_block == 0 {
+            return 0;
+        }
+
+        let blocks_per_word = Self::blocks_per_word(self.bits_per_block);
+        let shift = (index % blocks_per_word) as u32 * self.bits_per_block;
+        let mask = (1 << self.bits_per_block) - 1;
+
+        ((self.data[index / blocks_per_word] >> shift) & mask) as usize
+    }
+
+    fn write_palette_index(&mut self, index: usize, palette_index: usize) {
+        if self.bits_per_block == 0 {
+            return;
+        }
+
+        let blocks_per_word = Self::blocks_per_word(self.bits_per_block);
+        let shift = (index % blocks_per_word) as u32 * self.bits_per_block;
+        let mask = (1 << self.bits_per_block) - 1;
+        let word = &mut self.data[index / blocks_per_word];
+
+        *word = (*word & !(mask << shift)) | ((palette_index as u64) << shift);
+    }
+
+    /// Repack every index with `bits_per_block` bits
+    fn resize(&mut self, bits_per_block: u32) {
+        let mut resized = Self {
+            len: self.len,
+            palette: Vec::new(),
+            bits_per_block,
+            data: vec![0; Self::word_count(self.len, bits_per_block)],
+        };
+
+        for index in 0..self.len {
+            resized.write_palette_index(index, self.palette_index(index));
+        }
+
+        self.bits_per_block = bits_per_block;
+        self.data = resized.data;
+    }
+
+    /// Drop the blocks no position uses anymore from the palette, and narrow the indices to what
+    /// is left
+    fn compact(&mut self) {
+        let mut used = vec![false; self.palette.len()];
+        for index in 0..self.len {
+            used[self.palette_index(index)] = true;
+        }
+        if self.len == 0 || used.iter().all(|used| *used) {
+            return;
+        }
+
+        // New palette index of each block still used
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::new();
+        for (palette_index, block) in self.palette.iter().enumerate() {
+            if used[palette_index] {
+                remap[palette_index] = palette.len();
+                palette.push(*block);
+            }
+        }
+
+        let mut compacted = Self {
+            len: self.len,
+            bits_per_block: bits_needed(palette.len()),
+            data: Vec::new(),
+            palette,
+        };
+        compacted.data = vec![0; Self::word_count(compacted.len, compacted.bits_per_block)];
+        for index in 0..self.len {
+            compacted.write_palette_index(index, remap[self.palette_index(index)]);
+        }
+
+        *self = compacted;
+    }
+}
+
+/// Smallest index size able to address a palette of `palette_len` blocks
+fn bits_needed(palette_len: usize) -> u32 {
+    usize::BITS - (palette_len - 1).leading_zeros()
+}
+
+impl From<PalettedStorage> for SerializedPalettedStorage {
+    fn from(mut storage: PalettedStorage) -> Self {
+        storage.compact();
+
+        Self {
+            len: storage.len,
+            palette: storage.palette,
+            bits_per_block: storage.bits_per_block,
+            data: storage.data,
+        }
+    }
+}
+
+impl TryFrom<SerializedPalettedStorage> for PalettedStorage {
+    type Error = String;
+
+    fn try_from(serialized: SerializedPalettedStorage) -> Result<Self, Self::Error> {
+        let storage = Self {
+            len: serialized.len,
+            palette: serialized.palette,
+            bits_per_block: serialized.bits_per_block,
+            data: serialized.data,
+        };
+
+        if storage.len > SECTION_VOLUME {
+            return Err(format!(
+                "{} blocks, more than a chunk section holds",
+                storage.len
+            ));
+        }
+        if storage.palette.is_empty() {
+            return Err("empty block palette".to_string());
+        }
+        if storage.bits_per_block > MAX_BITS_PER_BLOCK
+            || storage.palette.len() > 1 << storage.bits_per_block
+        {
+            return Err(format!(
+                "{} bits per block can't index a palette of {} blocks",
+                storage.bits_per_block,
+                storage.palette.len()
+            ));
+        }
+        if storage.data.len() != Self::word_count(storage.len, storage.bits_per_block) {
+            return Err(format!(
+                "{} words of block indices for {} blocks",
+                storage.data.len(),
+                storage.len
+            ));
+        }
+        // Every index is 0 without bits, and the palette isn't empty
+        if storage.bits_per_block > 0
+            && (0..storage.len).any(|index| storage.palette_index(index) >= storage.palette.len())
+        {
+            return Err("block index outside of the palette".to_string());
+        }
+
+        Ok(storage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::block::BlockType;
+    use bincode::config;
+
+    const GRASS: BlockType = BlockType::from_id(1);
+    const DIRT: BlockType = BlockType::from_id(2);
+    const STONE: BlockType = BlockType::from_id(3);
+    const SAND: BlockType = BlockType::from_id(4);
+
+    #[test]
+    fn test_grows_bits_only_when_needed() {
+        let mut storage = PalettedStorage::new(4096, Block::new_empty());
+        assert_eq!(storage.bits_per_block(), 0);
+        assert_eq!(storage.heap_size(), size_of::<Block>());
+
+        storage.set(0, Block::new(STONE));
+        assert_eq!(storage.bits_per_block(), 1);
+
+        // Already in the palette
+        storage.set(1, Block::new(STONE));
+        storage.set(2, Block::new_empty());
+        assert_eq!(storage.bits_per_block(), 1);
+
+        storage.set(3, Block::new(DIRT));
+        storage.set(4, Block::new(GRASS));
+        assert_eq!(storage.bits_per_block(), 2);
+
+        assert_eq!(storage.get(0).voxel_type, STONE);
+        assert_eq!(storage.get(1).voxel_type, STONE);
+        assert_eq!(storage.get(2).voxel_type, BlockType::AIR);
+        assert_eq!(storage.get(3).voxel_type, DIRT);
+        assert_eq!(storage.get(4).voxel_type, GRASS);
+        assert_eq!(storage.get(4095).voxel_type, BlockType::AIR);
+    }
+
+    #[test]
+    fn test_set_returns_previous_block() {
+        let mut storage = PalettedStorage::new(16, Block::new_empty());
+
+        assert_eq!(storage.set(5, Block::new(DIRT)).voxel_type, BlockType::AIR);
+        assert_eq!(storage.set(5, Block::new(STONE)).voxel_type, DIRT);
+    }
+
+    #[test]
+    fn test_serialization_roundtrip() {
+        let mut storage = PalettedStorage::new(1000, Block::new_empty());
+        for index in (0..1000).step_by(7) {
+            storage.set(index, Block::new(STONE));
+        }
+
+        let encoded = bincode::serde::encode_to_vec(&storage, config::standard()).unwrap();
+        let (decoded, _): (PalettedStorage, _) =
+            bincode::serde::decode_from_slice(&encoded, config::standard()).unwrap();
+
+        assert!(storage.iter().eq(decoded.iter()));
+    }
+
+    #[test]
+    fn test_rejects_invalid_data() {
+        let mut storage = PalettedStorage::new(100, Block::new_empty());
+        storage.set(0, Block::new(STONE));
+        storage.set(1, Block::new(DIRT));
+
+        // An index pointing past the 3 blocks of the palette, written as is rather than
+        // compacted
+        storage.write_palette_index(2, 3);
+        let invalid = SerializedPalettedStorage {
+            len: storage.len,
+            palette: storage.palette.clone(),
+            bits_per_block: storage.bits_per_block,
+            data: storage.data.clone(),
+        };
+
+        let encoded = bincode::serde::encode_to_vec(&invalid, config::standard()).unwrap();
+        assert!(bincode::serde::decode_from_slice::<PalettedStorage, _>(
+            &encoded,
+            config::standard()
+        )
+        .is_err());
+
+        // Without bits every index is valid, but a huge storage must not be walked through
+        let huge = SerializedPalettedStorage {
+            len: usize::MAX,
+            palette: vec![Block::new_empty()],
+            bits_per_block: 0,
+            data: Vec::new(),
+        };
+        assert!(PalettedStorage::try_from(huge).is_err());
+    }
+
+    #[test]
+    fn test_compacts_unused_blocks() {
+        let mut storage = PalettedStorage::new(64, Block::new_empty());
+        storage.set(0, Block::new(STONE));
+        storage.set(1, Block::new(DIRT));
+        storage.set(2, Block::new(GRASS));
+        assert_eq!(storage.bits_per_block(), 2);
+
+        // The blocks no position uses stay in the palette while it has room
+        for index in 0..64 {
+            storage.set(index, Block::new(STONE));
+        }
+        storage.set(1, Block::new(GRASS));
+        storage.set(2, Block::new(DIRT));
+        assert_eq!(storage.palette().len(), 4);
+        assert_eq!(storage.bits_per_block(), 2);
+
+        storage.set(2, Block::new(STONE));
+        let encoded = bincode::serde::encode_to_vec(&storage, config::standard()).unwrap();
+        let (decoded, _): (PalettedStorage, _) =
+            bincode::serde::decode_from_slice(&encoded, config::standard()).unwrap();
+        assert_eq!(decoded.palette().len(), 2);
+        assert_eq!(decoded.bits_per_block(), 1);
+        assert!(storage.iter().eq(decoded.iter()));
+
+        // A full palette is compacted before growing, only air and grass are left in this one
+        let mut storage = PalettedStorage::new(64, Block::new_empty());
+        storage.set(0, Block::new(STONE));
+        storage.set(0, Block::new(DIRT));
+        storage.set(0, Block::new(GRASS));
+        assert_eq!(storage.palette().len(), 4);
+
+        storage.set(1, Block::new(SAND));
+        assert_eq!(storage.palette().len(), 3);
+        assert_eq!(storage.bits_per_block(), 2);
+        assert_eq!(storage.get(0).voxel_type, GRASS);
+        assert_eq!(storage.get(1).voxel_type, SAND);
+        assert_eq!(storage.get(63).voxel_type, BlockType::AIR);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::ClientState;
 use bevy::image::{ImageAddressMode, ImageFilterMode, ImageSampler, ImageSamplerDescriptor};
+use bevy::pbr::{ExtendedMaterial, MaterialExtension};
 use bevy::prelude::*;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef};
 
 pub const BLOCK_TEXTURE_ROWS: u8 = 8;
 pub const BLOCK_TEXTURE_COLUMNS: u8 = 16;
@@ -9,14 +11,46 @@ pub const UV_WIDTH: f32 = 1.0 / BLOCK_TEXTURE_COLUMNS as f32;
 pub const UV_HEIGHT: f32 = 1.0 / BLOCK_TEXTURE_ROWS as f32;
 pub type UvCoordinate = [Vec2; 4];
 
+/// Material of the chunk meshes
+pub type BlockMaterial = ExtendedMaterial<StandardMaterial, TiledAtlas>;
+
+/// Repeats a tile of the atlas over each block of a quad, so merged faces don't stretch it
+///
+/// Block meshes count blocks across each quad in `UV_0` and give the top left corner of its tile
+/// in `UV_1`, see `assets/shaders/block.wgsl`.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct TiledAtlas {
+    /// Size of a tile in the atlas, in UV units
+    #[uniform(100)]
+    pub tile_size: Vec2,
+}
+
+impl MaterialExtension for TiledAtlas {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/block.wgsl".into()
+    }
+
+    fn deferred_fragment_shader() -> ShaderRef {
+        "shaders/block.wgsl".into()
+    }
+}
+
 #[derive(Resource)]
 pub struct ResourcePack {
-    pub handle: Handle<StandardMaterial>,
+    /// Opaque and cutout blocks, the transparent pixels of the texture discarded
+    pub handle: Handle<BlockMaterial>,
+    /// Same texture blended with what is behind it, for translucent blocks and fluids
+    pub translucent: Handle<BlockMaterial>,
 }
 
 #[derive(Resource, Default)]
 struct TexturePackLoading(Handle<Image>);
 
+/// Top left corner of the tile of a face in the atlas
+pub fn tile_origin(face_id: u16) -> Vec2 {
+    convert_face_id_to_uv(face_id)[0]
+}
+
 pub fn convert_face_id_to_uv(face_id: u16) -> UvCoordinate {
     let row = (face_id as u8) / BLOCK_TEXTURE_COLUMNS;
     let col = (face_id as u8) % BLOCK_TEXTURE_COLUMNS;
@@ -37,21 +71,39 @@ pub fn convert_face_id_to_uv(face_id: u16) -> UvCoordinate {
 fn setup_texture(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut materials: ResMut<Assets<BlockMaterial>>,
     mut loading: ResMut<TexturePackLoading>,
 ) {
     let custom_texture_handle: Handle<Image> = asset_server.load("textures/spritesheet_blocks.png");
 
     *loading = TexturePackLoading(custom_texture_handle.clone());
 
-    let resource_pack = materials.add(StandardMaterial {
-        base_color_texture: Some(custom_texture_handle),
-        unlit: true,
-        ..Default::default()
+    // Unlit, as the light of the voxels is baked into the vertex colors of the meshes
+    let tiled = TiledAtlas {
+        tile_size: Vec2::new(UV_WIDTH, UV_HEIGHT),
+    };
+    let resource_pack = materials.add(BlockMaterial {
+        base: StandardMaterial {
+            base_color_texture: Some(custom_texture_handle.clone()),
+            unlit: true,
+            alpha_mode: AlphaMode::Mask(0.5),
+            ..Default::default()
+        },
+        extension: tiled.clone(),
+    });
+    let translucent = materials.add(BlockMaterial {
+        base: StandardMaterial {
+            base_color_texture: Some(custom_texture_handle),
+            unlit: true,
+            alpha_mode: AlphaMode::Blend,
+            ..Default::default()
+        },
+        extension: tiled,
     });
 
     commands.insert_resource(ResourcePack {
         handle: resource_pack,
+        translucent,
     });
 }
 
@@ -89,7 +141,8 @@ fn check_assets_ready(
 pub struct TexturePlugin;
 impl Plugin for TexturePlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<TexturePackLoading>()
+        app.add_plugins(MaterialPlugin::<BlockMaterial>::default())
+            .init_resource::<TexturePackLoading>()
             .add_systems(
                 Update,
                 check_assets_ready.run_if(in_state(ClientState::LoadingTexture)),
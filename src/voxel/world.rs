@@ -1,13 +1,28 @@
 use crate::chunk::ServerChunkEntity;
-use crate::voxel::block::{Block, BlockType};
-use crate::voxel::chunk::ChunkEntity;
-use crate::voxel::chunk::{Chunk, CHUNK_HEIGHT, CHUNK_SIZE};
+use crate::player::{Player, PlayerSpawn};
+use crate::storage::level::LevelData;
+use crate::storage::{
+    advance_world_time_system, autosave_world_system, load_level_system, save_world_on_exit_system,
+    WorldStorage,
+};
+use crate::voxel::block::Block;
+use crate::voxel::block_entity::BlockEntity;
+use crate::voxel::block_model::{block_boxes, ModelBox};
+use crate::voxel::block_registry::BlockRegistry;
+use crate::voxel::chunk::{
+    Chunk, ChunkEntity, ChunkSectionEntity, ChunkSections, SectionTranslucentEntity, CHUNK_HEIGHT,
+    CHUNK_SIZE, SECTION_COUNT,
+};
+use crate::voxel::light::{propagate, LightUpdates, WorldLight};
 use crate::{Channel, ClientMessage, ClientState, ResMut, ServerState};
 use bevy::app::App;
-use bevy::math::{FloatOrd, IVec2, IVec3, Vec3};
+use bevy::ecs::schedule::common_conditions::resource_exists;
+use bevy::ecs::system::SystemParam;
+use bevy::math::{IVec2, IVec3, Vec3, Vec3Swizzles};
 use bevy::prelude::{
-    default, Commands, Component, Entity, Mesh, OnEnter, Plugin, PointLight, Res, Resource,
-    Transform,
+    default, in_state, Assets, Children, Commands, Component, Condition, DespawnRecursiveExt,
+    Entity, IntoSystemConfigs, Last, Local, Mesh, Mesh3d, OnEnter, Plugin, PointLight, Query, Res,
+    Resource, Transform, Update, With,
 };
 use bevy::tasks::Task;
 use bevy_renet::renet::RenetClient;
@@ -19,7 +34,40 @@ use std::sync::{Arc, RwLock, Weak};
 pub struct ComputeMesh(pub Task<(Mesh, IVec3)>);
 
 pub const DEFAULT_MAX_CHUNKS: usize = 10000;
-pub const WORLD_SIZE: i32 = 5;
+/// Offsets of the chunks around a chunk, in the order of [`Chunk::neighbors`]
+const NEIGHBOR_OFFSETS: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::X,
+    IVec3::NEG_Z,
+    IVec3::Z,
+    IVec3::NEG_Y,
+    IVec3::Y,
+];
+/// Chunks generated around the origin when the server starts
+pub const SPAWN_AREA_SIZE: i32 = 5;
+
+pub const DEFAULT_VIEW_DISTANCE: i32 = 8;
+pub const MIN_VIEW_DISTANCE: i32 = 2;
+pub const MAX_VIEW_DISTANCE: i32 = 32;
+/// Chunks are 256 blocks high, so one chunk above and below the player is plenty
+pub const DEFAULT_VERTICAL_VIEW_DISTANCE: i32 = 1;
+
+#[derive(Resource)]
+pub struct ChunkLoadingSettings {
+    /// Radius, in chunks, of the area loaded around the player
+    pub view_distance: i32,
+    /// Number of chunks loaded above and below the player
+    pub vertical_view_distance: i32,
+}
+
+impl Default for ChunkLoadingSettings {
+    fn default() -> Self {
+        Self {
+            view_distance: DEFAULT_VIEW_DISTANCE,
+            vertical_view_distance: DEFAULT_VERTICAL_VIEW_DISTANCE,
+        }
+    }
+}
 
 #[derive(Resource)]
 pub struct GameWorld {
@@ -39,7 +87,9 @@ pub type ChunkDataMap = HashMap<IVec3, Arc<RwLock<Chunk>>>;
 pub struct World {
     pub(crate) chunk_data_map: Arc<RwLock<ChunkDataMap>>,
     pub(crate) chunk_entities: Arc<RwLock<HashMap<IVec3, Entity>>>,
-    pub(crate) dirty_chunks: Arc<RwLock<HashSet<IVec3>>>,
+    /// Sections to remesh, see [`World::section_coord`]
+    pub(crate) dirty_sections: Arc<RwLock<HashSet<IVec3>>>,
+    pub(crate) unsaved_chunks: Arc<RwLock<HashSet<IVec3>>>,
     pub(crate) pending_requested_chunks: Arc<RwLock<HashSet<IVec3>>>,
     pub(crate) pending_generating_chunks: Arc<RwLock<HashMap<IVec3, HashSet<u64>>>>,
     pub(crate) players: Arc<RwLock<HashMap<u64, Entity>>>,
@@ -56,7 +106,8 @@ impl World {
         Self {
             chunk_data_map: Arc::new(RwLock::new(HashMap::with_capacity(DEFAULT_MAX_CHUNKS))),
             chunk_entities: Arc::new(RwLock::new(HashMap::with_capacity(DEFAULT_MAX_CHUNKS))),
-            dirty_chunks: Arc::new(RwLock::new(HashSet::with_capacity(DEFAULT_MAX_CHUNKS))),
+            dirty_sections: Arc::new(RwLock::new(HashSet::with_capacity(DEFAULT_MAX_CHUNKS))),
+            unsaved_chunks: Arc::new(RwLock::new(HashSet::new())),
             pending_requested_chunks: Arc::new(RwLock::new(HashSet::with_capacity(
                 DEFAULT_MAX_CHUNKS,
             ))),
@@ -82,6 +133,72 @@ impl World {
             local_pos.z -= CHUNK_SIZE;
             chunk_pos.z += 1;
         }
+        while local_pos.y < 0 {
+            local_pos.y += CHUNK_HEIGHT;
+            chunk_pos.y -= 1;
+        }
+        while local_pos.y >= CHUNK_HEIGHT {
+            local_pos.y -= CHUNK_HEIGHT;
+            chunk_pos.y += 1;
+        }
+    }
+
+    /// Coordinate of the chunk containing a voxel
+    pub fn get_chunk_coord(global_coord: &IVec3) -> IVec3 {
+        let mut chunk_coord = IVec3::default();
+        let mut local_coord = *global_coord;
+        Self::make_coords_valid(&mut chunk_coord, &mut local_coord);
+        chunk_coord
+    }
+
+    /// Coordinate of a section in the world, its y being counted in sections from the bottom of
+    /// the world
+    pub fn section_coord(chunk_coord: &IVec3, section_index: usize) -> IVec3 {
+        IVec3::new(
+            chunk_coord.x,
+            chunk_coord.y * SECTION_COUNT as i32 + section_index as i32,
+            chunk_coord.z,
+        )
+    }
+
+    /// Chunk coordinate and index in that chunk of a section, the opposite of
+    /// [`World::section_coord`]
+    pub fn section_chunk(section_coord: &IVec3) -> (IVec3, usize) {
+        (
+            IVec3::new(
+                section_coord.x,
+                section_coord.y.div_euclid(SECTION_COUNT as i32),
+                section_coord.z,
+            ),
+            section_coord.y.rem_euclid(SECTION_COUNT as i32) as usize,
+        )
+    }
+
+    /// Mark every section of a chunk to be remeshed
+    pub fn mark_chunk_dirty(&self, chunk_coord: &IVec3) {
+        self.dirty_sections
+            .write()
+            .unwrap()
+            .extend((0..SECTION_COUNT).map(|section| Self::section_coord(chunk_coord, section)));
+    }
+
+    /// Chunks within `radius` chunks horizontally and `vertical_radius` chunks vertically of
+    /// `center`, closest first
+    pub fn chunks_in_radius(center: IVec3, radius: i32, vertical_radius: i32) -> Vec<IVec3> {
+        let mut chunks = Vec::new();
+
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                if x * x + z * z <= radius * radius {
+                    for y in -vertical_radius..=vertical_radius {
+                        chunks.push(center + IVec3::new(x, y, z));
+                    }
+                }
+            }
+        }
+
+        chunks.sort_by_key(|chunk_coord| (*chunk_coord - center).length_squared());
+        chunks
     }
 
     pub fn get_voxel(&self, global_coord: &IVec3) -> Option<Block> {
@@ -98,19 +215,116 @@ impl World {
         }
     }
 
-    pub fn edit_voxel(&self, global_coord: &IVec3, voxel_type: BlockType) {
+    pub fn edit_voxel(&self, global_coord: &IVec3, block: Block, registry: &BlockRegistry) {
         let mut chunk_coord = IVec3::default();
         let mut local_coord = *global_coord;
         Self::make_coords_valid(&mut chunk_coord, &mut local_coord);
-        let chunks = self.chunk_data_map.read().unwrap();
-        let chunk = chunks.get(&chunk_coord);
 
-        if let Some(chunk) = chunk {
-            chunk
-                .write()
-                .unwrap()
-                .edit_voxel(self, local_coord, voxel_type);
+        if let Some(chunk) = self.get_chunk(chunk_coord) {
+            let light_updates =
+                chunk
+                    .write()
+                    .unwrap()
+                    .edit_voxel(self, local_coord, block, registry);
+            self.unsaved_chunks.write().unwrap().insert(chunk_coord);
+
+            // The chunk is unlocked, so the light can go on through the chunks around
+            if !light_updates.is_empty() {
+                propagate(&mut WorldLight::new(self), light_updates, registry);
+            }
+        }
+    }
+
+    /// Spread the light across the borders between a chunk and the loaded chunks around it
+    pub fn spread_light_between(&self, chunk_coord: &IVec3, registry: &BlockRegistry) {
+        let Some(chunk) = self.get_chunk(*chunk_coord) else {
+            return;
+        };
+        let chunk_size = IVec3::new(CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE);
+        let face = |offset: i32, size: i32| match offset {
+            -1 => 0..1,
+            1 => size - 1..size,
+            _ => 0..size,
+        };
+        let mut updates = LightUpdates::default();
+
+        {
+            let chunk = chunk.read().unwrap();
+
+            for (i, offset) in NEIGHBOR_OFFSETS.iter().enumerate() {
+                let Some(neighbor) = chunk.neighbors[i].upgrade() else {
+                    continue;
+                };
+                let neighbor = neighbor.read().unwrap();
+
+                for x in face(offset.x, CHUNK_SIZE) {
+                    for y in face(offset.y, CHUNK_HEIGHT) {
+                        for z in face(offset.z, CHUNK_SIZE) {
+                            let local = IVec3::new(x, y, z);
+                            let neighbor_local = (local + *offset).rem_euclid(chunk_size);
+
+                            // Either side may light the other
+                            if chunk.light_at(&local) != neighbor.light_at(&neighbor_local) {
+                                updates.spread(Self::chunk_local_to_world(&chunk.pos, &local));
+                                updates.spread(Self::chunk_local_to_world(
+                                    &neighbor.pos,
+                                    &neighbor_local,
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
         }
+
+        propagate(&mut WorldLight::new(self), updates, registry);
+    }
+
+    pub fn get_block_entity(&self, global_coord: &IVec3) -> Option<BlockEntity> {
+        let mut chunk_coord = IVec3::default();
+        let mut local_coord = *global_coord;
+        Self::make_coords_valid(&mut chunk_coord, &mut local_coord);
+
+        self.get_chunk(chunk_coord)?
+            .read()
+            .unwrap()
+            .block_entity(&local_coord)
+            .cloned()
+    }
+
+    /// Attach a block entity to the block at a world position, returning the one it replaced
+    ///
+    /// Does nothing if the chunk of the block isn't loaded.
+    pub fn set_block_entity(
+        &self,
+        global_coord: &IVec3,
+        block_entity: BlockEntity,
+    ) -> Option<BlockEntity> {
+        let mut chunk_coord = IVec3::default();
+        let mut local_coord = *global_coord;
+        Self::make_coords_valid(&mut chunk_coord, &mut local_coord);
+        let chunk = self.get_chunk(chunk_coord)?;
+
+        self.unsaved_chunks.write().unwrap().insert(chunk_coord);
+        let mut chunk = chunk.write().unwrap();
+        chunk.set_block_entity(local_coord, block_entity)
+    }
+
+    pub fn remove_block_entity(&self, global_coord: &IVec3) -> Option<BlockEntity> {
+        let mut chunk_coord = IVec3::default();
+        let mut local_coord = *global_coord;
+        Self::make_coords_valid(&mut chunk_coord, &mut local_coord);
+
+        let block_entity = self
+            .get_chunk(chunk_coord)?
+            .write()
+            .unwrap()
+            .remove_block_entity(&local_coord);
+        if block_entity.is_some() {
+            self.unsaved_chunks.write().unwrap().insert(chunk_coord);
+        }
+
+        block_entity
     }
 
     pub fn get_chunk(&self, chunk_coord: IVec3) -> Option<Arc<RwLock<Chunk>>> {
@@ -118,7 +332,7 @@ impl World {
         chunks.get(&chunk_coord).map(Arc::clone)
     }
 
-    pub fn set_chunk(&self, chunk_coord: IVec3, chunk: Chunk) {
+    pub fn set_chunk(&self, chunk_coord: IVec3, chunk: Chunk, registry: &BlockRegistry) {
         let chunk = Arc::new(RwLock::new(chunk));
 
         let neighbors = self.get_neighbors_chunks(&chunk_coord);
@@ -130,10 +344,23 @@ impl World {
 
                 let neighbor = neighbor.upgrade().unwrap();
                 let mut neighbor = neighbor.write().unwrap();
-                // i ^ 1 is the opposite direction of i (i.e. 0 ^ 1 = 1, 1 ^ 1 = 0, 2 ^ 1 = 3, 3 ^ 1 = 2)
+                // i ^ 1 is the opposite direction of i: left and right (0 and 1), back and front
+                // (2 and 3), below and above (4 and 5)
                 neighbor.set_neighbor(i ^ 1, Arc::downgrade(&chunk));
 
-                self.dirty_chunks.write().unwrap().insert(neighbor.pos);
+                self.mark_chunk_dirty(&neighbor.pos);
+            }
+        }
+
+        // The corners of the diagonal chunks get their ambient occlusion from this one
+        for offset in [
+            IVec3::new(-1, 0, -1),
+            IVec3::new(-1, 0, 1),
+            IVec3::new(1, 0, -1),
+            IVec3::new(1, 0, 1),
+        ] {
+            if self.get_chunk(chunk_coord + offset).is_some() {
+                self.mark_chunk_dirty(&(chunk_coord + offset));
             }
         }
 
@@ -141,40 +368,107 @@ impl World {
             .write()
             .unwrap()
             .insert(chunk_coord, chunk);
-        self.dirty_chunks.write().unwrap().insert(chunk_coord);
+        self.spread_light_between(&chunk_coord, registry);
+        self.mark_chunk_dirty(&chunk_coord);
         self.pending_requested_chunks
             .write()
             .unwrap()
             .remove(&chunk_coord);
     }
 
-    pub fn check_block_at_coord(&self, global_coord: &IVec3) -> bool {
+    /// Remove a chunk from the world, unlinking it from its neighbors
+    pub fn remove_chunk(&self, chunk_coord: &IVec3) -> Option<Arc<RwLock<Chunk>>> {
+        let chunk = self.chunk_data_map.write().unwrap().remove(chunk_coord);
+
+        if let Some(chunk) = &chunk {
+            let neighbors = chunk.read().unwrap().neighbors.clone();
+
+            for (i, neighbor) in neighbors.iter().enumerate() {
+                if let Some(neighbor) = neighbor.upgrade() {
+                    // i ^ 1 is the opposite direction of i
+                    neighbor.write().unwrap().set_neighbor(i ^ 1, Weak::new());
+                }
+            }
+        }
+
+        self.dirty_sections
+            .write()
+            .unwrap()
+            .retain(|section_coord| Self::section_chunk(section_coord).0 != *chunk_coord);
+        self.unsaved_chunks.write().unwrap().remove(chunk_coord);
+        self.pending_requested_chunks
+            .write()
+            .unwrap()
+            .remove(chunk_coord);
+
+        chunk
+    }
+
+    /// Whether players collide with the block at a world position, fluids letting them through
+    pub fn check_block_at_coord(&self, global_coord: &IVec3, registry: &BlockRegistry) -> bool {
         if let Some(voxel) = self.get_voxel(global_coord) {
-            voxel.voxel_type != BlockType::Void
+            voxel.is_solid(registry)
         } else {
             false
         }
     }
 
+    /// Whether a point of the world is inside of the shape of a solid block
+    pub fn is_solid_at(&self, point: Vec3, registry: &BlockRegistry) -> bool {
+        let coord = World::coord_to_world(point);
+
+        self.get_voxel(&coord).is_some_and(|voxel| {
+            voxel.is_solid(registry)
+                && self
+                    .block_boxes(&coord, voxel, registry)
+                    .iter()
+                    .any(|model_box| model_box.contains(point - coord.as_vec3()))
+        })
+    }
+
+    /// Boxes of the model of a block of the world, fences connecting to the loaded blocks around
+    /// it
+    fn block_boxes(&self, coord: &IVec3, block: Block, registry: &BlockRegistry) -> Vec<ModelBox> {
+        block_boxes(registry, block, |direction| {
+            self.get_voxel(&(*coord + direction.get_normal().as_ivec3()))
+        })
+    }
+
+    /// Highest solid voxel of a column, looking through the loaded chunks from the top
     pub fn get_highest_block_at_coord(&self, global_coord: &IVec2) -> IVec3 {
         let mut chunk_coord = IVec3::default();
         let mut local_coord = IVec3::new(global_coord.x, CHUNK_HEIGHT - 1, global_coord.y);
         Self::make_coords_valid(&mut chunk_coord, &mut local_coord);
-        let chunks = self.chunk_data_map.read().unwrap();
-        let chunk = chunks.get(&chunk_coord);
 
-        if let Some(chunk) = chunk {
-            while local_coord.y > 0
-                && chunk.read().unwrap().voxels[Chunk::get_index(&local_coord)].voxel_type
-                    == BlockType::Void
-            {
-                local_coord.y -= 1;
-            }
-        } else {
+        let mut column: Vec<Arc<RwLock<Chunk>>> = self
+            .chunk_data_map
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(coord, _)| coord.x == chunk_coord.x && coord.z == chunk_coord.z)
+            .map(|(_, chunk)| Arc::clone(chunk))
+            .collect();
+        column.sort_by_key(|chunk| -chunk.read().unwrap().pos.y);
+
+        if column.is_empty() {
             todo!("Force load chunk, to get the height");
-        };
+        }
+
+        for chunk in &column {
+            let chunk = chunk.read().unwrap();
+
+            for y in (0..CHUNK_HEIGHT).rev() {
+                let coord = local_coord.with_y(y);
 
-        Self::chunk_local_to_world(&chunk_coord, &local_coord)
+                if !chunk.voxel_at(&coord).is_air() {
+                    return Self::chunk_local_to_world(&chunk.pos, &coord);
+                }
+            }
+        }
+
+        // Nothing solid, use the bottom of the lowest loaded chunk
+        let lowest = column.last().unwrap().read().unwrap().pos;
+        Self::chunk_local_to_world(&lowest, &local_coord.with_y(0))
     }
 
     pub fn coord_to_world(origin: Vec3) -> IVec3 {
@@ -188,38 +482,21 @@ impl World {
     pub fn chunk_local_to_world(chunk_coord: &IVec3, voxel_coord: &IVec3) -> IVec3 {
         IVec3::new(
             chunk_coord.x * CHUNK_SIZE + voxel_coord.x,
-            voxel_coord.y,
+            chunk_coord.y * CHUNK_HEIGHT + voxel_coord.y,
             chunk_coord.z * CHUNK_SIZE + voxel_coord.z,
         )
     }
 
-    pub fn get_neighbors_chunks(&self, chunk_coord: &IVec3) -> [Option<Weak<RwLock<Chunk>>>; 4] {
-        [
-            self.chunk_data_map
-                .read()
-                .unwrap()
-                .get(&IVec3::new(chunk_coord.x - 1, chunk_coord.y, chunk_coord.z))
-                .map(Arc::downgrade),
-            self.chunk_data_map
-                .read()
-                .unwrap()
-                .get(&IVec3::new(chunk_coord.x + 1, chunk_coord.y, chunk_coord.z))
-                .map(Arc::downgrade),
-            self.chunk_data_map
-                .read()
-                .unwrap()
-                .get(&IVec3::new(chunk_coord.x, chunk_coord.y, chunk_coord.z - 1))
-                .map(Arc::downgrade),
-            self.chunk_data_map
-                .read()
-                .unwrap()
-                .get(&IVec3::new(chunk_coord.x, chunk_coord.y, chunk_coord.z + 1))
-                .map(Arc::downgrade),
-        ]
+    /// Loaded chunks around a chunk, in the order of [`Chunk::neighbors`]
+    pub fn get_neighbors_chunks(&self, chunk_coord: &IVec3) -> [Option<Weak<RwLock<Chunk>>>; 6] {
+        let chunks = self.chunk_data_map.read().unwrap();
+
+        NEIGHBOR_OFFSETS.map(|offset| chunks.get(&(*chunk_coord + offset)).map(Arc::downgrade))
     }
 
-    /// Ray cast from the origin until it hits a voxel.
-    /// Returns the position of the voxel, the position of the previous voxel and the voxel itself.
+    /// Ray cast from the origin until it hits the shape of a voxel, going through fluids.
+    /// Returns the position of the voxel, the position of the last empty voxel before it and the
+    /// voxel itself.
     /// If it didn't hit a voxel, returns None.
     ///
     /// # Arguments
@@ -231,12 +508,15 @@ impl World {
     /// * `max_distance` - The maximum distance the ray can travel.
     ///
     /// * `step` - The distance between each step of the ray.
+    ///
+    /// * `registry` - The blocks of the world, giving their shapes.
     pub fn ray_casting_voxel(
         &self,
         origin: Vec3,
         direction: Vec3,
         max_distance: f32,
         step: f32,
+        registry: &BlockRegistry,
     ) -> Option<(IVec3, IVec3, Block)> {
         let mut position = origin;
         let mut last_position = origin;
@@ -245,12 +525,18 @@ impl World {
 
         while distance < max_distance {
             position += direction * step;
-            let voxel = self.get_voxel(&World::coord_to_world(position));
-            if voxel.is_some() && voxel.unwrap().voxel_type != BlockType::Void {
+            let coord = World::coord_to_world(position);
+            let voxel = self.get_voxel(&coord);
+            if voxel.is_none_or(|voxel| voxel.is_air() || voxel.is_fluid(registry)) {
+                last_position = position;
+            } else if voxel.is_some_and(|voxel| {
+                self.block_boxes(&coord, voxel, registry)
+                    .iter()
+                    .any(|model_box| model_box.contains(position - coord.as_vec3()))
+            }) {
                 last_voxel = voxel;
                 break;
             }
-            last_position = position;
             distance += step;
         }
 
@@ -264,73 +550,182 @@ impl World {
     }
 }
 
-fn setup_world(
-    mut commands: Commands,
-    client_world: Res<GameWorld>,
-    mut client: ResMut<RenetClient>,
-) {
-    let world = &client_world.world;
+fn setup_world(mut commands: Commands) {
+    commands.spawn((
+        PointLight {
+            intensity: 1000.0,
+            range: 100.0,
+            ..default()
+        },
+        Transform::from_xyz(1.8, 300.0, 1.8).looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+}
 
-    let mut request = Vec::default();
-    for x in -(WORLD_SIZE - 1)..WORLD_SIZE {
-        for z in -(WORLD_SIZE - 1)..WORLD_SIZE {
-            let chunk_pos = IVec3::new(x, 0, z);
-            request.push(chunk_pos);
+/// Where the chunks are loaded around on the client
+#[derive(SystemParam)]
+pub struct StreamingCenter<'w, 's> {
+    player_query: Query<'w, 's, &'static Transform, With<Player>>,
+    spawn: Option<Res<'w, PlayerSpawn>>,
+}
 
-            world
-                .read()
-                .unwrap()
-                .chunk_entities
-                .write()
-                .unwrap()
-                .insert(chunk_pos, commands.spawn(ChunkEntity(chunk_pos)).id());
+impl StreamingCenter<'_, '_> {
+    /// The player, or until it is spawned where it will spawn
+    fn position(&self) -> Vec3 {
+        self.player_query
+            .get_single()
+            .map(|transform| transform.translation)
+            .ok()
+            .or(self.spawn.as_ref().map(|spawn| spawn.0))
+            .unwrap_or(Vec3::ZERO)
+    }
+}
+
+/// Spawns the entities of the chunks on the client, and despawns them with their meshes
+#[derive(SystemParam)]
+pub struct ChunkEntitySpawner<'w, 's> {
+    commands: Commands<'w, 's>,
+    chunk_sections: Query<'w, 's, &'static ChunkSections>,
+    section_meshes: Query<
+        'w,
+        's,
+        (Option<&'static Mesh3d>, Option<&'static Children>),
+        With<ChunkSectionEntity>,
+    >,
+    translucent_meshes: Query<'w, 's, &'static Mesh3d, With<SectionTranslucentEntity>>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+}
+
+impl ChunkEntitySpawner<'_, '_> {
+    fn spawn(&mut self, chunk_coord: IVec3) -> Entity {
+        self.commands
+            .spawn((ChunkEntity(chunk_coord), ChunkSections::default()))
+            .id()
+    }
+
+    fn despawn(&mut self, entity: Entity) {
+        if let Ok(sections) = self.chunk_sections.get(entity) {
+            for section_entity in sections.0.iter().flatten() {
+                if let Ok((mesh, children)) = self.section_meshes.get(*section_entity) {
+                    if let Some(mesh) = mesh {
+                        self.meshes.remove(&mesh.0);
+                    }
+                    for translucent_mesh in self
+                        .translucent_meshes
+                        .iter_many(children.into_iter().flatten())
+                    {
+                        self.meshes.remove(&translucent_mesh.0);
+                    }
+                }
+            }
         }
+        self.commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Request the chunks around the player that are not loaded yet, and unload the ones that
+/// went out of the view distance
+pub fn stream_chunks(
+    game_world: Res<GameWorld>,
+    settings: Res<ChunkLoadingSettings>,
+    streaming_center: StreamingCenter,
+    mut chunk_spawner: ChunkEntitySpawner,
+    mut client: ResMut<RenetClient>,
+    storage: Option<Res<WorldStorage>>,
+    mut last_center: Local<Option<(IVec3, i32, i32)>>,
+) {
+    let position = streaming_center.position();
+    let center = World::get_chunk_coord(&World::coord_to_world(position));
+
+    let distances = (
+        center,
+        settings.view_distance,
+        settings.vertical_view_distance,
+    );
+    if *last_center == Some(distances) {
+        return;
     }
+    *last_center = Some(distances);
 
-    world
+    let world = game_world.world.read().unwrap();
+
+    // Keep one more ring of chunks than requested, so walking along a chunk border does not
+    // load and unload the same chunks over and over
+    let unload_distance = settings.view_distance + 1;
+    let vertical_unload_distance = settings.vertical_view_distance + 1;
+    let out_of_range: Vec<(IVec3, Entity)> = world
+        .chunk_entities
         .read()
         .unwrap()
-        .pending_requested_chunks
-        .write()
-        .unwrap()
-        .extend(request.iter());
+        .iter()
+        .filter(|(chunk_coord, _)| {
+            (**chunk_coord - center).xz().length_squared() > unload_distance * unload_distance
+                || (chunk_coord.y - center.y).abs() > vertical_unload_distance
+        })
+        .map(|(chunk_coord, entity)| (*chunk_coord, *entity))
+        .collect();
 
-    request.sort_by_key(|pos| FloatOrd(Vec3::distance(Vec3::ZERO, pos.as_vec3())));
+    for (chunk_coord, entity) in out_of_range {
+        chunk_spawner.despawn(entity);
 
-    request.iter().for_each(|request| {
         let message = bincode::serde::encode_to_vec(
-            ClientMessage::RequestChunk(*request),
+            ClientMessage::ChunkUnloaded(chunk_coord),
             config::standard(),
         )
         .unwrap();
         client.send_message(Channel::Reliable, message);
-    });
 
-    commands.spawn((
-        PointLight {
-            intensity: 1000.0,
-            range: 100.0,
-            ..default()
-        },
-        Transform::from_xyz(1.8, 300.0, 1.8).looking_at(Vec3::ZERO, Vec3::Y),
-    ));
+        world.chunk_entities.write().unwrap().remove(&chunk_coord);
+        // In singleplayer the world is shared with the server, which saves and evicts the chunk
+        // once no player needs it
+        if storage.is_none() {
+            world.remove_chunk(&chunk_coord);
+        }
+    }
+
+    let request: Vec<IVec3> = World::chunks_in_radius(
+        center,
+        settings.view_distance,
+        settings.vertical_view_distance,
+    )
+    .into_iter()
+    .filter(|chunk_coord| {
+        !world
+            .chunk_entities
+            .read()
+            .unwrap()
+            .contains_key(chunk_coord)
+    })
+    .collect();
+
+    for chunk_coord in request {
+        world
+            .chunk_entities
+            .write()
+            .unwrap()
+            .insert(chunk_coord, chunk_spawner.spawn(chunk_coord));
+        world
+            .pending_requested_chunks
+            .write()
+            .unwrap()
+            .insert(chunk_coord);
+
+        let message = bincode::serde::encode_to_vec(
+            ClientMessage::RequestChunk(chunk_coord),
+            config::standard(),
+        )
+        .unwrap();
+        client.send_message(Channel::Reliable, message);
+    }
 }
 
-fn setup_server_world(mut commands: Commands, server_world: Res<GameWorld>) {
+fn setup_server_world(mut commands: Commands) {
     println!("Setting up server world");
-    let world = &server_world.world;
 
-    for x in -(WORLD_SIZE - 1)..WORLD_SIZE {
-        for z in -(WORLD_SIZE - 1)..WORLD_SIZE {
-            let chunk_pos = IVec3::new(x, 0, z);
-
-            world
-                .read()
-                .unwrap()
-                .chunk_entities
-                .write()
-                .unwrap()
-                .insert(chunk_pos, commands.spawn(ServerChunkEntity(chunk_pos)).id());
+    for x in -(SPAWN_AREA_SIZE - 1)..SPAWN_AREA_SIZE {
+        for z in -(SPAWN_AREA_SIZE - 1)..SPAWN_AREA_SIZE {
+            for y in -DEFAULT_VERTICAL_VIEW_DISTANCE..=DEFAULT_VERTICAL_VIEW_DISTANCE {
+                commands.spawn(ServerChunkEntity(IVec3::new(x, y, z)));
+            }
         }
     }
 }
@@ -339,7 +734,13 @@ pub struct ClientWorldPlugin;
 impl Plugin for ClientWorldPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<GameWorld>()
-            .add_systems(OnEnter(ClientState::LoadingWorld), setup_world);
+            .init_resource::<ChunkLoadingSettings>()
+            .add_systems(OnEnter(ClientState::LoadingWorld), setup_world)
+            .add_systems(
+                Update,
+                stream_chunks
+                    .run_if(in_state(ClientState::LoadingWorld).or(in_state(ClientState::Playing))),
+            );
     }
 }
 
@@ -347,7 +748,20 @@ pub struct ServerWorldPlugin;
 impl Plugin for ServerWorldPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<GameWorld>()
-            .add_systems(OnEnter(ServerState::LoadingWorld), setup_server_world);
+            .add_systems(
+                OnEnter(ServerState::LoadingWorld),
+                (
+                    load_level_system,
+                    setup_server_world.run_if(resource_exists::<LevelData>),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (advance_world_time_system, autosave_world_system)
+                    .run_if(in_state(ServerState::Running)),
+            )
+            .add_systems(Last, save_world_on_exit_system);
     }
 }
 
@@ -355,6 +769,8 @@ impl Plugin for ServerWorldPlugin {
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
+    use crate::connection_config;
+    use bevy::ecs::system::RunSystemOnce;
 
     #[test]
     fn test_make_coords_valid_same_chunk() {
@@ -388,4 +804,187 @@ mod tests {
         assert_eq!(chunk_pos, IVec3::new(1, 0, 0));
         assert_eq!(local_pos, IVec3::new(0, 75, 5));
     }
+
+    #[test]
+    fn test_section_coord() {
+        let chunk_coord = IVec3::new(-2, -1, 7);
+        let section_coord = World::section_coord(&chunk_coord, 3);
+
+        assert_eq!(section_coord, IVec3::new(-2, 3 - SECTION_COUNT as i32, 7));
+        assert_eq!(World::section_chunk(&section_coord), (chunk_coord, 3));
+    }
+
+    #[test]
+    fn test_make_coords_valid_vertical() {
+        let mut chunk_pos = IVec3::new(0, 0, 0);
+        let mut local_pos = IVec3::new(5, -1, 5);
+
+        World::make_coords_valid(&mut chunk_pos, &mut local_pos);
+
+        assert_eq!(chunk_pos, IVec3::new(0, -1, 0));
+        assert_eq!(local_pos, IVec3::new(5, CHUNK_HEIGHT - 1, 5));
+        assert_eq!(
+            World::chunk_local_to_world(&chunk_pos, &local_pos),
+            IVec3::new(5, -1, 5)
+        );
+    }
+
+    #[test]
+    fn test_chunks_in_radius() {
+        let center = IVec3::new(3, 0, -2);
+        let chunks = World::chunks_in_radius(center, 2, 1);
+
+        assert_eq!(chunks.len(), 13 * 3);
+        assert_eq!(chunks[0], center);
+        assert!(chunks.contains(&IVec3::new(5, 0, -2)));
+        assert!(chunks.contains(&IVec3::new(3, -1, -2)));
+        assert!(!chunks.contains(&IVec3::new(3, 2, -2)));
+        assert!(!chunks.contains(&IVec3::new(5, 0, 0)));
+    }
+
+    #[test]
+    fn test_remove_chunk_unlinks_neighbors() {
+        let registry = BlockRegistry::default();
+        let world = World::new();
+        world.set_chunk(IVec3::new(0, 0, 0), Chunk::default(), &registry);
+        world.set_chunk(
+            IVec3::new(1, 0, 0),
+            Chunk::new(IVec3::new(1, 0, 0)),
+            &registry,
+        );
+
+        let chunk = world.get_chunk(IVec3::new(0, 0, 0)).unwrap();
+        assert!(chunk.read().unwrap().neighbors[1].upgrade().is_some());
+
+        let removed = world.remove_chunk(&IVec3::new(1, 0, 0));
+        assert!(removed.is_some());
+        assert!(world.get_chunk(IVec3::new(1, 0, 0)).is_none());
+        // The neighbor link is cleared even while the removed chunk is still referenced
+        assert!(chunk.read().unwrap().neighbors[1].upgrade().is_none());
+    }
+
+    #[test]
+    fn test_fluids_are_not_solid() {
+        let registry = BlockRegistry::default();
+        let water = Block::new(registry.block_type("water").unwrap());
+        let stone = Block::new(registry.block_type("stone").unwrap());
+
+        let mut chunk = Chunk::default();
+        for y in 0..4 {
+            chunk.set_voxel(&IVec3::new(2, y, 2), water);
+        }
+        chunk.set_voxel(&IVec3::new(2, 4, 2), stone);
+        let world = World::new();
+        world.set_chunk(IVec3::ZERO, chunk, &registry);
+
+        assert!(!world.check_block_at_coord(&IVec3::new(2, 1, 2), &registry));
+        assert!(world.check_block_at_coord(&IVec3::new(2, 4, 2), &registry));
+
+        // Looking up through the water, the ray stops on the stone above it
+        let (hit, previous, block) = world
+            .ray_casting_voxel(Vec3::new(2.0, -3.0, 2.0), Vec3::Y, 10.0, 0.1, &registry)
+            .unwrap();
+        assert_eq!(hit, IVec3::new(2, 4, 2));
+        assert_eq!(previous, IVec3::new(2, 3, 2));
+        assert_eq!(block, stone);
+    }
+
+    #[test]
+    fn test_collision_follows_block_model() {
+        let registry = BlockRegistry::default();
+        let slab = Block::new(registry.block_type("stone_slab").unwrap());
+        let grass = Block::new(registry.block_type("tall_grass").unwrap());
+
+        let mut chunk = Chunk::default();
+        chunk.set_voxel(&IVec3::new(2, 1, 2), slab);
+        chunk.set_voxel(&IVec3::new(5, 1, 2), grass);
+        let world = World::new();
+        world.set_chunk(IVec3::ZERO, chunk, &registry);
+
+        // Only the bottom half of the slab is solid, and plants can be walked through
+        assert!(world.is_solid_at(Vec3::new(2.2, 0.8, 2.0), &registry));
+        assert!(!world.is_solid_at(Vec3::new(2.2, 1.2, 2.0), &registry));
+        assert!(!world.is_solid_at(Vec3::new(5.0, 0.8, 2.0), &registry));
+
+        // A ray going down above the slab hits its top, not the empty half above it
+        let (hit, previous, block) = world
+            .ray_casting_voxel(Vec3::new(2.0, 4.0, 2.0), Vec3::NEG_Y, 10.0, 0.1, &registry)
+            .unwrap();
+        assert_eq!(hit, IVec3::new(2, 1, 2));
+        assert_eq!(previous, IVec3::new(2, 2, 2));
+        assert_eq!(block, slab);
+
+        // Rays going past the slab, through its empty half, don't stop on it
+        assert!(world
+            .ray_casting_voxel(Vec3::new(0.0, 1.3, 2.0), Vec3::X, 4.0, 0.1, &registry)
+            .is_none());
+
+        // Plants can still be aimed at
+        let (hit, _, block) = world
+            .ray_casting_voxel(Vec3::new(5.0, 4.0, 2.0), Vec3::NEG_Y, 10.0, 0.1, &registry)
+            .unwrap();
+        assert_eq!(hit, IVec3::new(5, 1, 2));
+        assert_eq!(block, grass);
+    }
+
+    #[test]
+    fn test_singleplayer_chunk_is_requested_again_after_unload() {
+        let game_world = GameWorld::default();
+        let world = Arc::clone(&game_world.world);
+        let mut app = App::new();
+        app.insert_resource(game_world)
+            .insert_resource(ChunkLoadingSettings {
+                view_distance: 1,
+                vertical_view_distance: 0,
+            })
+            .insert_resource(RenetClient::new(connection_config()))
+            .insert_resource(WorldStorage::new(
+                std::env::temp_dir().join("stream_chunks_unload"),
+            ))
+            .init_resource::<Assets<Mesh>>();
+        let player = app
+            .world_mut()
+            .spawn((Player::default(), Transform::default()))
+            .id();
+        let mut move_player = |x: f32| {
+            app.world_mut()
+                .entity_mut(player)
+                .insert(Transform::from_xyz(x, 0.0, 0.0));
+            app.world_mut().run_system_once(stream_chunks).unwrap();
+        };
+
+        move_player(0.0);
+        // The server generated the chunk in the world it shares with the client
+        world
+            .read()
+            .unwrap()
+            .set_chunk(IVec3::ZERO, Chunk::default(), &BlockRegistry::default());
+
+        // Walking away drops the chunk entity, but the chunk stays for the server to evict
+        move_player(10.0 * CHUNK_SIZE as f32);
+        {
+            let world = world.read().unwrap();
+            assert!(!world
+                .chunk_entities
+                .read()
+                .unwrap()
+                .contains_key(&IVec3::ZERO));
+            assert!(world.get_chunk(IVec3::ZERO).is_some());
+        }
+
+        // Coming back requests it again, and the server still has it to answer
+        move_player(0.0);
+        let world = world.read().unwrap();
+        assert!(world
+            .chunk_entities
+            .read()
+            .unwrap()
+            .contains_key(&IVec3::ZERO));
+        assert!(world
+            .pending_requested_chunks
+            .read()
+            .unwrap()
+            .contains(&IVec3::ZERO));
+        assert!(world.get_chunk(IVec3::ZERO).is_some());
+    }
 }